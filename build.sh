@@ -1,5 +1,7 @@
 #!/bin/bash
 set -e
 cd "`dirname $0`"
-cargo build --all --target wasm32-unknown-unknown --release
+cargo build -p orderly-contract -p test-token --target wasm32-unknown-unknown --release
+# the factory builds the pool contract, that it embeds, in its build script
+cargo build -p orderly-factory --target wasm32-unknown-unknown --release
 cp target/wasm32-unknown-unknown/release/*.wasm ./res/
@@ -0,0 +1,868 @@
+use crate::{
+    curve::WEIGHT_DIVISOR,
+    math::{MAX_TICK, MIN_TICK},
+    oracle::{timestamp_seconds, Oracle},
+    *,
+};
+use near_sdk::collections::TreeMap;
+
+/// Maximum distance between the ticks, that can be used for positions.
+pub const MAX_TICK_SPACING: u32 = 16_384;
+
+/// Liquidity pool, where liquidity is provided within price ranges between two ticks
+/// instead of over the whole price curve. The price of token a in token b at a tick
+/// is `1.0001^tick`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct ConcentratedPool {
+    /// Supplies of both tokens are the amounts of all positions including uncollected fees.
+    pub token_a: TokenPair,
+    pub token_b: TokenPair,
+    pub fee: u32,
+    pub tick_spacing: u32,
+    /// Square root of the price of token a in token b as Q64.64 number.
+    pub sqrt_price: u128,
+    /// Largest tick at or below the current price.
+    pub tick: i32,
+    /// Liquidity of all positions, that are in range of the current price.
+    pub liquidity: u128,
+    /// Accumulated fees per unit of liquidity as Q64.64 numbers, which are allowed to overflow.
+    pub fee_growth_a: u128,
+    pub fee_growth_b: u128,
+    /// Initialized ticks, which are the bounds of at least one position.
+    pub ticks: TreeMap<i32, Tick>,
+    pub oracle: Oracle,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+pub(crate) struct Tick {
+    /// Liquidity of all positions, that use this tick as a bound.
+    pub liquidity_gross: u128,
+    /// Liquidity, that gets added when the price crosses this tick upwards.
+    pub liquidity_net: i128,
+    /// Fee growth on the other side of this tick relative to the current tick.
+    pub fee_growth_outside_a: u128,
+    pub fee_growth_outside_b: u128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct Position {
+    pub owner_id: AccountId,
+    pub pool_id: u64,
+    pub lower_tick: i32,
+    pub upper_tick: i32,
+    pub liquidity: u128,
+    /// Fee growth within the range of the position at its last update.
+    pub fee_growth_inside_a: u128,
+    pub fee_growth_inside_b: u128,
+    /// Accrued fees, that have not been collected yet.
+    pub fees_a: u128,
+    pub fees_b: u128,
+}
+
+/// Swap in a concentrated pool, whose crossed ticks have not been written yet.
+pub(crate) struct ConcentratedSwap {
+    pub amount_in: u128,
+    pub amount_out: u128,
+    pub crossed_ticks: Vec<(i32, Tick)>,
+}
+
+impl ConcentratedPool {
+    pub fn new(
+        pool_id: u64,
+        token_a: TokenPair,
+        token_b: TokenPair,
+        fee: u32,
+        tick_spacing: u32,
+        tick: i32,
+    ) -> Self {
+        Self {
+            token_a,
+            token_b,
+            fee,
+            tick_spacing,
+            sqrt_price: math::sqrt_price_at_tick(tick),
+            tick,
+            liquidity: 0,
+            fee_growth_a: 0,
+            fee_growth_b: 0,
+            ticks: TreeMap::new(StorageKey::Ticks { pool_id }.try_to_vec().unwrap()),
+            oracle: Oracle::new(StorageKey::ConcentratedObservations { pool_id }),
+        }
+    }
+
+    /// Records the spot prices of the current square root price in the oracle.
+    /// This needs to be called after every swap and change of the positions.
+    pub fn update_oracle(&mut self) {
+        let price = U256::from(self.sqrt_price) * U256::from(self.sqrt_price);
+        let max = U256::from(u128::MAX);
+        let price_a = ((price >> 64) * U256::from(PRICE_DIVISOR)) >> 64;
+        let price_b = (U256::from(PRICE_DIVISOR) << 128)
+            .checked_div(price)
+            .unwrap_or(max);
+        self.oracle.update(
+            std::cmp::min(price_a, max).as_u128(),
+            std::cmp::min(price_b, max).as_u128(),
+            timestamp_seconds(),
+        );
+    }
+
+    pub fn contains(&self, token_id: &AccountId) -> bool {
+        token_id == &self.token_a.account_id || token_id == &self.token_b.account_id
+    }
+
+    /// Returns the pair of `token_id` and the other pair of the pool.
+    pub fn pairs(&self, token_id: &AccountId) -> (&TokenPair, &TokenPair) {
+        if token_id == &self.token_a.account_id {
+            (&self.token_a, &self.token_b)
+        } else if token_id == &self.token_b.account_id {
+            (&self.token_b, &self.token_a)
+        } else {
+            panic!("Token {} does not belong to liquidity pool", token_id);
+        }
+    }
+
+    /// Returns the pair of `token_id` and the other pair of the pool.
+    pub fn pairs_mut(&mut self, token_id: &AccountId) -> (&mut TokenPair, &mut TokenPair) {
+        if token_id == &self.token_a.account_id {
+            (&mut self.token_a, &mut self.token_b)
+        } else if token_id == &self.token_b.account_id {
+            (&mut self.token_b, &mut self.token_a)
+        } else {
+            panic!("Token {} does not belong to liquidity pool", token_id);
+        }
+    }
+
+    /// Returns the fee growth of both tokens between `lower_tick` and `upper_tick`.
+    fn fee_growth_inside(&self, lower_tick: i32, upper_tick: i32) -> (u128, u128) {
+        let lower = self.ticks.get(&lower_tick).unwrap_or_default();
+        let upper = self.ticks.get(&upper_tick).unwrap_or_default();
+        let (below_a, below_b) = if self.tick >= lower_tick {
+            (lower.fee_growth_outside_a, lower.fee_growth_outside_b)
+        } else {
+            (
+                self.fee_growth_a.wrapping_sub(lower.fee_growth_outside_a),
+                self.fee_growth_b.wrapping_sub(lower.fee_growth_outside_b),
+            )
+        };
+        let (above_a, above_b) = if self.tick < upper_tick {
+            (upper.fee_growth_outside_a, upper.fee_growth_outside_b)
+        } else {
+            (
+                self.fee_growth_a.wrapping_sub(upper.fee_growth_outside_a),
+                self.fee_growth_b.wrapping_sub(upper.fee_growth_outside_b),
+            )
+        };
+        (
+            self.fee_growth_a
+                .wrapping_sub(below_a)
+                .wrapping_sub(above_a),
+            self.fee_growth_b
+                .wrapping_sub(below_b)
+                .wrapping_sub(above_b),
+        )
+    }
+
+    /// Adds `liquidity_delta` to a bound of a position and writes the tick.
+    fn update_tick(&mut self, tick: i32, liquidity_delta: i128, upper: bool) -> Tick {
+        let mut info = self.ticks.get(&tick).unwrap_or_else(|| {
+            // all fees so far are assumed to be below the current tick
+            if tick <= self.tick {
+                Tick {
+                    fee_growth_outside_a: self.fee_growth_a,
+                    fee_growth_outside_b: self.fee_growth_b,
+                    ..Default::default()
+                }
+            } else {
+                Tick::default()
+            }
+        });
+        info.liquidity_gross = add_delta(info.liquidity_gross, liquidity_delta);
+        let max_liquidity = max_liquidity_per_tick(self.tick_spacing);
+        assert!(
+            info.liquidity_gross <= max_liquidity,
+            "Liquidity of tick {} exceeds the maximum of {}",
+            tick,
+            max_liquidity
+        );
+        info.liquidity_net = if upper {
+            info.liquidity_net - liquidity_delta
+        } else {
+            info.liquidity_net + liquidity_delta
+        };
+        self.ticks.insert(&tick, &info);
+        info
+    }
+
+    /// Adds `liquidity_delta` to `position` and accrues its fees.
+    /// Returns the amounts of both tokens, that are added to or removed from the position,
+    /// which are rounded in favor of the pool.
+    pub fn update_position(
+        &mut self,
+        position: &mut Position,
+        liquidity_delta: i128,
+    ) -> (u128, u128) {
+        let (lower_tick, upper_tick) = (position.lower_tick, position.upper_tick);
+        let ticks = if liquidity_delta != 0 {
+            Some((
+                self.update_tick(lower_tick, liquidity_delta, false),
+                self.update_tick(upper_tick, liquidity_delta, true),
+            ))
+        } else {
+            None
+        };
+
+        let (fee_growth_inside_a, fee_growth_inside_b) =
+            self.fee_growth_inside(lower_tick, upper_tick);
+        position.fees_a += accrued_fees(
+            position.liquidity,
+            fee_growth_inside_a.wrapping_sub(position.fee_growth_inside_a),
+        );
+        position.fees_b += accrued_fees(
+            position.liquidity,
+            fee_growth_inside_b.wrapping_sub(position.fee_growth_inside_b),
+        );
+        position.fee_growth_inside_a = fee_growth_inside_a;
+        position.fee_growth_inside_b = fee_growth_inside_b;
+        position.liquidity = add_delta(position.liquidity, liquidity_delta);
+
+        // ticks without liquidity are only removed after computing the fee growth
+        if let Some((lower, upper)) = ticks {
+            if lower.liquidity_gross == 0 {
+                self.ticks.remove(&lower_tick);
+            }
+            if upper.liquidity_gross == 0 {
+                self.ticks.remove(&upper_tick);
+            }
+        }
+        if (lower_tick..upper_tick).contains(&self.tick) {
+            self.liquidity = add_delta(self.liquidity, liquidity_delta);
+        }
+        math::amounts_for_liquidity(
+            self.sqrt_price,
+            math::sqrt_price_at_tick(lower_tick),
+            math::sqrt_price_at_tick(upper_tick),
+            liquidity_delta.unsigned_abs(),
+            liquidity_delta > 0,
+        )
+    }
+
+    /// Swaps `amount_in` of `token_in` and moves the price along the initialized ticks,
+    /// until the whole amount is used or no liquidity is left.
+    /// Crossed ticks are returned instead of being written, so that swaps can also be quoted.
+    pub fn swap(&mut self, token_in: &AccountId, amount_in: u128) -> ConcentratedSwap {
+        let a_for_b = token_in == &self.token_a.account_id;
+        let (mut amount_remaining, mut amount_out) = (amount_in, 0);
+        let mut crossed_ticks = vec![];
+        while amount_remaining > 0 {
+            let next_tick = if a_for_b {
+                self.ticks.floor_key(&self.tick)
+            } else {
+                self.ticks.higher(&self.tick)
+            };
+            let target_tick = next_tick.unwrap_or(if a_for_b { MIN_TICK } else { MAX_TICK });
+            let sqrt_price_target = math::sqrt_price_at_tick(target_tick);
+            let step = math::compute_swap_step(
+                self.sqrt_price,
+                sqrt_price_target,
+                self.liquidity,
+                amount_remaining,
+                self.fee,
+            );
+            amount_remaining -= step.amount_in + step.fee_amount;
+            amount_out += step.amount_out;
+            if self.liquidity > 0 {
+                let fee_growth =
+                    ((U256::from(step.fee_amount) << 64) / U256::from(self.liquidity)).low_u128();
+                if a_for_b {
+                    self.fee_growth_a = self.fee_growth_a.wrapping_add(fee_growth);
+                } else {
+                    self.fee_growth_b = self.fee_growth_b.wrapping_add(fee_growth);
+                }
+            }
+            self.sqrt_price = step.sqrt_price;
+
+            if step.sqrt_price != sqrt_price_target {
+                self.tick = math::tick_at_sqrt_price(step.sqrt_price);
+                continue;
+            }
+            let tick = match next_tick {
+                Some(tick) => tick,
+                None => {
+                    self.tick = target_tick;
+                    break;
+                }
+            };
+            let mut info = self.ticks.get(&tick).unwrap();
+            info.fee_growth_outside_a = self.fee_growth_a.wrapping_sub(info.fee_growth_outside_a);
+            info.fee_growth_outside_b = self.fee_growth_b.wrapping_sub(info.fee_growth_outside_b);
+            if a_for_b {
+                self.liquidity = add_delta(self.liquidity, -info.liquidity_net);
+                self.tick = tick - 1;
+            } else {
+                self.liquidity = add_delta(self.liquidity, info.liquidity_net);
+                self.tick = tick;
+            }
+            crossed_ticks.push((tick, info));
+        }
+        ConcentratedSwap {
+            amount_in: amount_in - amount_remaining,
+            amount_out,
+            crossed_ticks,
+        }
+    }
+
+    pub fn info(&self) -> ConcentratedPoolInfo {
+        ConcentratedPoolInfo {
+            token_a_id: self.token_a.account_id.clone(),
+            token_a_supply: self.token_a.supply,
+            token_b_id: self.token_b.account_id.clone(),
+            token_b_supply: self.token_b.supply,
+            fee: self.fee,
+            tick_spacing: self.tick_spacing,
+            sqrt_price: self.sqrt_price.into(),
+            tick: self.tick,
+            liquidity: self.liquidity.into(),
+        }
+    }
+}
+
+/// Returns the maximum liquidity, that can reference a single tick, so that the liquidity
+/// of all usable ticks together never overflows `u128`, as in Uniswap v3.
+pub(crate) fn max_liquidity_per_tick(tick_spacing: u32) -> u128 {
+    let tick_spacing = tick_spacing as i32;
+    let min_tick = MIN_TICK / tick_spacing * tick_spacing;
+    let max_tick = MAX_TICK / tick_spacing * tick_spacing;
+    let ticks = (max_tick - min_tick) / tick_spacing + 1;
+    u128::MAX / ticks as u128
+}
+
+fn add_delta(liquidity: u128, liquidity_delta: i128) -> u128 {
+    if liquidity_delta >= 0 {
+        liquidity + liquidity_delta as u128
+    } else {
+        liquidity - liquidity_delta.unsigned_abs()
+    }
+}
+
+fn accrued_fees(liquidity: u128, fee_growth: u128) -> u128 {
+    ((U256::from(liquidity) * U256::from(fee_growth)) >> 64).as_u128()
+}
+
+#[near_bindgen]
+impl OrderlyContract {
+    /// Adds a concentrated liquidity pool for `token_a` and `token_b` with a swap `fee`
+    /// in basis points, that is paid to the positions in range. Positions can only use ticks,
+    /// that are multiples of `tick_spacing`. The pool starts at the price `1.0001^initial_tick`
+    /// of token a in token b. Like `add_pool` this needs a deposit for the observations.
+    #[payable]
+    pub fn add_concentrated_pool(
+        &mut self,
+        token_a: AccountId,
+        token_b: AccountId,
+        fee: u32,
+        tick_spacing: u32,
+        initial_tick: i32,
+    ) -> Promise {
+        self.assert_owner();
+        assert_ne!(token_a, token_b, "Tokens of a pool must be different");
+        assert!(fee < FEE_DIVISOR, "Fee must be less than {}", FEE_DIVISOR);
+        assert!(
+            (1..=MAX_TICK_SPACING).contains(&tick_spacing),
+            "Tick spacing must be between 1 and {}",
+            MAX_TICK_SPACING
+        );
+        assert!(
+            (MIN_TICK..=MAX_TICK).contains(&initial_tick),
+            "Tick {} is out of bounds",
+            initial_tick
+        );
+        self.internal_reserve_observations();
+        ext_fungible_token::ext(token_a.clone())
+            .ft_metadata()
+            .and(ext_fungible_token::ext(token_b.clone()).ft_metadata())
+            .then(
+                Self::ext(env::current_account_id()).handle_add_concentrated_pool(
+                    token_a,
+                    token_b,
+                    fee,
+                    tick_spacing,
+                    initial_tick,
+                ),
+            )
+    }
+
+    /// Adds the concentrated pool with the fetched metadata of both tokens and returns its id.
+    /// If the metadata of either token could not be fetched, no pool is added
+    /// and the deposit is refunded to the owner.
+    #[private]
+    pub fn handle_add_concentrated_pool(
+        &mut self,
+        token_a: AccountId,
+        token_b: AccountId,
+        fee: u32,
+        tick_spacing: u32,
+        initial_tick: i32,
+    ) -> Option<u64> {
+        let (token_a_metadata, token_b_metadata) = match metadata_results(&token_a, &token_b) {
+            Some(metadata) => metadata,
+            None => {
+                log!(
+                    "Concentrated pool for tokens {} and {} was not added",
+                    token_a,
+                    token_b
+                );
+                self.internal_refund_observations();
+                return None;
+            }
+        };
+        let pool_id = self.concentrated_pools.len();
+        self.concentrated_pools.push(&ConcentratedPool::new(
+            pool_id,
+            TokenPair {
+                account_id: token_a.clone(),
+                metadata: token_a_metadata,
+                supply: U128::from(0),
+                weight: WEIGHT_DIVISOR / 2,
+            },
+            TokenPair {
+                account_id: token_b.clone(),
+                metadata: token_b_metadata,
+                supply: U128::from(0),
+                weight: WEIGHT_DIVISOR / 2,
+            },
+            fee,
+            tick_spacing,
+            initial_tick,
+        ));
+        self.tokens.insert(&token_a);
+        self.tokens.insert(&token_b);
+        log!(
+            "Added concentrated pool {} for tokens {} and {}",
+            pool_id,
+            token_a,
+            token_b
+        );
+        Some(pool_id)
+    }
+
+    /// Opens a position with liquidity between `lower_tick` and `upper_tick` from the pending
+    /// deposits of the caller. Only the amounts matching the current price are used,
+    /// the remainder stays in the deposits. Returns the id of the position.
+    pub fn open_position(
+        &mut self,
+        pool_id: u64,
+        lower_tick: i32,
+        upper_tick: i32,
+        amount_a: U128,
+        amount_b: U128,
+        min_liquidity: U128,
+    ) -> U64 {
+        self.assert_not_paused();
+        let account_id = env::predecessor_account_id();
+        let mut pool = self.internal_get_concentrated_pool(pool_id);
+        assert!(
+            lower_tick < upper_tick,
+            "Lower tick must be less than upper tick"
+        );
+        let tick_spacing = pool.tick_spacing as i32;
+        assert!(
+            lower_tick % tick_spacing == 0 && upper_tick % tick_spacing == 0,
+            "Ticks must be multiples of the tick spacing {}",
+            tick_spacing
+        );
+        let liquidity = math::liquidity_for_amounts(
+            pool.sqrt_price,
+            math::sqrt_price_at_tick(lower_tick),
+            math::sqrt_price_at_tick(upper_tick),
+            amount_a.0,
+            amount_b.0,
+        );
+        assert!(
+            liquidity > 0,
+            "Not enough liquidity added to open a position"
+        );
+        assert!(
+            liquidity >= min_liquidity.0,
+            "Slippage exceeded: liquidity of {} is less than the minimum of {}",
+            liquidity,
+            min_liquidity.0
+        );
+
+        let mut position = Position {
+            owner_id: account_id.clone(),
+            pool_id,
+            lower_tick,
+            upper_tick,
+            liquidity: 0,
+            fee_growth_inside_a: 0,
+            fee_growth_inside_b: 0,
+            fees_a: 0,
+            fees_b: 0,
+        };
+        // the position pays for the ticks it initializes
+        let initial_storage = env::storage_usage();
+        let liquidity_delta =
+            i128::try_from(liquidity).expect("Liquidity of the position is too large");
+        let (amount_a, amount_b) = pool.update_position(&mut position, liquidity_delta);
+        let position_id = self.next_position_id;
+        self.next_position_id += 1;
+        self.positions.insert(&position_id, &position);
+        let mut position_ids = self.account_positions.get(&account_id).unwrap_or_default();
+        position_ids.push(position_id);
+        self.account_positions.insert(&account_id, &position_ids);
+        self.internal_update_storage(&account_id, initial_storage);
+
+        self.internal_withdraw_deposit(&account_id, &pool.token_a.account_id, amount_a);
+        self.internal_withdraw_deposit(&account_id, &pool.token_b.account_id, amount_b);
+        self.assert_storage_covered(&account_id);
+        pool.token_a.supply.0 += amount_a;
+        pool.token_b.supply.0 += amount_b;
+        pool.update_oracle();
+        log!(
+            "User {} opened position {} with liquidity {} between ticks {} and {} in concentrated pool {} for {} of token {} and {} of token {}",
+            account_id,
+            position_id,
+            liquidity,
+            lower_tick,
+            upper_tick,
+            pool_id,
+            amount_a,
+            pool.token_a.account_id,
+            amount_b,
+            pool.token_b.account_id
+        );
+        self.concentrated_pools.replace(pool_id, &pool);
+        position_id.into()
+    }
+
+    /// Removes `liquidity` from a position of the caller and credits the amounts of both tokens
+    /// together with the accrued fees to the deposits of the caller.
+    /// The position is closed, once all of its liquidity is removed.
+    pub fn decrease_liquidity(
+        &mut self,
+        position_id: U64,
+        liquidity: U128,
+        min_amount_a: U128,
+        min_amount_b: U128,
+    ) -> (U128, U128) {
+        let account_id = env::predecessor_account_id();
+        let mut position = self.internal_get_position(position_id.0, &account_id);
+        assert!(
+            liquidity.0 > 0 && liquidity.0 <= position.liquidity,
+            "Liquidity must be between 1 and {}",
+            position.liquidity
+        );
+        let mut pool = self.internal_get_concentrated_pool(position.pool_id);
+        let initial_storage = env::storage_usage();
+        let liquidity_delta =
+            i128::try_from(liquidity.0).expect("Liquidity of the position is too large");
+        let (amount_a, amount_b) = pool.update_position(&mut position, -liquidity_delta);
+        assert!(
+            amount_a >= min_amount_a.0,
+            "Slippage exceeded: {} of token {} is less than the minimum of {}",
+            amount_a,
+            pool.token_a.account_id,
+            min_amount_a.0
+        );
+        assert!(
+            amount_b >= min_amount_b.0,
+            "Slippage exceeded: {} of token {} is less than the minimum of {}",
+            amount_b,
+            pool.token_b.account_id,
+            min_amount_b.0
+        );
+        log!(
+            "User {} removed liquidity {} from position {} for {} of token {} and {} of token {}",
+            account_id,
+            liquidity.0,
+            position_id.0,
+            amount_a,
+            pool.token_a.account_id,
+            amount_b,
+            pool.token_b.account_id
+        );
+        if position.liquidity == 0 {
+            self.positions.remove(&position_id.0);
+            let mut position_ids = self.account_positions.get(&account_id).unwrap_or_default();
+            position_ids.retain(|id| id != &position_id.0);
+            if position_ids.is_empty() {
+                self.account_positions.remove(&account_id);
+            } else {
+                self.account_positions.insert(&account_id, &position_ids);
+            }
+            log!("User {} closed position {}", account_id, position_id.0);
+        }
+        self.internal_update_storage(&account_id, initial_storage);
+        let amounts = self.internal_collect(&mut pool, &mut position, amount_a, amount_b);
+        if position.liquidity > 0 {
+            self.positions.insert(&position_id.0, &position);
+        }
+        pool.update_oracle();
+        self.concentrated_pools.replace(position.pool_id, &pool);
+        amounts
+    }
+
+    /// Credits the accrued fees of a position of the caller to the deposits of the caller.
+    pub fn collect_fees(&mut self, position_id: U64) -> (U128, U128) {
+        let account_id = env::predecessor_account_id();
+        let mut position = self.internal_get_position(position_id.0, &account_id);
+        let mut pool = self.internal_get_concentrated_pool(position.pool_id);
+        pool.update_position(&mut position, 0);
+        let amounts = self.internal_collect(&mut pool, &mut position, 0, 0);
+        self.positions.insert(&position_id.0, &position);
+        self.concentrated_pools.replace(position.pool_id, &pool);
+        amounts
+    }
+
+    /// Returns the amount of the other token, that would be received by a swap of `amount_in`
+    /// of `token_in` in a concentrated pool.
+    pub fn get_concentrated_return(
+        &self,
+        pool_id: u64,
+        token_in: AccountId,
+        amount_in: U128,
+    ) -> U128 {
+        let mut pool = self.internal_get_concentrated_pool(pool_id);
+        pool.pairs(&token_in);
+        pool.swap(&token_in, amount_in.0).amount_out.into()
+    }
+
+    pub fn get_concentrated_pool(&self, pool_id: u64) -> Option<ConcentratedPoolInfo> {
+        self.concentrated_pools.get(pool_id).map(|pool| pool.info())
+    }
+
+    /// Returns up to `limit` concentrated pools starting at pool id `from_index`.
+    pub fn get_concentrated_pools(&self, from_index: u64, limit: u64) -> Vec<ConcentratedPoolInfo> {
+        (from_index
+            ..std::cmp::min(
+                from_index.saturating_add(limit),
+                self.concentrated_pools.len(),
+            ))
+            .map(|pool_id| self.internal_get_concentrated_pool(pool_id).info())
+            .collect()
+    }
+
+    /// Returns a position with its current amounts and accrued fees.
+    pub fn get_position(&self, position_id: U64) -> Option<PositionInfo> {
+        self.positions
+            .get(&position_id.0)
+            .map(|position| self.internal_position_info(position_id.0, position))
+    }
+
+    /// Returns all positions of `account_id`.
+    pub fn get_positions(&self, account_id: AccountId) -> Vec<PositionInfo> {
+        self.account_positions
+            .get(&account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|position_id| {
+                self.internal_position_info(position_id, self.positions.get(&position_id).unwrap())
+            })
+            .collect()
+    }
+}
+
+impl OrderlyContract {
+    pub(crate) fn internal_get_concentrated_pool(&self, pool_id: u64) -> ConcentratedPool {
+        self.concentrated_pools
+            .get(pool_id)
+            .unwrap_or_else(|| panic!("Concentrated pool {} does not exist", pool_id))
+    }
+
+    fn internal_get_position(&self, position_id: u64, account_id: &AccountId) -> Position {
+        let position = self
+            .positions
+            .get(&position_id)
+            .unwrap_or_else(|| panic!("Position {} does not exist", position_id));
+        assert_eq!(
+            &position.owner_id, account_id,
+            "Position {} does not belong to {}",
+            position_id, account_id
+        );
+        position
+    }
+
+    /// Credits the given amounts together with the accrued fees of `position`
+    /// to the deposits of its owner and returns the credited amounts.
+    fn internal_collect(
+        &mut self,
+        pool: &mut ConcentratedPool,
+        position: &mut Position,
+        amount_a: u128,
+        amount_b: u128,
+    ) -> (U128, U128) {
+        let (fees_a, fees_b) = (position.fees_a, position.fees_b);
+        position.fees_a = 0;
+        position.fees_b = 0;
+        if fees_a > 0 || fees_b > 0 {
+            log!(
+                "User {} collected fees of {} of token {} and {} of token {}",
+                position.owner_id,
+                fees_a,
+                pool.token_a.account_id,
+                fees_b,
+                pool.token_b.account_id
+            );
+        }
+        let (amount_a, amount_b) = (amount_a + fees_a, amount_b + fees_b);
+        pool.token_a.supply.0 -= amount_a;
+        pool.token_b.supply.0 -= amount_b;
+        for (token_id, amount) in [
+            (&pool.token_a.account_id, amount_a),
+            (&pool.token_b.account_id, amount_b),
+        ] {
+            if amount > 0 {
+                self.internal_deposit(&position.owner_id, token_id, amount);
+            }
+        }
+        (amount_a.into(), amount_b.into())
+    }
+
+    fn internal_position_info(&self, position_id: u64, mut position: Position) -> PositionInfo {
+        let mut pool = self.internal_get_concentrated_pool(position.pool_id);
+        pool.update_position(&mut position, 0);
+        let (amount_a, amount_b) = math::amounts_for_liquidity(
+            pool.sqrt_price,
+            math::sqrt_price_at_tick(position.lower_tick),
+            math::sqrt_price_at_tick(position.upper_tick),
+            position.liquidity,
+            false,
+        );
+        PositionInfo {
+            position_id: position_id.into(),
+            owner_id: position.owner_id,
+            pool_id: position.pool_id,
+            lower_tick: position.lower_tick,
+            upper_tick: position.upper_tick,
+            liquidity: position.liquidity.into(),
+            amount_a: amount_a.into(),
+            amount_b: amount_b.into(),
+            fees_a: position.fees_a.into(),
+            fees_b: position.fees_b.into(),
+        }
+    }
+
+    pub(crate) fn internal_concentrated_swap(
+        &mut self,
+        sender_id: AccountId,
+        token_in: AccountId,
+        amount: U128,
+        transfer_msg: TransferMsg,
+    ) -> PromiseOrValue<U128> {
+        if transfer_msg.route.is_some()
+            || transfer_msg.amount_out.is_some()
+            || transfer_msg.then_call.is_some()
+        {
+            log!("Concentrated swaps only support a pool_id, min_amount_out, deadline and receiver_id");
+            return PromiseOrValue::Value(amount);
+        }
+        if let Some(deadline) = transfer_msg.deadline {
+            if env::block_timestamp() > deadline.0 {
+                log!("Swap deadline of {} exceeded", deadline.0);
+                return PromiseOrValue::Value(amount);
+            }
+        }
+        let pool_id = if let Some(pool_id) = transfer_msg.pool_id {
+            pool_id
+        } else {
+            log!("Concentrated swap requires a pool_id");
+            return PromiseOrValue::Value(amount);
+        };
+        let mut pool = match self.concentrated_pools.get(pool_id) {
+            Some(pool) if pool.contains(&token_in) => pool,
+            Some(_) => {
+                log!("Deposited token address does not belong to liquidity pool");
+                return PromiseOrValue::Value(amount);
+            }
+            None => {
+                log!("Concentrated pool {} does not exist", pool_id);
+                return PromiseOrValue::Value(amount);
+            }
+        };
+
+        let swap = pool.swap(&token_in, amount.0);
+        if swap.amount_out == 0 {
+            log!("Not enough liquidity available for swap");
+            return PromiseOrValue::Value(amount);
+        }
+        if let Some(min_amount_out) = transfer_msg.min_amount_out {
+            if swap.amount_out < min_amount_out.0 {
+                log!(
+                    "Slippage exceeded: {} of token {} is less than the minimum of {}",
+                    swap.amount_out,
+                    pool.pairs(&token_in).1.account_id,
+                    min_amount_out.0
+                );
+                return PromiseOrValue::Value(amount);
+            }
+        }
+        for (tick, info) in &swap.crossed_ticks {
+            pool.ticks.insert(tick, info);
+        }
+        let (in_pair, out_pair) = pool.pairs_mut(&token_in);
+        in_pair.supply.0 += swap.amount_in;
+        out_pair.supply.0 -= swap.amount_out;
+        let token_out = out_pair.account_id.clone();
+        pool.update_oracle();
+        log!(
+            "User {} swapping {} of token {} for {} of token {} in concentrated pool {}",
+            &sender_id,
+            swap.amount_in,
+            token_in,
+            swap.amount_out,
+            token_out,
+            pool_id
+        );
+        self.concentrated_pools.replace(pool_id, &pool);
+
+        // the swap cannot be reverted once ticks are crossed,
+        // so the output is credited to the deposits of the sender, if the transfer fails
+        ext_fungible_token::ext(token_out.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(
+                transfer_msg
+                    .receiver_id
+                    .unwrap_or_else(|| sender_id.clone()),
+                swap.amount_out.into(),
+                Some("swap".to_string()),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_WITHDRAW)
+                    .handle_withdraw(sender_id, token_out, swap.amount_out.into()),
+            );
+        PromiseOrValue::Value((amount.0 - swap.amount_in).into())
+    }
+}
+
+#[derive(Deserialize, Serialize, Eq, PartialEq, Debug)]
+pub struct ConcentratedPoolInfo {
+    pub token_a_id: AccountId,
+    pub token_a_supply: U128,
+    pub token_b_id: AccountId,
+    pub token_b_supply: U128,
+    pub fee: u32,
+    pub tick_spacing: u32,
+    /// Square root of the price of token a in token b as Q64.64 number.
+    pub sqrt_price: U128,
+    pub tick: i32,
+    /// Liquidity in range of the current price.
+    pub liquidity: U128,
+}
+
+#[derive(Deserialize, Serialize, Eq, PartialEq, Debug)]
+pub struct PositionInfo {
+    pub position_id: U64,
+    pub owner_id: AccountId,
+    pub pool_id: u64,
+    pub lower_tick: i32,
+    pub upper_tick: i32,
+    pub liquidity: U128,
+    /// Amounts of both tokens, that the liquidity is worth at the current price.
+    pub amount_a: U128,
+    pub amount_b: U128,
+    /// Accrued fees, that can be collected.
+    pub fees_a: U128,
+    pub fees_b: U128,
+}
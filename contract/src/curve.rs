@@ -0,0 +1,362 @@
+use crate::{
+    math::{mul_div, mul_div_ceil},
+    *,
+};
+
+pub const MAX_AMP: u64 = 1_000_000;
+/// Factor by which the amplification can change at most during a single ramp.
+pub const MAX_AMP_CHANGE: u64 = 10;
+/// Minimum duration of an amplification ramp in nanoseconds.
+pub const MIN_RAMP_DURATION: u64 = 24 * 60 * 60 * 1_000_000_000;
+/// Sum of the weights of both tokens in basis points.
+pub const WEIGHT_DIVISOR: u32 = 10_000;
+/// Minimum weight of a token in basis points, which bounds the exponent of the weighted math.
+pub const MIN_WEIGHT: u32 = 200;
+/// Maximum decimals of the tokens of a StableSwap pool. Amounts are normalized by
+/// `10^(decimals_a - decimals_b)`, which needs to fit into the invariant math.
+pub const MAX_STABLE_SWAP_DECIMALS: u8 = 24;
+
+/// Curve of a pool, which determines the price of a swap.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub(crate) enum Curve {
+    /// `x * y = k`
+    ConstantProduct,
+    /// StableSwap invariant for pegged tokens, that behaves like a constant sum close to the peg.
+    StableSwap(Amplification),
+    /// `x^weight_a * y^weight_b = k` with the weights of the token pairs.
+    Weighted,
+}
+
+/// Amplification coefficient of a StableSwap pool, that can be ramped linearly over time.
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Amplification {
+    pub initial_amp: u64,
+    pub target_amp: u64,
+    /// Block timestamp in nanoseconds.
+    pub start_timestamp: U64,
+    /// Block timestamp in nanoseconds, when `target_amp` is reached.
+    pub end_timestamp: U64,
+}
+
+impl Amplification {
+    pub fn new(amp: u64) -> Self {
+        assert_amp(amp);
+        Self {
+            initial_amp: amp,
+            target_amp: amp,
+            start_timestamp: 0.into(),
+            end_timestamp: 0.into(),
+        }
+    }
+
+    /// Returns the amplification at `timestamp`.
+    pub fn current(&self, timestamp: u64) -> u64 {
+        let (start, end) = (self.start_timestamp.0, self.end_timestamp.0);
+        if timestamp >= end {
+            return self.target_amp;
+        }
+        let (elapsed, duration) = ((timestamp - start) as u128, (end - start) as u128);
+        if self.target_amp > self.initial_amp {
+            let diff = (self.target_amp - self.initial_amp) as u128;
+            self.initial_amp + mul_div(diff, elapsed, duration) as u64
+        } else {
+            let diff = (self.initial_amp - self.target_amp) as u128;
+            self.initial_amp - mul_div(diff, elapsed, duration) as u64
+        }
+    }
+
+    /// Ramps the amplification from its current value to `target_amp` until `end_timestamp`.
+    pub fn ramp(&mut self, target_amp: u64, end_timestamp: u64, timestamp: u64) {
+        assert_amp(target_amp);
+        assert!(
+            end_timestamp >= timestamp + MIN_RAMP_DURATION,
+            "Ramp must last at least {} nanoseconds",
+            MIN_RAMP_DURATION
+        );
+        let current_amp = self.current(timestamp);
+        assert!(
+            target_amp <= current_amp * MAX_AMP_CHANGE
+                && target_amp * MAX_AMP_CHANGE >= current_amp,
+            "Amplification can change at most by a factor of {}",
+            MAX_AMP_CHANGE
+        );
+        self.initial_amp = current_amp;
+        self.target_amp = target_amp;
+        self.start_timestamp = timestamp.into();
+        self.end_timestamp = end_timestamp.into();
+    }
+}
+
+pub(crate) fn assert_amp(amp: u64) {
+    assert!(
+        (1..=MAX_AMP).contains(&amp),
+        "Amplification must be between 1 and {}",
+        MAX_AMP
+    );
+}
+
+pub(crate) fn assert_weights(weight_a: u32, weight_b: u32) {
+    assert!(
+        weight_a.checked_add(weight_b) == Some(WEIGHT_DIVISOR),
+        "Weights must add up to {}",
+        WEIGHT_DIVISOR
+    );
+    assert!(
+        weight_a >= MIN_WEIGHT && weight_b >= MIN_WEIGHT,
+        "Weights must be at least {}",
+        MIN_WEIGHT
+    );
+}
+
+impl Curve {
+    /// See [`math::get_amount_out`].
+    pub fn get_amount_out(
+        &self,
+        amount_in: u128,
+        in_pair: &TokenPair,
+        out_pair: &TokenPair,
+        fee: u32,
+    ) -> u128 {
+        match self {
+            Curve::ConstantProduct => {
+                math::get_amount_out(amount_in, in_pair.supply.0, out_pair.supply.0, fee)
+            }
+            Curve::StableSwap(amplification) => {
+                let amp = amplification.current(env::block_timestamp());
+                let amount_in_with_fee =
+                    mul_div(amount_in, (FEE_DIVISOR - fee) as u128, FEE_DIVISOR as u128);
+                let decimals = Decimals::new(in_pair, out_pair);
+                let amount_out = math::stable_get_amount_out(
+                    amp,
+                    decimals.normalize_in(amount_in_with_fee),
+                    decimals.normalize_in(in_pair.supply.0),
+                    decimals.normalize_out(out_pair.supply.0),
+                );
+                decimals.denormalize_out(amount_out)
+            }
+            Curve::Weighted => {
+                let amount_in_with_fee =
+                    mul_div(amount_in, (FEE_DIVISOR - fee) as u128, FEE_DIVISOR as u128);
+                math::weighted_get_amount_out(
+                    amount_in_with_fee,
+                    in_pair.supply.0,
+                    in_pair.weight,
+                    out_pair.supply.0,
+                    out_pair.weight,
+                )
+            }
+        }
+    }
+
+    /// See [`math::get_amount_in`]. Returns `None`, if the input does not fit into `u128`.
+    pub fn get_amount_in(
+        &self,
+        amount_out: u128,
+        in_pair: &TokenPair,
+        out_pair: &TokenPair,
+        fee: u32,
+    ) -> Option<u128> {
+        match self {
+            Curve::ConstantProduct => Some(math::get_amount_in(
+                amount_out,
+                in_pair.supply.0,
+                out_pair.supply.0,
+                fee,
+            )),
+            Curve::StableSwap(amplification) => {
+                let amp = amplification.current(env::block_timestamp());
+                let decimals = Decimals::new(in_pair, out_pair);
+                let amount_in_with_fee = math::stable_get_amount_in(
+                    amp,
+                    decimals.normalize_out(amount_out),
+                    decimals.normalize_in(in_pair.supply.0),
+                    decimals.normalize_out(out_pair.supply.0),
+                );
+                Some(mul_div_ceil(
+                    decimals.denormalize_in_ceil(amount_in_with_fee),
+                    FEE_DIVISOR as u128,
+                    (FEE_DIVISOR - fee) as u128,
+                ))
+            }
+            Curve::Weighted => {
+                let amount_in_with_fee = math::weighted_get_amount_in(
+                    amount_out,
+                    in_pair.supply.0,
+                    in_pair.weight,
+                    out_pair.supply.0,
+                    out_pair.weight,
+                )?;
+                math::checked_mul_div_ceil(
+                    amount_in_with_fee,
+                    FEE_DIVISOR as u128,
+                    (FEE_DIVISOR - fee) as u128,
+                )
+            }
+        }
+    }
+
+    /// See [`math::get_price_impact`].
+    pub fn get_price_impact(
+        &self,
+        amount_in: u128,
+        in_pair: &TokenPair,
+        out_pair: &TokenPair,
+        fee: u32,
+    ) -> u32 {
+        match self {
+            Curve::ConstantProduct => {
+                math::get_price_impact(amount_in, in_pair.supply.0, out_pair.supply.0, fee)
+            }
+            Curve::StableSwap(amplification) => {
+                let amp = amplification.current(env::block_timestamp());
+                let amount_in_with_fee =
+                    mul_div(amount_in, (FEE_DIVISOR - fee) as u128, FEE_DIVISOR as u128);
+                let decimals = Decimals::new(in_pair, out_pair);
+                let (amount_in, reserve_in, reserve_out) = (
+                    decimals.normalize_in(amount_in_with_fee),
+                    decimals.normalize_in(in_pair.supply.0),
+                    decimals.normalize_out(out_pair.supply.0),
+                );
+                let amount_out =
+                    math::stable_get_amount_out(amp, amount_in, reserve_in, reserve_out);
+                let spot_amount_out =
+                    math::stable_get_spot_amount_out(amp, amount_in, reserve_in, reserve_out);
+                if spot_amount_out <= amount_out {
+                    return 0;
+                }
+                let (impact, rem) =
+                    ((spot_amount_out - amount_out) * FEE_DIVISOR).div_mod(spot_amount_out);
+                (if rem.is_zero() { impact } else { impact + 1 }).as_u32()
+            }
+            Curve::Weighted => {
+                let amount_in_with_fee =
+                    mul_div(amount_in, (FEE_DIVISOR - fee) as u128, FEE_DIVISOR as u128);
+                let (reserve_in, weight_in, reserve_out, weight_out) = (
+                    in_pair.supply.0,
+                    in_pair.weight,
+                    out_pair.supply.0,
+                    out_pair.weight,
+                );
+                let amount_out = math::weighted_get_amount_out(
+                    amount_in_with_fee,
+                    reserve_in,
+                    weight_in,
+                    reserve_out,
+                    weight_out,
+                );
+                let spot_amount_out = math::weighted_get_spot_amount_out(
+                    amount_in_with_fee,
+                    reserve_in,
+                    weight_in,
+                    reserve_out,
+                    weight_out,
+                );
+                if spot_amount_out <= amount_out {
+                    return 0;
+                }
+                mul_div_ceil(
+                    spot_amount_out - amount_out,
+                    FEE_DIVISOR as u128,
+                    spot_amount_out,
+                ) as u32
+            }
+        }
+    }
+
+    /// Returns the spot price of the input token in the output token without fees
+    /// multiplied by [`PRICE_DIVISOR`]. Prices, that do not fit into `u128`, are capped.
+    pub fn get_spot_price(&self, in_pair: &TokenPair, out_pair: &TokenPair) -> u128 {
+        let price = match self {
+            Curve::ConstantProduct => {
+                U256::from(PRICE_DIVISOR) * U256::from(out_pair.supply.0)
+                    / U256::from(in_pair.supply.0)
+            }
+            Curve::StableSwap(amplification) => {
+                let amp = amplification.current(env::block_timestamp());
+                let decimals = Decimals::new(in_pair, out_pair);
+                math::stable_get_spot_amount_out(
+                    amp,
+                    decimals.normalize_in(PRICE_DIVISOR),
+                    decimals.normalize_in(in_pair.supply.0),
+                    decimals.normalize_out(out_pair.supply.0),
+                ) / decimals.out_factor
+            }
+            Curve::Weighted => {
+                U256::from(PRICE_DIVISOR)
+                    * U256::from(out_pair.supply.0)
+                    * U256::from(in_pair.weight)
+                    / (U256::from(in_pair.supply.0) * U256::from(out_pair.weight))
+            }
+        };
+        std::cmp::min(price, U256::from(u128::MAX)).as_u128()
+    }
+
+    /// Returns the current state of the curve as shown in views.
+    pub fn info(&self, token_a: &TokenPair, token_b: &TokenPair) -> PoolCurve {
+        match self {
+            Curve::ConstantProduct => PoolCurve::ConstantProduct,
+            Curve::StableSwap(amplification) => PoolCurve::StableSwap {
+                amp: amplification.current(env::block_timestamp()),
+            },
+            Curve::Weighted => PoolCurve::Weighted {
+                weight_a: token_a.weight,
+                weight_b: token_b.weight,
+            },
+        }
+    }
+}
+
+impl From<PoolCurve> for Curve {
+    fn from(curve: PoolCurve) -> Self {
+        match curve {
+            PoolCurve::ConstantProduct => Curve::ConstantProduct,
+            PoolCurve::StableSwap { amp } => Curve::StableSwap(Amplification::new(amp)),
+            PoolCurve::Weighted { .. } => Curve::Weighted,
+        }
+    }
+}
+
+impl PoolCurve {
+    /// Returns the weights of both tokens, which are equal unless the curve is weighted.
+    pub(crate) fn weights(&self) -> (u32, u32) {
+        match *self {
+            PoolCurve::Weighted { weight_a, weight_b } => (weight_a, weight_b),
+            _ => (WEIGHT_DIVISOR / 2, WEIGHT_DIVISOR / 2),
+        }
+    }
+}
+
+/// Scales amounts of two tokens with different decimals to the larger decimals.
+struct Decimals {
+    in_factor: U256,
+    out_factor: U256,
+}
+
+impl Decimals {
+    fn new(in_pair: &TokenPair, out_pair: &TokenPair) -> Self {
+        let (decimals_in, decimals_out) = (in_pair.metadata.decimals, out_pair.metadata.decimals);
+        let decimals = std::cmp::max(decimals_in, decimals_out);
+        Self {
+            in_factor: U256::exp10((decimals - decimals_in) as usize),
+            out_factor: U256::exp10((decimals - decimals_out) as usize),
+        }
+    }
+
+    fn normalize_in(&self, amount: u128) -> U256 {
+        U256::from(amount) * self.in_factor
+    }
+
+    fn normalize_out(&self, amount: u128) -> U256 {
+        U256::from(amount) * self.out_factor
+    }
+
+    fn denormalize_in_ceil(&self, amount: U256) -> u128 {
+        let (res, rem) = amount.div_mod(self.in_factor);
+        (if rem.is_zero() { res } else { res + 1 }).as_u128()
+    }
+
+    /// Rounds down.
+    fn denormalize_out(&self, amount: U256) -> u128 {
+        (amount / self.out_factor).as_u128()
+    }
+}
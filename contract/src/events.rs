@@ -0,0 +1,172 @@
+//! Events of this contract in the NEP-297 format, that are logged as `EVENT_JSON:{...}`
+//! and picked up by indexers.
+//!
+//! Liquidity shares are NEP-245 multi tokens, so their mints, burns and transfers are
+//! logged as `nep245` events, which carry the pool id as token id. Events, that are specific
+//! to this contract, are logged with the standard `orderly`.
+use near_sdk::{env, json_types::U128, AccountId};
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "standard", rename_all = "snake_case")]
+enum NearEvent<'a> {
+    Nep245(Nep245Event<'a>),
+    Orderly(OrderlyEvent<'a>),
+}
+
+impl NearEvent<'_> {
+    fn emit(self) {
+        env::log_str(&format!(
+            "EVENT_JSON:{}",
+            serde_json::to_string(&self).unwrap()
+        ));
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Nep245Event<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: Nep245EventKind<'a>,
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+enum Nep245EventKind<'a> {
+    MtMint(&'a [MtMint<'a>]),
+    MtBurn(&'a [MtBurn<'a>]),
+    MtTransfer(&'a [MtTransfer<'a>]),
+}
+
+fn emit_nep245(event_kind: Nep245EventKind) {
+    NearEvent::Nep245(Nep245Event {
+        version: "1.0.0",
+        event_kind,
+    })
+    .emit()
+}
+
+#[must_use]
+#[derive(Serialize, Debug)]
+pub struct MtMint<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: &'a [&'a str],
+    pub amounts: &'a [U128],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MtMint<'_> {
+    pub fn emit(self) {
+        emit_nep245(Nep245EventKind::MtMint(&[self]))
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug)]
+pub struct MtBurn<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: &'a [&'a str],
+    pub amounts: &'a [U128],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MtBurn<'_> {
+    pub fn emit(self) {
+        emit_nep245(Nep245EventKind::MtBurn(&[self]))
+    }
+}
+
+#[must_use]
+#[derive(Serialize, Debug)]
+pub struct MtTransfer<'a> {
+    pub old_owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+    pub token_ids: &'a [&'a str],
+    pub amounts: &'a [U128],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl MtTransfer<'_> {
+    pub fn emit(self) {
+        emit_nep245(Nep245EventKind::MtTransfer(&[self]))
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct OrderlyEvent<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: OrderlyEventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+enum OrderlyEventKind<'a> {
+    OwnerProposed(&'a [OwnerProposed<'a>]),
+    OwnershipTransferred(&'a [OwnershipTransferred<'a>]),
+    MetadataFailed(&'a [MetadataFailed<'a>]),
+}
+
+fn emit_orderly(event_kind: OrderlyEventKind) {
+    NearEvent::Orderly(OrderlyEvent {
+        version: "1.0.0",
+        event_kind,
+    })
+    .emit()
+}
+
+/// The owner proposed `new_owner_id`, who still needs to accept the ownership.
+#[must_use]
+#[derive(Serialize, Debug)]
+pub struct OwnerProposed<'a> {
+    pub owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+}
+
+impl OwnerProposed<'_> {
+    pub fn emit(self) {
+        emit_orderly(OrderlyEventKind::OwnerProposed(&[self]))
+    }
+}
+
+/// The proposed owner accepted the ownership.
+#[must_use]
+#[derive(Serialize, Debug)]
+pub struct OwnershipTransferred<'a> {
+    pub old_owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+}
+
+impl OwnershipTransferred<'_> {
+    pub fn emit(self) {
+        emit_orderly(OrderlyEventKind::OwnershipTransferred(&[self]))
+    }
+}
+
+/// Why the metadata of a token could not be fetched.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataFailure {
+    /// The `ft_metadata` call failed, e.g. because the account is not a token.
+    CallFailed,
+    /// The token returned metadata, that is not valid NEP-148 metadata.
+    InvalidMetadata,
+}
+
+/// Fetching the metadata of a token failed, so the pool was not added or not refreshed.
+#[must_use]
+#[derive(Serialize, Debug)]
+pub struct MetadataFailed<'a> {
+    pub token_id: &'a AccountId,
+    pub reason: MetadataFailure,
+}
+
+impl MetadataFailed<'_> {
+    pub fn emit(self) {
+        emit_orderly(OrderlyEventKind::MetadataFailed(&[self]))
+    }
+}
@@ -1,35 +1,108 @@
 use near_contract_standards::fungible_token::{
-    metadata::FungibleTokenMetadata, receiver::FungibleTokenReceiver,
+    metadata::FungibleTokenMetadata, receiver::FungibleTokenReceiver, FungibleToken,
 };
 use near_sdk::{
     borsh::{self, BorshDeserialize, BorshSerialize},
-    collections::LazyOption,
+    collections::{LookupMap, LookupSet, TreeMap, UnorderedSet, Vector},
     env, ext_contract,
-    json_types::U128,
+    json_types::{U128, U64},
     log, near_bindgen,
     serde::{Deserialize, Serialize},
-    AccountId, PanicOnDefault, Promise, PromiseOrValue,
+    AccountId, Gas, PanicOnDefault, Promise, PromiseOrValue, PromiseResult,
 };
+use std::collections::{hash_map::Entry, HashMap};
+
+mod concentrated;
+mod curve;
+mod events;
+mod liquidity;
+mod math;
+mod multi_token;
+mod oracle;
+mod order_book;
+mod pool;
+mod storage;
+mod upgrade;
+
+use concentrated::{ConcentratedPool, Position};
+pub use concentrated::{ConcentratedPoolInfo, PositionInfo};
+use curve::Curve;
+pub use curve::{Amplification, MAX_STABLE_SWAP_DECIMALS};
+use events::{MetadataFailed, MetadataFailure, OwnerProposed, OwnershipTransferred};
+use math::U256;
+pub use multi_token::{ExtMultiTokenReceiver, MtBaseTokenMetadata, MtContractMetadata, Token};
+pub use oracle::{MAX_OBSERVATIONS, OBSERVATIONS_STORAGE};
+pub use order_book::{
+    LimitOrder, OrderInfo, PriceLevel, MAX_FILLS_PER_SWAP, MIN_ORDER_AMOUNT, PRICE_DIVISOR,
+};
+use order_book::{Order, OrderFills};
+use pool::Pool;
+use storage::AccountStorage;
+pub use storage::MIN_STORAGE_USAGE;
+pub use upgrade::STATE_VERSION;
+
+/// Fees are expressed in basis points, i.e. hundredths of a percent.
+pub const FEE_DIVISOR: u32 = 10_000;
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(100_000_000_000_000);
+const GAS_FOR_RESOLVE_SWAP: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_RESOLVE_WITHDRAW: Gas = Gas(10_000_000_000_000);
 
 #[ext_contract]
 pub trait ExtFungibleToken {
     fn ft_metadata(&self) -> FungibleTokenMetadata;
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+    fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128>;
 }
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct OrderlyContract {
     owner: AccountId,
-    token_a: LazyOption<TokenPair>,
-    token_b: LazyOption<TokenPair>,
+    /// Account, that was proposed as new owner and still needs to accept the ownership.
+    pending_owner: Option<AccountId>,
+    /// Accounts, that can pause the contract in an emergency besides the owner.
+    guardians: UnorderedSet<AccountId>,
+    /// While paused, all incoming transfers are refunded and no liquidity can be added.
+    paused: bool,
+    /// Liquidity pools indexed by their pool id.
+    pools: Vector<Pool>,
+    /// Tokens of all pools, that can be deposited.
+    tokens: LookupSet<AccountId>,
+    /// Pending token balances per account, that have not yet been added as liquidity.
+    deposits: LookupMap<AccountId, HashMap<AccountId, u128>>,
+    /// Concentrated liquidity pools indexed by their own pool id.
+    concentrated_pools: Vector<ConcentratedPool>,
+    /// Positions in concentrated liquidity pools by their position id.
+    positions: LookupMap<u64, Position>,
+    /// Position ids of each account.
+    account_positions: LookupMap<AccountId, Vec<u64>>,
+    next_position_id: u64,
+    /// Limit orders by their order id.
+    orders: LookupMap<u64, Order>,
+    /// Order ids of each account.
+    account_orders: LookupMap<AccountId, Vec<u64>>,
+    /// Order ids by pool and sold token, grouped by price in the order they were placed.
+    order_books: LookupMap<(u64, AccountId), TreeMap<u128, Vec<u64>>>,
+    next_order_id: u64,
+    /// Storage balances of the registered accounts, that pay for their records.
+    storage_accounts: LookupMap<AccountId, AccountStorage>,
 }
 
-#[derive(BorshDeserialize, BorshSerialize)]
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
 struct TokenPair {
     pub account_id: AccountId,
     pub metadata: FungibleTokenMetadata,
     pub supply: U128,
+    /// Weight of the token in basis points, see [`PoolCurve::Weighted`].
+    pub weight: u32,
 }
 
 #[near_bindgen]
@@ -38,60 +111,419 @@ impl OrderlyContract {
     pub fn new(owner: AccountId) -> Self {
         assert!(!env::state_exists(), "Already initialized");
         log!("Contract initialized with {} as owner", owner);
-        Self {
-            owner,
-            token_a: LazyOption::new(StorageKey::TokenA.try_to_vec().unwrap(), None),
-            token_b: LazyOption::new(StorageKey::TokenB.try_to_vec().unwrap(), None),
+        upgrade::write_state_version();
+        Self::empty(owner)
+    }
+
+    /// Proposes `new_owner` as owner of the contract, who becomes the owner once
+    /// it calls `accept_ownership`. A previous proposal is replaced.
+    pub fn propose_owner(&mut self, new_owner: AccountId) {
+        self.assert_owner();
+        assert_ne!(
+            new_owner, self.owner,
+            "New owner must be different from the current owner"
+        );
+        OwnerProposed {
+            owner_id: &self.owner,
+            new_owner_id: &new_owner,
         }
+        .emit();
+        self.pending_owner = Some(new_owner);
     }
 
-    #[private]
-    pub fn init(&mut self, token_a: AccountId, token_b: AccountId) -> Promise {
-        assert!(self.token_a.get().is_none(), "Already initialized");
+    /// Transfers the ownership to the caller, if it was proposed as new owner.
+    pub fn accept_ownership(&mut self) {
+        let account_id = env::predecessor_account_id();
+        assert_eq!(
+            self.pending_owner.as_ref(),
+            Some(&account_id),
+            "Only the proposed owner can accept the ownership"
+        );
+        OwnershipTransferred {
+            old_owner_id: &self.owner,
+            new_owner_id: &account_id,
+        }
+        .emit();
+        self.owner = account_id;
+        self.pending_owner = None;
+    }
+
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner.clone()
+    }
+
+    /// Adds accounts, that can pause the contract.
+    pub fn add_guardians(&mut self, guardians: Vec<AccountId>) {
+        self.assert_owner();
+        for guardian in guardians {
+            log!("Added guardian {}", guardian);
+            self.guardians.insert(&guardian);
+        }
+    }
+
+    pub fn remove_guardians(&mut self, guardians: Vec<AccountId>) {
+        self.assert_owner();
+        for guardian in guardians {
+            log!("Removed guardian {}", guardian);
+            self.guardians.remove(&guardian);
+        }
+    }
+
+    pub fn get_guardians(&self) -> Vec<AccountId> {
+        self.guardians.to_vec()
+    }
+
+    /// Pauses swaps, deposits and adding liquidity in an emergency, e.g. if a token
+    /// got exploited. Withdrawals and removing liquidity are still possible.
+    /// Can be called by the owner and the guardians.
+    pub fn pause(&mut self) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            account_id == self.owner || self.guardians.contains(&account_id),
+            "Only the owner or a guardian can call this method"
+        );
+        log!("Contract paused by {}", account_id);
+        self.paused = true;
+    }
+
+    /// Resumes the contract after a pause, which only the owner can do.
+    pub fn resume(&mut self) {
+        self.assert_owner();
+        log!("Contract resumed by {}", self.owner);
+        self.paused = false;
+    }
+
+    /// Sets the swap fee of a pool in basis points. The fee is deducted from the input amount
+    /// and stays in the pool reserves.
+    pub fn set_fee(&mut self, pool_id: u64, fee: u32) {
+        self.assert_owner();
+        assert!(fee < FEE_DIVISOR, "Fee must be less than {}", FEE_DIVISOR);
+        let mut pool = self.internal_get_pool(pool_id);
+        log!("Swap fee of pool {} set to {} basis points", pool_id, fee);
+        pool.fee = fee;
+        self.pools.replace(pool_id, &pool);
+    }
+
+    /// Ramps the amplification of a StableSwap pool linearly from its current value
+    /// to `target_amp` until the block timestamp `end_timestamp` in nanoseconds.
+    pub fn ramp_amplification(&mut self, pool_id: u64, target_amp: u64, end_timestamp: U64) {
+        self.assert_owner();
+        let mut pool = self.internal_get_pool(pool_id);
+        if let Curve::StableSwap(amplification) = &mut pool.curve {
+            amplification.ramp(target_amp, end_timestamp.0, env::block_timestamp());
+        } else {
+            panic!("Pool {} is not a StableSwap pool", pool_id);
+        }
+        log!(
+            "Ramping amplification of pool {} to {} until {}",
+            pool_id,
+            target_amp,
+            end_timestamp.0
+        );
+        self.pools.replace(pool_id, &pool);
+    }
+
+    /// Adds a new liquidity pool for `token_a` and `token_b` after fetching their metadata.
+    /// The pool uses the constant product curve, unless another `curve` is given.
+    /// The id of the pool is returned by the callback.
+    ///
+    /// The attached deposit needs to cover the storage of all [`MAX_OBSERVATIONS`] of the pool,
+    /// see `get_pool_deposit`. The rest is refunded.
+    #[payable]
+    pub fn add_pool(
+        &mut self,
+        token_a: AccountId,
+        token_b: AccountId,
+        curve: Option<PoolCurve>,
+    ) -> Promise {
+        self.assert_owner();
+        assert_ne!(token_a, token_b, "Tokens of a pool must be different");
+        let curve = curve.unwrap_or_default();
+        match curve {
+            PoolCurve::ConstantProduct => {}
+            PoolCurve::StableSwap { amp } => curve::assert_amp(amp),
+            PoolCurve::Weighted { weight_a, weight_b } => curve::assert_weights(weight_a, weight_b),
+        }
+        self.internal_reserve_observations();
         ext_fungible_token::ext(token_a.clone())
             .ft_metadata()
             .and(ext_fungible_token::ext(token_b.clone()).ft_metadata())
-            .then(Self::ext(env::current_account_id()).handle_init(token_a, token_b))
+            .then(Self::ext(env::current_account_id()).handle_add_pool(token_a, token_b, curve))
     }
 
+    /// Adds the pool with the fetched metadata of both tokens and returns its id.
+    /// If the metadata of either token could not be fetched, e.g. because the account
+    /// is not a fungible token, no pool is added, the deposit is refunded to the owner
+    /// and `None` is returned. The same applies to StableSwap pools of tokens with more
+    /// than [`MAX_STABLE_SWAP_DECIMALS`].
     #[private]
-    pub fn handle_init(
+    pub fn handle_add_pool(
         &mut self,
         token_a: AccountId,
         token_b: AccountId,
-        #[callback_unwrap] token_a_metadata: FungibleTokenMetadata,
-        #[callback_unwrap] token_b_metadata: FungibleTokenMetadata,
-    ) {
-        self.token_a.set(&TokenPair {
-            account_id: token_a,
-            metadata: token_a_metadata,
-            supply: U128::from(0),
-        });
-        self.token_b.set(&TokenPair {
-            account_id: token_b,
-            metadata: token_b_metadata,
-            supply: U128::from(0),
-        });
+        curve: PoolCurve,
+    ) -> Option<u64> {
+        let (token_a_metadata, token_b_metadata) = match metadata_results(&token_a, &token_b) {
+            Some(metadata) => metadata,
+            None => {
+                log!("Pool for tokens {} and {} was not added", token_a, token_b);
+                self.internal_refund_observations();
+                return None;
+            }
+        };
+        let decimals = std::cmp::max(token_a_metadata.decimals, token_b_metadata.decimals);
+        if matches!(curve, PoolCurve::StableSwap { .. }) && decimals > MAX_STABLE_SWAP_DECIMALS {
+            log!(
+                "Pool for tokens {} and {} was not added, because StableSwap pools support at most {} decimals",
+                token_a,
+                token_b,
+                MAX_STABLE_SWAP_DECIMALS
+            );
+            self.internal_refund_observations();
+            return None;
+        }
+        let pool_id = self.pools.len();
+        let (weight_a, weight_b) = curve.weights();
+        self.pools.push(&Pool::new(
+            pool_id,
+            TokenPair {
+                account_id: token_a.clone(),
+                metadata: token_a_metadata,
+                supply: U128::from(0),
+                weight: weight_a,
+            },
+            TokenPair {
+                account_id: token_b.clone(),
+                metadata: token_b_metadata,
+                supply: U128::from(0),
+                weight: weight_b,
+            },
+            curve.into(),
+        ));
+        self.tokens.insert(&token_a);
+        self.tokens.insert(&token_b);
+        log!(
+            "Added pool {} for tokens {} and {}",
+            pool_id,
+            token_a,
+            token_b
+        );
+        Some(pool_id)
     }
 
-    pub fn get_contract_info(&self) -> Option<ContractInfo> {
-        if let (Some(token_a), Some(token_b)) = (self.token_a.get(), self.token_b.get()) {
-            Some(ContractInfo {
-                token_a_id: token_a.account_id,
-                token_a_name: token_a.metadata.name,
-                token_a_symbol: token_a.metadata.symbol,
-                token_a_supply: token_a.supply,
-                token_a_decimals: token_a.metadata.decimals,
-                token_b_id: token_b.account_id,
-                token_b_name: token_b.metadata.name,
-                token_b_symbol: token_b.metadata.symbol,
-                token_b_supply: token_b.supply,
-                token_b_decimals: token_b.metadata.decimals,
-            })
-        } else {
-            None
+    /// Fetches the metadata of `token_id` again, e.g. after its name or icon changed,
+    /// and updates it in all pools of the token. The number of updated pools is returned
+    /// by the callback.
+    pub fn refresh_metadata(&mut self, token_id: AccountId) -> Promise {
+        self.assert_owner();
+        assert!(
+            self.tokens.contains(&token_id),
+            "Token {} does not belong to any liquidity pool",
+            token_id
+        );
+        ext_fungible_token::ext(token_id.clone())
+            .ft_metadata()
+            .then(Self::ext(env::current_account_id()).handle_refresh_metadata(token_id))
+    }
+
+    /// Updates the metadata of `token_id` in all pools and returns the number of updated pools.
+    /// Pools keep their metadata, if the decimals changed, because StableSwap pools
+    /// normalize the reserves by them.
+    #[private]
+    pub fn handle_refresh_metadata(&mut self, token_id: AccountId) -> u64 {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expected one promise result"
+        );
+        let metadata = match metadata_result(0, &token_id) {
+            Some(metadata) => metadata,
+            None => return 0,
+        };
+        let mut updated = 0;
+        for pool_id in 0..self.pools.len() {
+            let mut pool = self.internal_get_pool(pool_id);
+            if pool.contains(&token_id) && update_metadata(pool.pairs_mut(&token_id).0, &metadata) {
+                self.pools.replace(pool_id, &pool);
+                updated += 1;
+            }
+        }
+        for pool_id in 0..self.concentrated_pools.len() {
+            let mut pool = self.internal_get_concentrated_pool(pool_id);
+            if pool.contains(&token_id) && update_metadata(pool.pairs_mut(&token_id).0, &metadata) {
+                self.concentrated_pools.replace(pool_id, &pool);
+                updated += 1;
+            }
+        }
+        log!(
+            "Refreshed metadata of token {} in {} pools",
+            token_id,
+            updated
+        );
+        updated
+    }
+
+    /// Resolves the outgoing transfer of a swap and returns the amount to refund to the sender.
+    /// This is the unused input of an exact output swap. If the transfer failed, e.g. because
+    /// the receiver is not registered on the output token, the output is credited to the deposits
+    /// of the sender like a failed withdrawal. The reserves are not restored, because other swaps
+    /// might have changed them in the meantime.
+    ///
+    /// If the output was forwarded via `ft_transfer_call`, the downstream contract might
+    /// not use all of it. The unused output is credited to the deposits of the sender as well.
+    #[private]
+    pub fn handle_swap(
+        &mut self,
+        sender_id: AccountId,
+        token_out: AccountId,
+        amount_out: U128,
+        amount_unused: U128,
+    ) -> U128 {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expected one promise result"
+        );
+        let amount_out = amount_out.0;
+        let amount_used = match env::promise_result(0) {
+            // `ft_transfer_call` returns the used amount, whereas `ft_transfer` returns nothing
+            PromiseResult::Successful(value) => {
+                serde_json::from_slice::<U128>(&value).map_or(amount_out, |used| used.0)
+            }
+            _ => 0,
+        };
+
+        if amount_used < amount_out {
+            let amount_credited = amount_out - amount_used;
+            if amount_used == 0 {
+                log!(
+                    "Transfer of {} of token {} failed. Crediting {} to deposits of {}",
+                    amount_out,
+                    token_out,
+                    amount_credited,
+                    sender_id
+                );
+            } else {
+                log!(
+                    "Receiver used {} of {} of token {}. Crediting {} to deposits of {}",
+                    amount_used,
+                    amount_out,
+                    token_out,
+                    amount_credited,
+                    sender_id
+                );
+            }
+            self.internal_deposit(&sender_id, &token_out, amount_credited);
+        }
+        amount_unused
+    }
+
+    /// Returns the amount of the other token, that would be received for swapping `amount_in`
+    /// including the limit orders, that would be filled.
+    pub fn get_return(&self, pool_id: u64, token_in: AccountId, amount_in: U128) -> U128 {
+        let pool = self.internal_get_pool(pool_id);
+        let fills = self.internal_match_orders(pool_id, &pool, &token_in, amount_in.0);
+        let (in_pair, out_pair) = pool.pairs(&token_in);
+        let amount_in = amount_in.0 - fills.amount_in;
+        if amount_in == 0 || in_pair.supply.0 == 0 || out_pair.supply.0 == 0 {
+            return fills.amount_out.into();
+        }
+        (fills.amount_out
+            + pool
+                .curve
+                .get_amount_out(amount_in, in_pair, out_pair, pool.fee))
+        .into()
+    }
+
+    /// Returns the amount of the other token, that needs to be swapped
+    /// to receive at least `amount_out` of `token_out`. Like exact output swaps
+    /// this only covers the pool without the limit orders.
+    pub fn get_amount_in(&self, pool_id: u64, token_out: AccountId, amount_out: U128) -> U128 {
+        let pool = self.internal_get_pool(pool_id);
+        let (out_pair, in_pair) = pool.pairs(&token_out);
+        pool.curve
+            .get_amount_in(amount_out.0, in_pair, out_pair, pool.fee)
+            .expect("Not enough liquidity available for swap")
+            .into()
+    }
+
+    /// Returns the price impact in basis points for swapping `amount_in` in the pool.
+    /// Limit orders, that a swap fills first, are not included.
+    pub fn get_price_impact(&self, pool_id: u64, token_in: AccountId, amount_in: U128) -> u32 {
+        let pool = self.internal_get_pool(pool_id);
+        let (in_pair, out_pair) = pool.pairs(&token_in);
+        pool.curve
+            .get_price_impact(amount_in.0, in_pair, out_pair, pool.fee)
+    }
+
+    pub fn get_contract_info(&self) -> ContractInfo {
+        ContractInfo {
+            owner: self.owner.clone(),
+            pool_count: self.pools.len(),
+            paused: self.paused,
+        }
+    }
+
+    pub fn get_pool(&self, pool_id: u64) -> Option<PoolInfo> {
+        self.pools.get(pool_id).map(|pool| pool.info())
+    }
+
+    /// Returns the amplification of a StableSwap pool including an ongoing ramp.
+    pub fn get_amplification(&self, pool_id: u64) -> Option<Amplification> {
+        match self.internal_get_pool(pool_id).curve {
+            Curve::StableSwap(amplification) => Some(amplification),
+            _ => None,
+        }
+    }
+
+    /// Returns up to `limit` pools starting at pool id `from_index`.
+    pub fn get_pools(&self, from_index: u64, limit: u64) -> Vec<PoolInfo> {
+        (from_index..std::cmp::min(from_index.saturating_add(limit), self.pools.len()))
+            .map(|pool_id| self.internal_get_pool(pool_id).info())
+            .collect()
+    }
+}
+
+impl OrderlyContract {
+    /// Returns a contract without any pools.
+    fn empty(owner: AccountId) -> Self {
+        Self {
+            owner,
+            pending_owner: None,
+            guardians: UnorderedSet::new(StorageKey::Guardians.try_to_vec().unwrap()),
+            paused: false,
+            pools: Vector::new(StorageKey::Pools.try_to_vec().unwrap()),
+            tokens: LookupSet::new(StorageKey::Tokens.try_to_vec().unwrap()),
+            deposits: LookupMap::new(StorageKey::Deposits.try_to_vec().unwrap()),
+            concentrated_pools: Vector::new(StorageKey::ConcentratedPools.try_to_vec().unwrap()),
+            positions: LookupMap::new(StorageKey::Positions.try_to_vec().unwrap()),
+            account_positions: LookupMap::new(StorageKey::AccountPositions.try_to_vec().unwrap()),
+            next_position_id: 0,
+            orders: LookupMap::new(StorageKey::Orders.try_to_vec().unwrap()),
+            account_orders: LookupMap::new(StorageKey::AccountOrders.try_to_vec().unwrap()),
+            order_books: LookupMap::new(StorageKey::OrderBooks.try_to_vec().unwrap()),
+            next_order_id: 0,
+            storage_accounts: LookupMap::new(StorageKey::StorageAccounts.try_to_vec().unwrap()),
         }
     }
+
+    fn internal_get_pool(&self, pool_id: u64) -> Pool {
+        self.pools
+            .get(pool_id)
+            .unwrap_or_else(|| panic!("Pool {} does not exist", pool_id))
+    }
+
+    fn assert_owner(&self) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can call this method"
+        );
+    }
+
+    pub(crate) fn assert_not_paused(&self) {
+        assert!(!self.paused, "Contract is paused");
+    }
 }
 
 #[near_bindgen]
@@ -100,68 +532,373 @@ impl FungibleTokenReceiver for OrderlyContract {
         &mut self,
         sender_id: AccountId,
         amount: U128,
-        #[allow(unused_variables)] msg: String,
+        msg: String,
     ) -> PromiseOrValue<U128> {
-        let (mut pair_a, mut pair_b) = (
-            self.token_a.get().expect("Contract uninitialized"),
-            self.token_b.get().expect("Contract uninitialized"),
-        );
+        let token_in = env::predecessor_account_id();
+        if self.paused {
+            log!("Contract is paused");
+            return PromiseOrValue::Value(amount);
+        }
+        if !self.tokens.contains(&token_in) {
+            log!("Deposited token address does not belong to any liquidity pool");
+            return PromiseOrValue::Value(amount);
+        }
+        let transfer_msg = if msg.is_empty() {
+            TransferMsg::default()
+        } else if let Ok(transfer_msg) = serde_json::from_str::<TransferMsg>(&msg) {
+            transfer_msg
+        } else {
+            log!("Invalid transfer message: {}", msg);
+            return PromiseOrValue::Value(amount);
+        };
+
+        match transfer_msg.action {
+            TransferAction::Swap if transfer_msg.limit_order.is_some() => {
+                self.internal_place_order(sender_id, token_in, amount, transfer_msg)
+            }
+            TransferAction::Swap => self.internal_swap(sender_id, token_in, amount, transfer_msg),
+            TransferAction::ConcentratedSwap => {
+                self.internal_concentrated_swap(sender_id, token_in, amount, transfer_msg)
+            }
+            TransferAction::Deposit => {
+                if !self.is_registered(&sender_id) {
+                    log!(
+                        "Account {} is not registered with storage_deposit",
+                        sender_id
+                    );
+                    return PromiseOrValue::Value(amount);
+                }
+                self.internal_deposit(&sender_id, &token_in, amount.0);
+                // panicking refunds the transfer
+                self.assert_storage_covered(&sender_id);
+                PromiseOrValue::Value(0.into())
+            }
+        }
+    }
+}
 
-        let prod = pair_a.supply.0 * pair_b.supply.0;
-        if prod == 0 && sender_id != self.owner {
-            log!("Not enough liquidity available for swap");
+impl OrderlyContract {
+    fn internal_swap(
+        &mut self,
+        sender_id: AccountId,
+        token_in: AccountId,
+        amount: U128,
+        transfer_msg: TransferMsg,
+    ) -> PromiseOrValue<U128> {
+        if transfer_msg.receiver_id.is_some() && transfer_msg.then_call.is_some() {
+            log!("Swap can either have a receiver_id or then_call");
             return PromiseOrValue::Value(amount);
         }
-        let (in_pair, in_token, out_pair, out_token) =
-            if env::predecessor_account_id() == pair_a.account_id {
-                (
-                    &mut pair_a,
-                    &mut self.token_a,
-                    &mut pair_b,
-                    &mut self.token_b,
-                )
-            } else if env::predecessor_account_id() == pair_b.account_id {
-                (
-                    &mut pair_b,
-                    &mut self.token_b,
-                    &mut pair_a,
-                    &mut self.token_a,
-                )
+        if let Some(deadline) = transfer_msg.deadline {
+            if env::block_timestamp() > deadline.0 {
+                log!("Swap deadline of {} exceeded", deadline.0);
+                return PromiseOrValue::Value(amount);
+            }
+        }
+        // limit orders are only filled by swaps of the whole transferred amount in a single pool
+        let match_orders = transfer_msg.pool_id.is_some() && transfer_msg.amount_out.is_none();
+        let route = match (transfer_msg.pool_id, transfer_msg.route) {
+            (Some(_), Some(_)) => {
+                log!("Swap can either have a pool_id or route");
+                return PromiseOrValue::Value(amount);
+            }
+            (Some(pool_id), None) => {
+                let token_out = match self.pools.get(pool_id) {
+                    Some(pool) if pool.contains(&token_in) => {
+                        pool.pairs(&token_in).1.account_id.clone()
+                    }
+                    Some(_) => {
+                        log!("Deposited token address does not belong to liquidity pool");
+                        return PromiseOrValue::Value(amount);
+                    }
+                    None => {
+                        log!("Pool {} does not exist", pool_id);
+                        return PromiseOrValue::Value(amount);
+                    }
+                };
+                vec![Hop {
+                    pool_id,
+                    token_out,
+                    min_amount_out: None,
+                }]
+            }
+            (None, Some(route)) => {
+                if route.is_empty() {
+                    log!("Route must not be empty");
+                    return PromiseOrValue::Value(amount);
+                }
+                if route.len() > 1 && transfer_msg.amount_out.is_some() {
+                    log!("Exact output swaps are not supported for routes");
+                    return PromiseOrValue::Value(amount);
+                }
+                route
+            }
+            (None, None) => {
+                log!("Swap requires a pool_id or route");
+                return PromiseOrValue::Value(amount);
+            }
+        };
+
+        // pools are only written back once all hops succeeded
+        let mut pools: HashMap<u64, Pool> = HashMap::new();
+        let mut order_fills = Vec::with_capacity(route.len());
+        let mut amount_unused = 0;
+        let (mut hop_token_in, mut hop_amount) = (token_in, amount.0);
+        for hop in route {
+            let pool = match pools.entry(hop.pool_id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    if let Some(pool) = self.pools.get(hop.pool_id) {
+                        entry.insert(pool)
+                    } else {
+                        log!("Pool {} does not exist", hop.pool_id);
+                        return PromiseOrValue::Value(amount);
+                    }
+                }
+            };
+            if hop_token_in == hop.token_out
+                || !pool.contains(&hop_token_in)
+                || !pool.contains(&hop.token_out)
+            {
+                log!(
+                    "Swap of token {} for token {} is not possible in pool {}",
+                    hop_token_in,
+                    hop.token_out,
+                    hop.pool_id
+                );
+                return PromiseOrValue::Value(amount);
+            }
+
+            let fills = if match_orders {
+                self.internal_match_orders(hop.pool_id, pool, &hop_token_in, hop_amount)
             } else {
-                log!("Deposited token address does not belong to liquidity pool");
+                OrderFills::default()
+            };
+            let pool_amount_in = hop_amount - fills.amount_in;
+            let (fee, curve) = (pool.fee, pool.curve.clone());
+            let (in_pair, out_pair) = pool.pairs_mut(&hop_token_in);
+            if pool_amount_in > 0 && (in_pair.supply.0 == 0 || out_pair.supply.0 == 0) {
+                log!("Not enough liquidity available for swap");
                 return PromiseOrValue::Value(amount);
+            }
+            let (amount_in, amount_out) = if let Some(amount_out) = transfer_msg.amount_out {
+                if amount_out.0 >= out_pair.supply.0 {
+                    log!("Not enough liquidity available for swap");
+                    return PromiseOrValue::Value(amount);
+                }
+                let amount_in = match curve.get_amount_in(amount_out.0, in_pair, out_pair, fee) {
+                    Some(amount_in) => amount_in,
+                    None => {
+                        log!("Not enough liquidity available for swap");
+                        return PromiseOrValue::Value(amount);
+                    }
+                };
+                if amount_in > hop_amount {
+                    log!(
+                        "Not enough tokens transferred: {} of token {} are needed for {} of token {}",
+                        amount_in,
+                        in_pair.account_id,
+                        amount_out.0,
+                        out_pair.account_id
+                    );
+                    return PromiseOrValue::Value(amount);
+                }
+                amount_unused = hop_amount - amount_in;
+                (amount_in, amount_out.0)
+            } else if pool_amount_in == 0 {
+                (0, 0)
+            } else {
+                (
+                    pool_amount_in,
+                    curve.get_amount_out(pool_amount_in, in_pair, out_pair, fee),
+                )
             };
-        in_pair.supply.0 += amount.0;
-        in_token.set(in_pair);
-        if sender_id != self.owner {
-            let out_pair_supply = out_pair.supply.0;
-            // this will truncate the remainder, thus resulting in a loss of lp token.
-            // in a real world solution, this would need to be addressed.
-            out_pair.supply.0 = prod / in_pair.supply.0;
-            let out_pair_diff = out_pair_supply - out_pair.supply.0;
+            let total_amount_out = amount_out + fills.amount_out;
+            if let Some(min_amount_out) = hop.min_amount_out {
+                if total_amount_out < min_amount_out.0 {
+                    log!(
+                        "Slippage exceeded: {} of token {} is less than the minimum of {}",
+                        total_amount_out,
+                        out_pair.account_id,
+                        min_amount_out.0
+                    );
+                    return PromiseOrValue::Value(amount);
+                }
+            }
+            let (reserve_in, reserve_out) = (in_pair.supply.0, out_pair.supply.0);
+            // the fee is not part of the swap, but stays in the pool
+            in_pair.supply.0 += amount_in;
+            out_pair.supply.0 -= amount_out;
+            // the invariants of constant product and weighted pools are checked against
+            // the rounding of the swap, StableSwap outputs are rounded down, so that its
+            // invariant never decreases
+            match curve {
+                Curve::ConstantProduct => assert!(
+                    U256::from(in_pair.supply.0) * U256::from(out_pair.supply.0)
+                        >= U256::from(reserve_in) * U256::from(reserve_out),
+                    "Swap must not decrease the product of the reserves"
+                ),
+                Curve::Weighted => assert!(
+                    math::weighted_invariant_holds(
+                        (reserve_in, in_pair.supply.0),
+                        in_pair.weight,
+                        (reserve_out, out_pair.supply.0),
+                        out_pair.weight,
+                    ),
+                    "Swap must not decrease the weighted product of the reserves"
+                ),
+                Curve::StableSwap(_) => {}
+            }
             log!(
-                "User {} swapping {} of token {} for {} of token {}",
+                "User {} swapping {} of token {} for {} of token {} in pool {}",
                 &sender_id,
-                amount.0,
+                amount_in,
                 in_pair.account_id,
-                out_pair_diff,
-                out_pair.account_id
+                amount_out,
+                out_pair.account_id,
+                hop.pool_id
             );
-            out_token.set(out_pair);
 
-            ext_fungible_token::ext(out_pair.account_id.clone())
-                .with_attached_deposit(1)
-                .with_static_gas(10_000_000_000_000.into())
-                .ft_transfer(sender_id, out_pair_diff.into(), Some("swap".to_string())); // .then(Self::ext(env::current_account_id()).handle_swap(token_a, token_b)),
-            PromiseOrValue::Value(0.into())
-        } else {
-            PromiseOrValue::Value(0.into())
+            order_fills.push((hop_token_in, fills));
+            hop_token_in = hop.token_out;
+            hop_amount = total_amount_out;
+        }
+        if let Some(min_amount_out) = transfer_msg.min_amount_out {
+            if hop_amount < min_amount_out.0 {
+                log!(
+                    "Slippage exceeded: {} of token {} is less than the minimum of {}",
+                    hop_amount,
+                    hop_token_in,
+                    min_amount_out.0
+                );
+                return PromiseOrValue::Value(amount);
+            }
+        }
+        for (pool_id, mut pool) in pools {
+            pool.update_oracle();
+            self.pools.replace(pool_id, &pool);
         }
+        for (token_in, fills) in order_fills {
+            self.internal_fill_orders(&token_in, fills);
+        }
+
+        let transfer = ext_fungible_token::ext(hop_token_in.clone()).with_attached_deposit(1);
+        let transfer = if let Some(then_call) = transfer_msg.then_call {
+            transfer
+                .with_static_gas(GAS_FOR_FT_TRANSFER_CALL)
+                .ft_transfer_call(
+                    then_call.receiver_id,
+                    hop_amount.into(),
+                    Some("swap".to_string()),
+                    then_call.msg,
+                )
+        } else {
+            transfer.with_static_gas(GAS_FOR_FT_TRANSFER).ft_transfer(
+                transfer_msg
+                    .receiver_id
+                    .unwrap_or_else(|| sender_id.clone()),
+                hop_amount.into(),
+                Some("swap".to_string()),
+            )
+        };
+        transfer
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_SWAP)
+                    .handle_swap(
+                        sender_id,
+                        hop_token_in,
+                        hop_amount.into(),
+                        amount_unused.into(),
+                    ),
+            )
+            .into()
     }
 }
 
+/// Message passed as JSON via the `msg` of `ft_transfer_call`.
+/// An empty message is an invalid swap, because the pool id is missing.
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct TransferMsg {
+    #[serde(default)]
+    pub action: TransferAction,
+    /// Pool to swap in. Swaps require either a pool id or a route.
+    pub pool_id: Option<u64>,
+    /// Swaps along several pools, where the output of each hop is the input of the next one.
+    /// Only the output of the last hop is sent out.
+    pub route: Option<Vec<Hop>>,
+    /// The swap will be refunded, if it would return less than this amount.
+    /// For routes this is the minimum output of the last hop.
+    pub min_amount_out: Option<U128>,
+    /// Swaps for exactly this amount instead of the whole transferred amount.
+    /// The unused part of the transferred amount will be refunded.
+    pub amount_out: Option<U128>,
+    /// The swap will be refunded, if it gets executed after this block timestamp in nanoseconds.
+    pub deadline: Option<U64>,
+    /// Account, that receives the output of the swap instead of the sender.
+    /// If the transfer to this account fails, the sender gets refunded.
+    pub receiver_id: Option<AccountId>,
+    /// Forwards the output of the swap via `ft_transfer_call` to another contract.
+    pub then_call: Option<ThenCall>,
+    /// Places a limit order with the transferred tokens instead of swapping them.
+    pub limit_order: Option<LimitOrder>,
+}
+
+/// Curve of a pool, that is passed to `add_pool` and returned in views.
+#[derive(Deserialize, Serialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolCurve {
+    /// Constant product curve `x * y = k`, that is suited for any pair.
+    #[default]
+    ConstantProduct,
+    /// StableSwap curve with the amplification coefficient `amp` for pegged pairs,
+    /// e.g. stablecoins. The higher the amplification, the lower the slippage close to the peg.
+    StableSwap { amp: u64 },
+    /// Weighted product curve for pairs with unequal value, e.g. an 80/20 pool.
+    /// The weights of both tokens are given in basis points and must add up to 10_000.
+    Weighted { weight_a: u32, weight_b: u32 },
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Hop {
+    pub pool_id: u64,
+    /// Token, that is received from this hop.
+    pub token_out: AccountId,
+    /// The swap will be refunded, if this hop would return less than this amount.
+    pub min_amount_out: Option<U128>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ThenCall {
+    /// Contract, that receives the output of the swap.
+    pub receiver_id: AccountId,
+    /// Message passed to `ft_on_transfer` of the receiver.
+    pub msg: String,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferAction {
+    /// Swaps the transferred tokens for the other token of the pool.
+    #[default]
+    Swap,
+    /// Deposits the transferred tokens into the pending balance of the sender,
+    /// which can then be used to add liquidity or be withdrawn.
+    Deposit,
+    /// Swaps the transferred tokens in the concentrated liquidity pool `pool_id`.
+    /// Any input, that cannot be swapped due to missing liquidity, is refunded.
+    ConcentratedSwap,
+}
+
 #[derive(Deserialize, Serialize, Eq, PartialEq, Debug)]
 pub struct ContractInfo {
+    pub owner: AccountId,
+    pub pool_count: u64,
+    pub paused: bool,
+}
+
+#[derive(Deserialize, Serialize, Eq, PartialEq, Debug)]
+pub struct PoolInfo {
     pub token_a_id: AccountId,
     pub token_a_name: String,
     pub token_a_symbol: String,
@@ -172,21 +909,89 @@ pub struct ContractInfo {
     pub token_b_symbol: String,
     pub token_b_supply: U128,
     pub token_b_decimals: u8,
+    pub fee: u32,
+    pub curve: PoolCurve,
+}
+
+/// Returns the metadata of both tokens from the results of their `ft_metadata` calls.
+pub(crate) fn metadata_results(
+    token_a: &AccountId,
+    token_b: &AccountId,
+) -> Option<(FungibleTokenMetadata, FungibleTokenMetadata)> {
+    assert_eq!(
+        env::promise_results_count(),
+        2,
+        "Expected two promise results"
+    );
+    // both results are read, so that a failure is logged for each token
+    match (metadata_result(0, token_a), metadata_result(1, token_b)) {
+        (Some(token_a_metadata), Some(token_b_metadata)) => {
+            Some((token_a_metadata, token_b_metadata))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the metadata of `token_id` from the promise result at `index`
+/// or emits an event why it is missing.
+fn metadata_result(index: u64, token_id: &AccountId) -> Option<FungibleTokenMetadata> {
+    let reason = match env::promise_result(index) {
+        PromiseResult::Successful(value) => {
+            match serde_json::from_slice::<FungibleTokenMetadata>(&value) {
+                Ok(metadata) => return Some(metadata),
+                Err(_) => MetadataFailure::InvalidMetadata,
+            }
+        }
+        _ => MetadataFailure::CallFailed,
+    };
+    MetadataFailed { token_id, reason }.emit();
+    None
+}
+
+/// Sets the metadata of a pair, unless the decimals changed. Returns whether it was set.
+fn update_metadata(pair: &mut TokenPair, metadata: &FungibleTokenMetadata) -> bool {
+    if pair.metadata.decimals != metadata.decimals {
+        log!(
+            "Decimals of token {} changed from {} to {}. Keeping its metadata",
+            pair.account_id,
+            pair.metadata.decimals,
+            metadata.decimals
+        );
+        return false;
+    }
+    pair.metadata = metadata.clone();
+    true
 }
 
 #[derive(BorshSerialize)]
 enum StorageKey {
-    TokenA,
-    TokenB,
+    Pools,
+    Tokens,
+    Shares { pool_id: u64 },
+    Deposits,
+    ConcentratedPools,
+    Ticks { pool_id: u64 },
+    Positions,
+    AccountPositions,
+    Orders,
+    AccountOrders,
+    OrderBooks,
+    PriceLevels { pool_id: u64, token_id: AccountId },
+    Observations { pool_id: u64 },
+    Guardians,
+    StorageAccounts,
+    ConcentratedObservations { pool_id: u64 },
 }
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use super::*;
 
+    use near_contract_standards::storage_management::StorageManagement;
     use near_sdk::{
-        test_utils::{accounts, VMContextBuilder},
-        testing_env,
+        mock::VmAction,
+        test_utils::{accounts, get_created_receipts, get_logs, VMContextBuilder},
+        testing_env, RuntimeFeesConfig, VMConfig,
     };
 
     fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
@@ -198,10 +1003,2839 @@ mod tests {
         builder
     }
 
+    const DEPOSIT_MSG: &str = r#"{"action":"deposit"}"#;
+    const SWAP_MSG: &str = r#"{"pool_id":0}"#;
+
+    fn get_metadata(name: &str, symbol: &str) -> FungibleTokenMetadata {
+        FungibleTokenMetadata {
+            spec: "ft-1.0.0".to_string(),
+            name: name.to_string(),
+            symbol: symbol.to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 12,
+        }
+    }
+
+    /// Sets the metadata as results of the `ft_metadata` calls, that are passed to a callback.
+    fn set_metadata_results(metadata: &[FungibleTokenMetadata]) {
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            metadata
+                .iter()
+                .map(|metadata| PromiseResult::Successful(serde_json::to_vec(metadata).unwrap()))
+                .collect(),
+        );
+    }
+
+    /// Sets up a StableSwap pool, where token a and token b have the given decimals.
+    fn setup_stable_pool(
+        amp: u64,
+        (decimals_a, supply_a): (u8, u128),
+        (decimals_b, supply_b): (u8, u128),
+    ) -> OrderlyContract {
+        let mut contract = OrderlyContract::new(accounts(1));
+        set_metadata_results(&[
+            FungibleTokenMetadata {
+                decimals: decimals_a,
+                ..get_metadata("TokenA", "TKNA")
+            },
+            FungibleTokenMetadata {
+                decimals: decimals_b,
+                ..get_metadata("TokenB", "TKNB")
+            },
+        ]);
+        contract.handle_add_pool(accounts(2), accounts(3), PoolCurve::StableSwap { amp });
+        add_liquidity(&mut contract, accounts(1), supply_a, supply_b);
+        contract
+    }
+
+    fn setup_weighted_pool(
+        (weight_a, supply_a): (u32, u128),
+        (weight_b, supply_b): (u32, u128),
+    ) -> OrderlyContract {
+        let mut contract = OrderlyContract::new(accounts(1));
+        set_metadata_results(&[
+            get_metadata("TokenA", "TKNA"),
+            get_metadata("TokenB", "TKNB"),
+        ]);
+        contract.handle_add_pool(
+            accounts(2),
+            accounts(3),
+            PoolCurve::Weighted { weight_a, weight_b },
+        );
+        add_liquidity(&mut contract, accounts(1), supply_a, supply_b);
+        contract
+    }
+
+    fn setup_contract(supply_a: u128, supply_b: u128) -> OrderlyContract {
+        let mut contract = OrderlyContract::new(accounts(1));
+        set_metadata_results(&[
+            get_metadata("TokenA", "TKNA"),
+            get_metadata("TokenB", "TKNB"),
+        ]);
+        contract.handle_add_pool(accounts(2), accounts(3), PoolCurve::ConstantProduct);
+        add_liquidity(&mut contract, accounts(1), supply_a, supply_b);
+        contract
+    }
+
+    /// Sets up a second pool of token b and token c with 2_000 liquidity each.
+    fn setup_two_pools() -> OrderlyContract {
+        let mut contract = setup_contract(1_000, 1_000);
+        set_metadata_results(&[
+            get_metadata("TokenB", "TKNB"),
+            get_metadata("TokenC", "TKNC"),
+        ]);
+        let pool_id =
+            contract.handle_add_pool(accounts(3), accounts(5), PoolCurve::ConstantProduct);
+        assert_eq!(pool_id, Some(1));
+        deposit(&mut contract, accounts(1), accounts(3), 2_000);
+        deposit(&mut contract, accounts(1), accounts(5), 2_000);
+        testing_env!(get_context(accounts(1)).build());
+        contract.add_liquidity(1, 2_000.into(), 2_000.into(), 0.into());
+        contract
+    }
+
+    fn route_msg(hop_min_amount_out: u128, min_amount_out: u128) -> String {
+        format!(
+            r#"{{"route":[{{"pool_id":0,"token_out":"{}","min_amount_out":"{}"}},{{"pool_id":1,"token_out":"{}"}}],"min_amount_out":"{}"}}"#,
+            accounts(3),
+            hop_min_amount_out,
+            accounts(5),
+            min_amount_out
+        )
+    }
+
+    /// Storage balance, that covers the records of an account in all tests.
+    const STORAGE_DEPOSIT: u128 = 100_000_000_000_000_000_000_000;
+
+    fn storage_deposit(contract: &mut OrderlyContract, account_id: AccountId) {
+        testing_env!(get_context(account_id)
+            .attached_deposit(STORAGE_DEPOSIT)
+            .build());
+        contract.storage_deposit(None, None);
+    }
+
+    /// Deposits tokens and registers the account beforehand, if necessary.
+    fn deposit(
+        contract: &mut OrderlyContract,
+        account_id: AccountId,
+        token: AccountId,
+        amount: u128,
+    ) {
+        if contract.storage_balance_of(account_id.clone()).is_none() {
+            storage_deposit(contract, account_id.clone());
+        }
+        testing_env!(get_context(token).build());
+        contract.ft_on_transfer(account_id, amount.into(), DEPOSIT_MSG.to_string());
+    }
+
+    fn add_liquidity(
+        contract: &mut OrderlyContract,
+        account_id: AccountId,
+        amount_a: u128,
+        amount_b: u128,
+    ) -> U128 {
+        deposit(contract, account_id.clone(), accounts(2), amount_a);
+        deposit(contract, account_id.clone(), accounts(3), amount_b);
+        testing_env!(get_context(account_id).build());
+        contract.add_liquidity(0, amount_a.into(), amount_b.into(), 0.into())
+    }
+
     #[test]
     fn test_new() {
         let context = get_context(accounts(1));
         testing_env!(context.build());
         OrderlyContract::new(accounts(1));
     }
+
+    #[test]
+    fn test_set_fee() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(1)).build());
+        contract.set_fee(0, 30);
+        assert_eq!(contract.get_pool(0).unwrap().fee, 30);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_set_fee_not_owner() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        testing_env!(get_context(accounts(4)).build());
+        contract.set_fee(0, 30);
+    }
+
+    #[test]
+    #[should_panic(expected = "Fee must be less than 10000")]
+    fn test_set_fee_too_high() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        contract.set_fee(0, FEE_DIVISOR);
+    }
+
+    #[test]
+    fn test_swap_with_fee() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(10_000, 10_000);
+        testing_env!(get_context(accounts(1)).build());
+        contract.set_fee(0, 100);
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 1_000.into(), SWAP_MSG.to_string());
+
+        let info = contract.get_pool(0).unwrap();
+        // 1% of the input stays in the pool, so only 990 are swapped
+        assert_eq!(info.token_a_supply, U128::from(11_000));
+        assert_eq!(info.token_b_supply, U128::from(9_100));
+    }
+
+    #[test]
+    fn test_swap_min_amount_out() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            100.into(),
+            r#"{"pool_id":0,"min_amount_out":"90"}"#.to_string(),
+        );
+        assert!(matches!(res, PromiseOrValue::Promise(_)));
+
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128::from(1_100));
+        assert_eq!(info.token_b_supply, U128::from(910));
+    }
+
+    #[test]
+    fn test_swap_min_amount_out_exceeded_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            100.into(),
+            r#"{"pool_id":0,"min_amount_out":"91"}"#.to_string(),
+        );
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128::from(1_000));
+        assert_eq!(info.token_b_supply, U128::from(1_000));
+    }
+
+    #[test]
+    fn test_swap_invalid_msg_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(accounts(4), 100.into(), "invalid".to_string());
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+    }
+
+    #[test]
+    fn test_swap_without_pool_id_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(accounts(4), 100.into(), "".to_string());
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+    }
+
+    #[test]
+    fn test_swap_unknown_pool_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(accounts(4), 100.into(), r#"{"pool_id":1}"#.to_string());
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+    }
+
+    #[test]
+    fn test_deposit_unknown_token_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(5)).build());
+        let res = contract.ft_on_transfer(accounts(4), 100.into(), DEPOSIT_MSG.to_string());
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+        assert!(contract.get_deposits(accounts(4)).is_empty());
+    }
+
+    #[test]
+    fn test_add_pool_deposit() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        let pool_deposit = contract.get_pool_deposit().0;
+        assert_eq!(
+            pool_deposit,
+            u128::from(OBSERVATIONS_STORAGE) * env::storage_byte_cost()
+        );
+
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(pool_deposit + 1)
+            .build());
+        contract.add_pool(accounts(2), accounts(3), None);
+        // the excess deposit is refunded
+        let refund = get_created_receipts()
+            .into_iter()
+            .find(|receipt| receipt.receiver_id == accounts(1))
+            .unwrap();
+        assert!(matches!(
+            refund.actions[0],
+            VmAction::Transfer { deposit: 1 }
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "is less than the required")]
+    fn test_add_pool_without_deposit() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        contract.add_pool(accounts(2), accounts(3), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "is less than the required")]
+    fn test_add_concentrated_pool_without_deposit() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        contract.add_concentrated_pool(accounts(2), accounts(3), 30, 10, 0);
+    }
+
+    #[test]
+    fn test_observations_storage() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut oracle = oracle::Oracle::new(StorageKey::Observations { pool_id: 0 });
+        let initial_storage = env::storage_usage();
+        for timestamp in 0..oracle::MAX_OBSERVATIONS + 10 {
+            oracle.update(PRICE_DIVISOR, PRICE_DIVISOR, timestamp);
+        }
+        assert_eq!(env::storage_usage() - initial_storage, OBSERVATIONS_STORAGE);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_add_pool_not_owner() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        testing_env!(get_context(accounts(4)).build());
+        contract.add_pool(accounts(2), accounts(3), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tokens of a pool must be different")]
+    fn test_add_pool_same_tokens() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        contract.add_pool(accounts(2), accounts(2), None);
+    }
+
+    #[test]
+    fn test_handle_add_pool_stable_swap_too_many_decimals() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        let mut metadata_b = get_metadata("TokenB", "TKNB");
+        metadata_b.decimals = MAX_STABLE_SWAP_DECIMALS + 1;
+        set_metadata_results(&[get_metadata("TokenA", "TKNA"), metadata_b]);
+        let pool_id =
+            contract.handle_add_pool(accounts(2), accounts(3), PoolCurve::StableSwap { amp: 100 });
+        assert_eq!(pool_id, None);
+        assert_eq!(contract.get_contract_info().pool_count, 0);
+        assert_eq!(
+            near_sdk::test_utils::get_logs(),
+            vec![format!(
+                "Pool for tokens {} and {} was not added, because StableSwap pools support at most 24 decimals",
+                accounts(2),
+                accounts(3)
+            )]
+        );
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, accounts(1));
+
+        // other curves do not normalize the amounts
+        let mut metadata_b = get_metadata("TokenB", "TKNB");
+        metadata_b.decimals = MAX_STABLE_SWAP_DECIMALS + 1;
+        set_metadata_results(&[get_metadata("TokenA", "TKNA"), metadata_b]);
+        let pool_id =
+            contract.handle_add_pool(accounts(2), accounts(3), PoolCurve::ConstantProduct);
+        assert_eq!(pool_id, Some(0));
+    }
+
+    #[test]
+    fn test_handle_add_pool_metadata_failed() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(b"{}".to_vec()),
+                PromiseResult::Failed
+            ],
+        );
+        let pool_id =
+            contract.handle_add_pool(accounts(2), accounts(3), PoolCurve::ConstantProduct);
+        assert_eq!(pool_id, None);
+        assert_eq!(contract.get_contract_info().pool_count, 0);
+        assert_eq!(
+            near_sdk::test_utils::get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"orderly","version":"1.0.0","event":"metadata_failed","data":[{"token_id":"charlie","reason":"invalid_metadata"}]}"#.to_string(),
+                r#"EVENT_JSON:{"standard":"orderly","version":"1.0.0","event":"metadata_failed","data":[{"token_id":"danny","reason":"call_failed"}]}"#.to_string(),
+                format!(
+                    "Pool for tokens {} and {} was not added",
+                    accounts(2),
+                    accounts(3)
+                ),
+            ]
+        );
+        // the deposit for the observations is refunded to the owner
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, accounts(1));
+        assert!(matches!(
+            receipts[0].actions[0],
+            VmAction::Transfer { deposit } if deposit == oracle::observations_deposit()
+        ));
+
+        // the tokens were not registered
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(accounts(1), 100.into(), DEPOSIT_MSG.to_string());
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+    }
+
+    #[test]
+    fn test_refresh_metadata() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        set_metadata_results(&[
+            get_metadata("TokenA", "TKNA"),
+            get_metadata("TokenC", "TKNC"),
+        ]);
+        contract.handle_add_concentrated_pool(accounts(2), accounts(5), 30, 10, 0);
+
+        testing_env!(get_context(accounts(1)).build());
+        contract.refresh_metadata(accounts(2));
+
+        set_metadata_results(&[FungibleTokenMetadata {
+            icon: Some("data:image/svg+xml,<svg></svg>".to_string()),
+            ..get_metadata("Token A", "TKNA")
+        }]);
+        assert_eq!(contract.handle_refresh_metadata(accounts(2)), 2);
+        let pool = contract.get_pool(0).unwrap();
+        assert_eq!(pool.token_a_name, "Token A");
+        assert_eq!(pool.token_b_name, "TokenB");
+        let pool = contract.internal_get_concentrated_pool(0);
+        assert_eq!(pool.token_a.metadata.name, "Token A");
+        assert_eq!(
+            pool.token_a.metadata.icon,
+            Some("data:image/svg+xml,<svg></svg>".to_string())
+        );
+    }
+
+    #[test]
+    fn test_refresh_metadata_decimals_changed() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        set_metadata_results(&[FungibleTokenMetadata {
+            decimals: 18,
+            ..get_metadata("Token A", "TKNA")
+        }]);
+        assert_eq!(contract.handle_refresh_metadata(accounts(2)), 0);
+        let pool = contract.get_pool(0).unwrap();
+        assert_eq!(pool.token_a_name, "TokenA");
+        assert_eq!(pool.token_a_decimals, 12);
+    }
+
+    #[test]
+    fn test_refresh_metadata_failed() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        assert_eq!(contract.handle_refresh_metadata(accounts(2)), 0);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"orderly","version":"1.0.0","event":"metadata_failed","data":[{"token_id":"charlie","reason":"call_failed"}]}"#
+            ]
+        );
+        assert_eq!(contract.get_pool(0).unwrap().token_a_name, "TokenA");
+    }
+
+    #[test]
+    #[should_panic(expected = "Token bob does not belong to any liquidity pool")]
+    fn test_refresh_metadata_unknown_token() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(1)).build());
+        contract.refresh_metadata(accounts(1));
+    }
+
+    #[test]
+    fn test_multiple_pools() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_two_pools();
+        assert_eq!(contract.get_contract_info().pool_count, 2);
+        assert_eq!(contract.mt_supply("0".to_string()), Some(U128(1_000)));
+        assert_eq!(contract.mt_supply("1".to_string()), Some(U128(2_000)));
+
+        // token a does not belong to the second pool
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(accounts(4), 100.into(), r#"{"pool_id":1}"#.to_string());
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+
+        testing_env!(get_context(accounts(3)).build());
+        let res = contract.ft_on_transfer(accounts(4), 200.into(), r#"{"pool_id":1}"#.to_string());
+        assert!(matches!(res, PromiseOrValue::Promise(_)));
+
+        let pools = contract.get_pools(0, 10);
+        assert_eq!(pools.len(), 2);
+        assert_eq!(pools[0].token_a_supply, U128(1_000));
+        assert_eq!(pools[0].token_b_supply, U128(1_000));
+        assert_eq!(pools[1].token_a_id, accounts(3));
+        assert_eq!(pools[1].token_a_supply, U128(2_200));
+        assert_eq!(pools[1].token_b_supply, U128(1_819));
+        assert_eq!(contract.get_pools(1, 10).len(), 1);
+        assert!(contract.get_pool(2).is_none());
+    }
+
+    #[test]
+    fn test_swap_route() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_two_pools();
+
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(accounts(4), 100.into(), route_msg(90, 86));
+        assert!(matches!(res, PromiseOrValue::Promise(_)));
+        // the promise gets scheduled once it is dropped
+        drop(res);
+
+        let pools = contract.get_pools(0, 2);
+        assert_eq!(pools[0].token_a_supply, U128(1_100));
+        assert_eq!(pools[0].token_b_supply, U128(910));
+        assert_eq!(pools[1].token_a_supply, U128(2_090));
+        assert_eq!(pools[1].token_b_supply, U128(1_914));
+
+        // only the output of the last hop is sent out
+        let receipts = get_created_receipts();
+        assert!(receipts
+            .iter()
+            .all(|receipt| receipt.receiver_id != accounts(3)));
+        let transfer = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id == accounts(5))
+            .unwrap();
+        match &transfer.actions[0] {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                ..
+            } => {
+                assert_eq!(function_name, "ft_transfer");
+                let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+                assert_eq!(args["receiver_id"], accounts(4).to_string());
+                assert_eq!(args["amount"], "86");
+            }
+            _ => panic!("Expected function call"),
+        }
+    }
+
+    #[test]
+    fn test_swap_route_min_amount_out_exceeded_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_two_pools();
+
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(accounts(4), 100.into(), route_msg(90, 87));
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+        let res = contract.ft_on_transfer(accounts(4), 100.into(), route_msg(91, 0));
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+
+        let pools = contract.get_pools(0, 2);
+        assert_eq!(pools[0].token_a_supply, U128(1_000));
+        assert_eq!(pools[0].token_b_supply, U128(1_000));
+        assert_eq!(pools[1].token_a_supply, U128(2_000));
+        assert_eq!(pools[1].token_b_supply, U128(2_000));
+    }
+
+    #[test]
+    fn test_swap_route_invalid_hop_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_two_pools();
+
+        testing_env!(get_context(accounts(2)).build());
+        let msg = format!(
+            r#"{{"route":[{{"pool_id":0,"token_out":"{}"}},{{"pool_id":0,"token_out":"{}"}}]}}"#,
+            accounts(3),
+            accounts(5)
+        );
+        let res = contract.ft_on_transfer(accounts(4), 100.into(), msg);
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            100.into(),
+            r#"{"pool_id":0,"route":[]}"#.to_string(),
+        );
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_000));
+        assert_eq!(info.token_b_supply, U128(1_000));
+    }
+
+    #[test]
+    fn test_handle_swap_route_failed_should_credit_deposits() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_two_pools();
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100.into(), route_msg(0, 0));
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        let res = contract.handle_swap(accounts(4), accounts(5), 86.into(), 0.into());
+        assert_eq!(res, U128(0));
+        assert_eq!(contract.get_deposits(accounts(4))[&accounts(5)], U128(86));
+
+        // the swaps along the route stay in the pools
+        let pools = contract.get_pools(0, 2);
+        assert_eq!(pools[0].token_a_supply, U128(1_100));
+        assert_eq!(pools[0].token_b_supply, U128(910));
+        assert_eq!(pools[1].token_a_supply, U128(2_090));
+        assert_eq!(pools[1].token_b_supply, U128(2_000 - 86));
+    }
+
+    #[test]
+    fn test_swap_stable_swap() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_stable_pool(100, (12, 1_000_000), (12, 1_000_000));
+        assert_eq!(
+            contract.get_pool(0).unwrap().curve,
+            PoolCurve::StableSwap { amp: 100 }
+        );
+
+        let amount_out = contract.get_return(0, accounts(2), 100_000.into());
+        // the constant product curve would only return 90_909
+        assert_eq!(amount_out, U128(99_949));
+        assert_eq!(contract.get_price_impact(0, accounts(2), 100_000.into()), 6);
+        let amount_in = contract.get_amount_in(0, accounts(3), amount_out);
+        // rounded up in favor of the pool
+        assert_eq!(amount_in, U128(100_001));
+        assert!(contract.get_return(0, accounts(2), amount_in).0 >= amount_out.0);
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100_000.into(), SWAP_MSG.to_string());
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_100_000));
+        assert_eq!(info.token_b_supply, U128(1_000_000 - amount_out.0));
+    }
+
+    #[test]
+    fn test_swap_stable_swap_decimals() {
+        const USDC: u128 = 10u128.pow(6);
+        const DAI: u128 = 10u128.pow(18);
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_stable_pool(100, (6, 1_000_000 * USDC), (18, 1_000_000 * DAI));
+
+        let amount_out = contract.get_return(0, accounts(2), (1_000 * USDC).into());
+        assert!(amount_out.0 > 999_990 * DAI / 1_000 && amount_out.0 < 1_000 * DAI);
+        let amount_out = contract.get_return(0, accounts(3), (1_000 * DAI).into());
+        assert!(amount_out.0 > 999_990 * USDC / 1_000 && amount_out.0 < 1_000 * USDC);
+        let amount_in = contract.get_amount_in(0, accounts(3), (1_000 * DAI).into());
+        assert!(amount_in.0 > 1_000 * USDC && amount_in.0 < 1_000_010 * USDC / 1_000);
+
+        testing_env!(get_context(accounts(3)).build());
+        contract.ft_on_transfer(accounts(4), (1_000 * DAI).into(), SWAP_MSG.to_string());
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_000_000 * USDC - amount_out.0));
+        assert_eq!(info.token_b_supply, U128(1_001_000 * DAI));
+    }
+
+    #[test]
+    #[should_panic(expected = "Amplification must be between 1 and 1000000")]
+    fn test_add_pool_invalid_amplification() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        contract.add_pool(
+            accounts(2),
+            accounts(3),
+            Some(PoolCurve::StableSwap { amp: 0 }),
+        );
+    }
+
+    #[test]
+    fn test_ramp_amplification() {
+        const DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_stable_pool(100, (12, 1_000_000), (12, 1_000_000));
+
+        testing_env!(get_context(accounts(1)).block_timestamp(DAY).build());
+        contract.ramp_amplification(0, 300, (3 * DAY).into());
+        assert_eq!(
+            contract.get_amplification(0),
+            Some(Amplification {
+                initial_amp: 100,
+                target_amp: 300,
+                start_timestamp: DAY.into(),
+                end_timestamp: (3 * DAY).into(),
+            })
+        );
+
+        testing_env!(get_context(accounts(1)).block_timestamp(2 * DAY).build());
+        assert_eq!(
+            contract.get_pool(0).unwrap().curve,
+            PoolCurve::StableSwap { amp: 200 }
+        );
+        // ramping again starts from the current amplification
+        contract.ramp_amplification(0, 50, (4 * DAY).into());
+        testing_env!(get_context(accounts(1)).block_timestamp(3 * DAY).build());
+        assert_eq!(
+            contract.get_pool(0).unwrap().curve,
+            PoolCurve::StableSwap { amp: 125 }
+        );
+        testing_env!(get_context(accounts(1)).block_timestamp(5 * DAY).build());
+        assert_eq!(
+            contract.get_pool(0).unwrap().curve,
+            PoolCurve::StableSwap { amp: 50 }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Amplification can change at most by a factor of 10")]
+    fn test_ramp_amplification_too_large() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_stable_pool(100, (12, 1_000_000), (12, 1_000_000));
+        contract.ramp_amplification(0, 1_001, U64(curve::MIN_RAMP_DURATION));
+    }
+
+    #[test]
+    #[should_panic(expected = "Ramp must last at least")]
+    fn test_ramp_amplification_too_fast() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_stable_pool(100, (12, 1_000_000), (12, 1_000_000));
+        contract.ramp_amplification(0, 200, U64(curve::MIN_RAMP_DURATION - 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Pool 0 is not a StableSwap pool")]
+    fn test_ramp_amplification_constant_product() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        contract.ramp_amplification(0, 200, U64(curve::MIN_RAMP_DURATION));
+    }
+
+    #[test]
+    fn test_swap_weighted() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_weighted_pool((8_000, 1_000_000), (2_000, 1_000_000));
+        contract.set_fee(0, 30);
+        assert_eq!(
+            contract.get_pool(0).unwrap().curve,
+            PoolCurve::Weighted {
+                weight_a: 8_000,
+                weight_b: 2_000
+            }
+        );
+
+        // token a is worth four times as much as token b
+        let amount_out = contract.get_return(0, accounts(2), 1_000.into());
+        assert_eq!(amount_out, U128(3_978));
+        assert_eq!(contract.get_price_impact(0, accounts(2), 1_000.into()), 26);
+        let amount_out = contract.get_return(0, accounts(2), 100_000.into());
+        assert_eq!(
+            contract.get_price_impact(0, accounts(2), 100_000.into()),
+            2_071
+        );
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100_000.into(), SWAP_MSG.to_string());
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_100_000));
+        assert_eq!(info.token_b_supply, U128(1_000_000 - amount_out.0));
+
+        let amount_out = contract.get_return(0, accounts(3), 100_000.into());
+        testing_env!(get_context(accounts(3)).build());
+        contract.ft_on_transfer(accounts(4), 100_000.into(), SWAP_MSG.to_string());
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_100_000 - amount_out.0));
+    }
+
+    #[test]
+    fn test_swap_weighted_exact_output() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_weighted_pool((2_000, 1_000_000), (8_000, 1_000_000));
+        contract.set_fee(0, 30);
+
+        let amount_in = contract.get_amount_in(0, accounts(3), 20_000.into());
+        assert!(contract.get_return(0, accounts(2), amount_in).0 >= 20_000);
+        assert!(
+            contract
+                .get_return(0, accounts(2), (amount_in.0 - 1).into())
+                .0
+                < 20_000
+        );
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(
+            accounts(4),
+            100_000.into(),
+            r#"{"pool_id":0,"amount_out":"20000"}"#.to_string(),
+        );
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_000_000 + amount_in.0));
+        assert_eq!(info.token_b_supply, U128(980_000));
+    }
+
+    #[test]
+    fn test_swap_weighted_exact_output_too_large_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        const SUPPLY: u128 = 10u128.pow(30);
+        let mut contract = setup_weighted_pool((200, SUPPLY), (9_800, SUPPLY));
+
+        // half of token b needs 2^49 times the supply of token a
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            100.into(),
+            format!(r#"{{"pool_id":0,"amount_out":"{}"}}"#, SUPPLY / 2),
+        );
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+        assert_eq!(
+            get_logs(),
+            vec!["Not enough liquidity available for swap".to_string()]
+        );
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(SUPPLY));
+        assert_eq!(info.token_b_supply, U128(SUPPLY));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough liquidity available for swap")]
+    fn test_get_amount_in_weighted_too_large() {
+        testing_env!(get_context(accounts(1)).build());
+        const SUPPLY: u128 = 10u128.pow(30);
+        let contract = setup_weighted_pool((200, SUPPLY), (9_800, SUPPLY));
+        contract.get_amount_in(0, accounts(3), (SUPPLY / 2).into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Weights must add up to 10000")]
+    fn test_add_pool_invalid_weights() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        contract.add_pool(
+            accounts(2),
+            accounts(3),
+            Some(PoolCurve::Weighted {
+                weight_a: 80,
+                weight_b: 20,
+            }),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Weights must be at least 200")]
+    fn test_add_pool_weight_too_small() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        contract.add_pool(
+            accounts(2),
+            accounts(3),
+            Some(PoolCurve::Weighted {
+                weight_a: 9_900,
+                weight_b: 100,
+            }),
+        );
+    }
+
+    /// Sets up a concentrated pool with a fee of 0.3% at price 1, where accounts(1) opens
+    /// a position between ticks -1000 and 1000.
+    fn setup_concentrated_pool() -> OrderlyContract {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        set_metadata_results(&[
+            get_metadata("TokenA", "TKNA"),
+            get_metadata("TokenB", "TKNB"),
+        ]);
+        contract.handle_add_concentrated_pool(accounts(2), accounts(3), 30, 10, 0);
+        open_position(
+            &mut contract,
+            accounts(1),
+            -1_000,
+            1_000,
+            1_000_000,
+            1_000_000,
+        );
+        contract
+    }
+
+    fn open_position(
+        contract: &mut OrderlyContract,
+        account_id: AccountId,
+        lower_tick: i32,
+        upper_tick: i32,
+        amount_a: u128,
+        amount_b: u128,
+    ) -> U64 {
+        deposit(contract, account_id.clone(), accounts(2), amount_a);
+        deposit(contract, account_id.clone(), accounts(3), amount_b);
+        testing_env!(get_context(account_id).build());
+        contract.open_position(
+            0,
+            lower_tick,
+            upper_tick,
+            amount_a.into(),
+            amount_b.into(),
+            0.into(),
+        )
+    }
+
+    fn concentrated_swap(
+        contract: &mut OrderlyContract,
+        token_in: AccountId,
+        amount: u128,
+    ) -> U128 {
+        testing_env!(get_context(token_in).build());
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            amount.into(),
+            r#"{"action":"concentrated_swap","pool_id":0}"#.to_string(),
+        );
+        match res {
+            PromiseOrValue::Value(amount_unused) => amount_unused,
+            PromiseOrValue::Promise(_) => panic!("Expected the unused amount"),
+        }
+    }
+
+    #[test]
+    fn test_open_position() {
+        let contract = setup_concentrated_pool();
+
+        let positions = contract.get_positions(accounts(1));
+        assert_eq!(positions.len(), 1);
+        let position = &positions[0];
+        assert_eq!(position.position_id, U64(0));
+        assert_eq!((position.lower_tick, position.upper_tick), (-1_000, 1_000));
+        assert_eq!(position.liquidity, U128(20_505_166));
+        assert_eq!(position.amount_a, position.amount_b);
+        assert_eq!(contract.get_position(U64(0)).as_ref(), Some(position));
+
+        let info = contract.get_concentrated_pool(0).unwrap();
+        assert_eq!(info.liquidity, position.liquidity);
+        assert_eq!(info.tick, 0);
+        // the unused remainder stays in the deposits
+        let deposits = contract.get_deposits(accounts(1));
+        let deposit = deposits.get(&accounts(2)).map_or(0, |deposit| deposit.0);
+        assert_eq!(deposit, 1_000_000 - info.token_a_supply.0);
+        assert!(deposit < 10);
+    }
+
+    #[test]
+    fn test_open_position_out_of_range() {
+        let mut contract = setup_concentrated_pool();
+
+        // a range above the current price only consists of token a
+        deposit(&mut contract, accounts(4), accounts(2), 1_000);
+        testing_env!(get_context(accounts(4)).build());
+        let position_id = contract.open_position(0, 100, 200, 1_000.into(), 0.into(), 0.into());
+        let position = contract.get_position(position_id).unwrap();
+        assert_eq!(position.amount_b, U128(0));
+        assert!(position.amount_a.0 > 990);
+        // and does not add liquidity at the current price
+        assert_eq!(
+            contract.get_concentrated_pool(0).unwrap().liquidity,
+            U128(20_505_166)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Ticks must be multiples of the tick spacing 10")]
+    fn test_open_position_invalid_tick() {
+        let mut contract = setup_concentrated_pool();
+        open_position(&mut contract, accounts(1), -1_005, 1_000, 1_000, 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Lower tick must be less than upper tick")]
+    fn test_open_position_invalid_range() {
+        let mut contract = setup_concentrated_pool();
+        open_position(&mut contract, accounts(1), 1_000, 1_000, 1_000, 1_000);
+    }
+
+    #[test]
+    fn test_max_liquidity_per_tick() {
+        assert_eq!(concentrated::max_liquidity_per_tick(1), u128::MAX / 887_273);
+        // the ticks from -442_368 to 442_368
+        assert_eq!(
+            concentrated::max_liquidity_per_tick(concentrated::MAX_TICK_SPACING),
+            u128::MAX / 55
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Liquidity of tick -1000 exceeds the maximum of")]
+    fn test_open_position_exceeds_max_liquidity_per_tick() {
+        let mut contract = setup_concentrated_pool();
+        // the liquidity is about 20 times the amounts
+        let amount = u128::MAX / 88_728 / 10;
+        open_position(&mut contract, accounts(4), -1_000, 1_000, amount, amount);
+    }
+
+    #[test]
+    fn test_concentrated_swap() {
+        let mut contract = setup_concentrated_pool();
+
+        let amount_out = contract.get_concentrated_return(0, accounts(2), 100_000.into());
+        // the constant product curve with the same reserves would only return 90_661
+        assert!(amount_out.0 > 99_000 && amount_out.0 < 99_700);
+        let amount_unused = concentrated_swap(&mut contract, accounts(2), 100_000);
+        assert_eq!(amount_unused, U128(0));
+
+        let info = contract.get_concentrated_pool(0).unwrap();
+        assert!(info.tick < 0);
+        let position = contract.get_position(U64(0)).unwrap();
+        assert_eq!(position.fees_a, U128(299));
+        assert_eq!(position.fees_b, U128(0));
+        assert_eq!(
+            info.token_b_supply.0,
+            contract.get_positions(accounts(1))[0].amount_b.0 + 1
+        );
+
+        let receipts = get_created_receipts();
+        let transfer = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id == accounts(3))
+            .unwrap();
+        match &transfer.actions[0] {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                ..
+            } => {
+                assert_eq!(function_name, "ft_transfer");
+                let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+                assert_eq!(args["receiver_id"], accounts(4).to_string());
+                assert_eq!(args["amount"], amount_out.0.to_string());
+            }
+            _ => panic!("Expected function call"),
+        }
+    }
+
+    #[test]
+    fn test_concentrated_swap_crosses_ticks() {
+        let mut contract = setup_concentrated_pool();
+        open_position(&mut contract, accounts(4), -100, 100, 200_000, 200_000);
+        let liquidity = contract.get_concentrated_pool(0).unwrap().liquidity.0;
+        assert!(liquidity > 20_505_166);
+
+        // crosses tick -100, where the narrow position ends
+        concentrated_swap(&mut contract, accounts(2), 600_000);
+        let info = contract.get_concentrated_pool(0).unwrap();
+        assert!(info.tick < -100);
+        assert_eq!(info.liquidity, U128(20_505_166));
+
+        // the narrow position only earned fees until the price left its range
+        let wide = contract.get_position(U64(0)).unwrap();
+        let narrow = contract.get_position(U64(1)).unwrap();
+        assert!(wide.fees_a.0 + narrow.fees_a.0 <= 1_800);
+        assert!(wide.fees_a.0 + narrow.fees_a.0 >= 1_790);
+        assert!(narrow.fees_a.0 > 0 && narrow.fees_a.0 < wide.fees_a.0);
+        // the narrow position only consists of token a
+        assert_eq!(narrow.amount_b, U128(0));
+
+        // crosses back
+        concentrated_swap(&mut contract, accounts(3), 1_200_000);
+        let info = contract.get_concentrated_pool(0).unwrap();
+        assert!(info.tick > 100);
+        assert_eq!(info.liquidity, U128(20_505_166));
+        let narrow = contract.get_position(U64(1)).unwrap();
+        assert!(narrow.fees_b.0 > 0);
+        assert_eq!(narrow.amount_a, U128(0));
+    }
+
+    #[test]
+    fn test_concentrated_swap_not_enough_liquidity_should_refund_unused() {
+        let mut contract = setup_concentrated_pool();
+
+        let amount_unused = concentrated_swap(&mut contract, accounts(3), 10_000_000);
+        assert!(amount_unused.0 > 8_900_000);
+        let info = contract.get_concentrated_pool(0).unwrap();
+        assert_eq!(info.liquidity, U128(0));
+        assert_eq!(contract.get_position(U64(0)).unwrap().amount_a, U128(0));
+
+        // no liquidity is left above the current price
+        let amount_unused = concentrated_swap(&mut contract, accounts(3), 1_000);
+        assert_eq!(amount_unused, U128(1_000));
+    }
+
+    #[test]
+    fn test_concentrated_swap_min_amount_out_should_refund() {
+        let mut contract = setup_concentrated_pool();
+
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            1_000.into(),
+            r#"{"action":"concentrated_swap","pool_id":0,"min_amount_out":"1000"}"#.to_string(),
+        );
+        assert!(matches!(res, PromiseOrValue::Value(U128(1_000))));
+        assert_eq!(contract.get_concentrated_pool(0).unwrap().tick, 0);
+    }
+
+    #[test]
+    fn test_collect_fees() {
+        let mut contract = setup_concentrated_pool();
+        concentrated_swap(&mut contract, accounts(2), 100_000);
+        let deposits = contract.get_deposits(accounts(1));
+
+        testing_env!(get_context(accounts(1)).build());
+        let (fees_a, fees_b) = contract.collect_fees(U64(0));
+        assert_eq!((fees_a, fees_b), (U128(299), U128(0)));
+        assert_eq!(
+            contract.get_deposits(accounts(1))[&accounts(2)].0,
+            deposits.get(&accounts(2)).map_or(0, |deposit| deposit.0) + 299
+        );
+        assert_eq!(contract.get_position(U64(0)).unwrap().fees_a, U128(0));
+        assert_eq!(contract.collect_fees(U64(0)), (U128(0), U128(0)));
+    }
+
+    #[test]
+    fn test_decrease_liquidity() {
+        let mut contract = setup_concentrated_pool();
+        let supply_a = contract.get_concentrated_pool(0).unwrap().token_a_supply.0;
+
+        testing_env!(get_context(accounts(1)).build());
+        let (amount_a, amount_b) =
+            contract.decrease_liquidity(U64(0), 10_252_583.into(), 0.into(), 0.into());
+        assert!(amount_a.0 < supply_a / 2 && amount_a.0 > supply_a / 2 - 10);
+        assert_eq!(amount_a, amount_b);
+        assert_eq!(
+            contract.get_position(U64(0)).unwrap().liquidity,
+            U128(10_252_583)
+        );
+
+        contract.decrease_liquidity(U64(0), 10_252_583.into(), 0.into(), 0.into());
+        assert_eq!(contract.get_position(U64(0)), None);
+        assert!(contract.get_positions(accounts(1)).is_empty());
+        let info = contract.get_concentrated_pool(0).unwrap();
+        assert_eq!(info.liquidity, U128(0));
+        // the rounding stays in the pool
+        assert!(info.token_a_supply.0 < 5);
+        let deposits = contract.get_deposits(accounts(1));
+        assert_eq!(deposits[&accounts(2)].0 + info.token_a_supply.0, 1_000_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Position 0 does not belong to eugene")]
+    fn test_decrease_liquidity_not_owner() {
+        let mut contract = setup_concentrated_pool();
+        testing_env!(get_context(accounts(4)).build());
+        contract.decrease_liquidity(U64(0), 1.into(), 0.into(), 0.into());
+    }
+
+    #[test]
+    fn test_mt_transfer() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        storage_deposit(&mut contract, accounts(4));
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.mt_transfer(accounts(4), "0".to_string(), 400.into(), None, None);
+        assert_eq!(
+            contract.mt_balance_of(accounts(1), "0".to_string()),
+            U128(600)
+        );
+        assert_eq!(
+            contract.mt_balance_of(accounts(4), "0".to_string()),
+            U128(400)
+        );
+        assert_eq!(contract.mt_supply("0".to_string()), Some(U128(1_000)));
+        assert_eq!(
+            get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"nep245","version":"1.0.0","event":"mt_transfer","data":[{{"old_owner_id":"{}","new_owner_id":"{}","token_ids":["0"],"amounts":["400"]}}]}}"#,
+                accounts(1),
+                accounts(4)
+            )]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Account eugene is not registered with storage_deposit")]
+    fn test_mt_transfer_unregistered_receiver() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.mt_transfer(accounts(4), "0".to_string(), 400.into(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid token id pool")]
+    fn test_mt_transfer_invalid_token_id() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        storage_deposit(&mut contract, accounts(4));
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.mt_transfer(accounts(4), "pool".to_string(), 400.into(), None, None);
+    }
+
+    #[test]
+    fn test_mt_transfer_call() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_two_pools();
+        storage_deposit(&mut contract, accounts(4));
+
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        let _ = contract.mt_batch_transfer_call(
+            accounts(4),
+            vec!["0".to_string(), "1".to_string()],
+            vec![400.into(), 1_000.into()],
+            None,
+            None,
+            String::new(),
+        );
+        let receipts = get_created_receipts();
+        assert!(matches!(
+            &receipts[0].actions[0],
+            VmAction::FunctionCall { function_name, .. } if function_name == "mt_on_transfer"
+        ));
+
+        // the receiver returns part of the shares of pool 1
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(br#"["0","300"]"#.to_vec())],
+        );
+        let used = contract.mt_resolve_transfer(
+            vec![accounts(1)],
+            accounts(4),
+            vec!["0".to_string(), "1".to_string()],
+            vec![400.into(), 1_000.into()],
+            None,
+        );
+        assert_eq!(used, vec![U128(400), U128(700)]);
+        assert_eq!(
+            contract.mt_batch_balance_of(accounts(4), vec!["0".to_string(), "1".to_string()]),
+            vec![U128(400), U128(700)]
+        );
+        assert_eq!(
+            contract.mt_balance_of(accounts(1), "1".to_string()),
+            U128(1_300)
+        );
+    }
+
+    #[test]
+    fn test_mt_resolve_transfer_failed() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        storage_deposit(&mut contract, accounts(4));
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.mt_transfer(accounts(4), "0".to_string(), 400.into(), None, None);
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        let used = contract.mt_resolve_transfer(
+            vec![accounts(1)],
+            accounts(4),
+            vec!["0".to_string()],
+            vec![400.into()],
+            None,
+        );
+        assert_eq!(used, vec![U128(0)]);
+        assert_eq!(
+            contract.mt_balance_of(accounts(4), "0".to_string()),
+            U128(0)
+        );
+        assert_eq!(
+            contract.mt_balance_of(accounts(1), "0".to_string()),
+            U128(1_000)
+        );
+        assert_eq!(
+            get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"nep245","version":"1.0.0","event":"mt_transfer","data":[{{"old_owner_id":"{}","new_owner_id":"{}","token_ids":["0"],"amounts":["400"],"memo":"refund"}}]}}"#,
+                accounts(4),
+                accounts(1)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_mt_token() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = setup_contract(1_000, 1_000);
+        assert_eq!(
+            contract.mt_token(vec!["0".to_string(), "1".to_string()]),
+            vec![
+                Some(Token {
+                    token_id: "0".to_string(),
+                    owner_id: None
+                }),
+                None
+            ]
+        );
+        assert_eq!(contract.mt_supply("1".to_string()), None);
+    }
+
+    #[test]
+    fn test_handle_swap() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100.into(), SWAP_MSG.to_string());
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        let res = contract.handle_swap(accounts(4), accounts(3), 90.into(), 0.into());
+        assert_eq!(res, U128(0));
+        assert!(contract.get_deposits(accounts(4)).is_empty());
+
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128::from(1_100));
+        assert_eq!(info.token_b_supply, U128::from(910));
+    }
+
+    #[test]
+    fn test_handle_swap_failed_should_credit_deposits() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100.into(), SWAP_MSG.to_string());
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        let res = contract.handle_swap(accounts(4), accounts(3), 90.into(), 0.into());
+        assert_eq!(res, U128(0));
+        assert_eq!(contract.get_deposits(accounts(4))[&accounts(3)], U128(90));
+        assert_eq!(
+            get_logs(),
+            vec![format!(
+                "Transfer of 90 of token {} failed. Crediting 90 to deposits of {}",
+                accounts(3),
+                accounts(4)
+            )]
+        );
+
+        // the reserves are not restored, so later swaps cannot make them underflow
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128::from(1_100));
+        assert_eq!(info.token_b_supply, U128::from(910));
+    }
+
+    #[test]
+    fn test_add_liquidity_initial() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = setup_contract(1_000, 4_000);
+        assert!(get_logs().contains(&format!(
+            r#"EVENT_JSON:{{"standard":"nep245","version":"1.0.0","event":"mt_mint","data":[{{"owner_id":"{}","token_ids":["0"],"amounts":["2000"]}}]}}"#,
+            accounts(1)
+        )));
+        // sqrt(1_000 * 4_000) = 2_000
+        assert_eq!(contract.mt_supply("0".to_string()), Some(U128(2_000)));
+        assert_eq!(
+            contract.mt_balance_of(accounts(1), "0".to_string()),
+            U128(2_000)
+        );
+        assert!(contract.get_deposits(accounts(1)).is_empty());
+    }
+
+    #[test]
+    fn test_add_liquidity_ratio() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        deposit(&mut contract, accounts(4), accounts(2), 500);
+        deposit(&mut contract, accounts(4), accounts(3), 1_000);
+        testing_env!(get_context(accounts(4)).build());
+        let shares = contract.add_liquidity(0, 500.into(), 1_000.into(), 500.into());
+        assert_eq!(shares, U128(500));
+        assert_eq!(contract.mt_supply("0".to_string()), Some(U128(1_500)));
+        assert_eq!(
+            contract.mt_balance_of(accounts(4), "0".to_string()),
+            U128(500)
+        );
+
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128::from(1_500));
+        assert_eq!(info.token_b_supply, U128::from(1_500));
+        let deposits = contract.get_deposits(accounts(4));
+        assert_eq!(deposits.len(), 1);
+        assert_eq!(deposits[&accounts(3)], U128(500));
+    }
+
+    #[test]
+    #[should_panic(expected = "Slippage exceeded: 500 shares are less than the minimum of 501")]
+    fn test_add_liquidity_min_shares() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        deposit(&mut contract, accounts(4), accounts(2), 500);
+        deposit(&mut contract, accounts(4), accounts(3), 500);
+        testing_env!(get_context(accounts(4)).build());
+        contract.add_liquidity(0, 500.into(), 500.into(), 501.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough deposited of token")]
+    fn test_add_liquidity_not_enough_deposited() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        deposit(&mut contract, accounts(4), accounts(2), 500);
+        testing_env!(get_context(accounts(4)).build());
+        contract.add_liquidity(0, 500.into(), 500.into(), 0.into());
+    }
+
+    #[test]
+    fn test_remove_liquidity() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 4_000);
+
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.remove_liquidity(0, 500.into(), 250.into(), 1_000.into());
+        assert_eq!(
+            get_logs()[0],
+            format!(
+                r#"EVENT_JSON:{{"standard":"nep245","version":"1.0.0","event":"mt_burn","data":[{{"owner_id":"{}","token_ids":["0"],"amounts":["500"]}}]}}"#,
+                accounts(1)
+            )
+        );
+        assert_eq!(contract.mt_supply("0".to_string()), Some(U128(1_500)));
+        assert_eq!(
+            contract.mt_balance_of(accounts(1), "0".to_string()),
+            U128(1_500)
+        );
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128::from(750));
+        assert_eq!(info.token_b_supply, U128::from(3_000));
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.handle_withdraw(accounts(1), accounts(3), 1_000.into());
+        assert_eq!(
+            contract.get_deposits(accounts(1))[&accounts(3)],
+            U128(1_000)
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Slippage exceeded: 250 of token charlie is less than the minimum of 251"
+    )]
+    fn test_remove_liquidity_min_amount() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 4_000);
+
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.remove_liquidity(0, 500.into(), 251.into(), 0.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Shares must be greater than zero")]
+    fn test_remove_liquidity_zero_shares() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 4_000);
+
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.remove_liquidity(0, 0.into(), 0.into(), 0.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Pool 0 has no liquidity")]
+    fn test_remove_liquidity_empty_pool() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        set_metadata_results(&[
+            get_metadata("TokenA", "TKNA"),
+            get_metadata("TokenB", "TKNB"),
+        ]);
+        contract.handle_add_pool(accounts(2), accounts(3), PoolCurve::ConstantProduct);
+
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.remove_liquidity(0, 1.into(), 0.into(), 0.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough shares of pool 0, bob has 2000")]
+    fn test_remove_liquidity_not_enough_shares() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 4_000);
+
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.remove_liquidity(0, 2_001.into(), 0.into(), 0.into());
+    }
+
+    #[test]
+    fn test_withdraw() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        deposit(&mut contract, accounts(4), accounts(2), 500);
+
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.withdraw(accounts(2), 200.into());
+        assert_eq!(contract.get_deposits(accounts(4))[&accounts(2)], U128(300));
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.handle_withdraw(accounts(4), accounts(2), 200.into());
+        assert_eq!(contract.get_deposits(accounts(4))[&accounts(2)], U128(500));
+    }
+
+    #[test]
+    fn test_storage_deposit() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        let min_balance = contract.storage_balance_bounds().min.0;
+        assert_eq!(
+            min_balance,
+            u128::from(MIN_STORAGE_USAGE) * env::storage_byte_cost()
+        );
+
+        testing_env!(get_context(accounts(4))
+            .attached_deposit(2 * min_balance)
+            .build());
+        contract.storage_deposit(None, Some(true));
+        let balance = contract.storage_balance_of(accounts(4)).unwrap();
+        assert_eq!(balance.total.0, min_balance);
+        // the registration itself uses storage
+        assert!(balance.available.0 < min_balance);
+
+        // the excess deposit is refunded
+        let receipts = get_created_receipts();
+        assert!(matches!(
+            receipts[0].actions[0],
+            VmAction::Transfer { deposit } if deposit == min_balance
+        ));
+
+        deposit(&mut contract, accounts(4), accounts(2), 500);
+        let available = contract
+            .storage_balance_of(accounts(4))
+            .unwrap()
+            .available
+            .0;
+        assert!(available < balance.available.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the minimum storage balance")]
+    fn test_storage_deposit_less_than_minimum() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.storage_deposit(None, None);
+    }
+
+    #[test]
+    fn test_deposit_unregistered_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(accounts(4), 100.into(), DEPOSIT_MSG.to_string());
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+        assert!(contract.get_deposits(accounts(4)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Storage balance of eugene is not enough")]
+    fn test_deposit_storage_not_covered() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        deposit(&mut contract, accounts(4), accounts(2), 500);
+
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.storage_withdraw(None);
+        assert_eq!(
+            contract.storage_balance_of(accounts(4)).unwrap().available,
+            U128(0)
+        );
+        deposit(&mut contract, accounts(4), accounts(3), 500);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token eugene does not belong to any liquidity pool")]
+    fn test_internal_deposit_unlisted_token() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        contract.internal_deposit(&accounts(1), &accounts(4), 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount is greater than the available storage balance")]
+    fn test_storage_withdraw_too_much() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.storage_withdraw(Some(STORAGE_DEPOSIT.into()));
+    }
+
+    #[test]
+    fn test_storage_unregister() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        let available = contract.storage_balance_of(accounts(1)).unwrap().available;
+
+        // removing all liquidity releases the storage of the shares
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.remove_liquidity(0, 1_000.into(), 0.into(), 0.into());
+        assert!(
+            contract
+                .storage_balance_of(accounts(1))
+                .unwrap()
+                .available
+                .0
+                > available.0
+        );
+
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        assert!(contract.storage_unregister(None));
+        assert!(contract.storage_balance_of(accounts(1)).is_none());
+        assert!(!contract.storage_unregister(None));
+    }
+
+    #[test]
+    #[should_panic(expected = "Account bob still has deposits, shares, positions or orders")]
+    fn test_storage_unregister_with_shares() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account eugene still has deposits, shares, positions or orders")]
+    fn test_storage_unregister_with_deposits() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        deposit(&mut contract, accounts(4), accounts(2), 100);
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn test_storage_unregister_after_withdraw_and_mt_transfer() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        deposit(&mut contract, accounts(4), accounts(2), 100);
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.withdraw(accounts(2), 100.into());
+        assert!(contract.storage_unregister(None));
+
+        storage_deposit(&mut contract, accounts(4));
+        testing_env!(get_context(accounts(1)).attached_deposit(1).build());
+        contract.mt_transfer(accounts(4), "0".to_string(), 1_000.into(), None, None);
+        assert!(contract.storage_unregister(None));
+    }
+
+    #[test]
+    fn test_share_metadata() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = setup_contract(1_000, 1_000);
+        assert_eq!(contract.mt_metadata_contract().spec, "mt-1.0.0");
+        let metadata = contract
+            .mt_metadata_base_by_token_id(vec!["0".to_string()])
+            .remove(0)
+            .unwrap();
+        assert_eq!(metadata.name, "Orderly LP TKNA-TKNB");
+        assert_eq!(metadata.id, "0");
+        assert_eq!(metadata.symbol.unwrap(), "TKNA-TKNB");
+        assert_eq!(metadata.decimals.unwrap(), "12");
+    }
+
+    #[test]
+    fn test_get_return() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(10_000, 10_000);
+        contract.set_fee(0, 100);
+
+        let amount_out = contract.get_return(0, accounts(2), 1_000.into());
+        assert_eq!(amount_out, U128(900));
+        assert_eq!(contract.get_price_impact(0, accounts(2), 1_000.into()), 910);
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 1_000.into(), SWAP_MSG.to_string());
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_b_supply, U128(10_000 - amount_out.0));
+    }
+
+    #[test]
+    fn test_get_amount_in() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(10_000, 10_000);
+        contract.set_fee(0, 100);
+
+        let amount_in = contract.get_amount_in(0, accounts(3), 900.into());
+        assert_eq!(amount_in, U128(1_000));
+        assert_eq!(contract.get_return(0, accounts(2), amount_in), U128(900));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token eugene does not belong to liquidity pool")]
+    fn test_get_return_invalid_token() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = setup_contract(10_000, 10_000);
+        contract.get_return(0, accounts(4), 1_000.into());
+    }
+
+    #[test]
+    fn test_swap_exact_output() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(10_000, 10_000);
+        contract.set_fee(0, 100);
+
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            1_500.into(),
+            r#"{"pool_id":0,"amount_out":"900"}"#.to_string(),
+        );
+        assert!(matches!(res, PromiseOrValue::Promise(_)));
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(11_000));
+        assert_eq!(info.token_b_supply, U128(9_100));
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])],
+        );
+        let res = contract.handle_swap(accounts(4), accounts(3), 900.into(), 500.into());
+        assert_eq!(res, U128(500));
+    }
+
+    #[test]
+    fn test_swap_exact_output_not_enough_input_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(10_000, 10_000);
+        contract.set_fee(0, 100);
+
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            999.into(),
+            r#"{"pool_id":0,"amount_out":"900"}"#.to_string(),
+        );
+        assert!(matches!(res, PromiseOrValue::Value(U128(999))));
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(10_000));
+        assert_eq!(info.token_b_supply, U128(10_000));
+    }
+
+    #[test]
+    fn test_handle_swap_exact_output_failed_should_credit_deposits() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(10_000, 10_000);
+        contract.set_fee(0, 100);
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(
+            accounts(4),
+            1_500.into(),
+            r#"{"pool_id":0,"amount_out":"900"}"#.to_string(),
+        );
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        let res = contract.handle_swap(accounts(4), accounts(3), 900.into(), 500.into());
+        // only the unused input is refunded
+        assert_eq!(res, U128(500));
+        assert_eq!(contract.get_deposits(accounts(4))[&accounts(3)], U128(900));
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_b_supply, U128(9_100));
+    }
+
+    #[test]
+    fn test_swap_deadline() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(2)).block_timestamp(1_000).build());
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            100.into(),
+            r#"{"pool_id":0,"deadline":"1000"}"#.to_string(),
+        );
+        assert!(matches!(res, PromiseOrValue::Promise(_)));
+    }
+
+    #[test]
+    fn test_swap_deadline_exceeded_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(2)).block_timestamp(1_001).build());
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            100.into(),
+            r#"{"pool_id":0,"deadline":"1000"}"#.to_string(),
+        );
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_000));
+        assert_eq!(info.token_b_supply, U128(1_000));
+    }
+
+    #[test]
+    fn test_swap_receiver() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(
+            accounts(4),
+            100.into(),
+            format!(r#"{{"pool_id":0,"receiver_id":"{}"}}"#, accounts(5)),
+        );
+
+        let receipts = get_created_receipts();
+        let transfer = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id == accounts(3))
+            .unwrap();
+        match &transfer.actions[0] {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                ..
+            } => {
+                assert_eq!(function_name, "ft_transfer");
+                let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+                assert_eq!(args["receiver_id"], accounts(5).to_string());
+                assert_eq!(args["amount"], "90");
+            }
+            _ => panic!("Expected function call"),
+        }
+    }
+
+    #[test]
+    fn test_swap_then_call() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(
+            accounts(4),
+            100.into(),
+            format!(
+                r#"{{"pool_id":0,"then_call":{{"receiver_id":"{}","msg":"hello"}}}}"#,
+                accounts(5)
+            ),
+        );
+
+        let receipts = get_created_receipts();
+        let transfer = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id == accounts(3))
+            .unwrap();
+        match &transfer.actions[0] {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                ..
+            } => {
+                assert_eq!(function_name, "ft_transfer_call");
+                let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+                assert_eq!(args["receiver_id"], accounts(5).to_string());
+                assert_eq!(args["amount"], "90");
+                assert_eq!(args["msg"], "hello");
+            }
+            _ => panic!("Expected function call"),
+        }
+    }
+
+    #[test]
+    fn test_handle_swap_then_call_partially_used() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100.into(), SWAP_MSG.to_string());
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"\"60\"".to_vec())],
+        );
+        let res = contract.handle_swap(accounts(4), accounts(3), 90.into(), 0.into());
+        assert_eq!(res, U128(0));
+        assert_eq!(contract.get_deposits(accounts(4))[&accounts(3)], U128(30));
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_100));
+        assert_eq!(info.token_b_supply, U128(910));
+    }
+
+    #[test]
+    fn test_handle_swap_then_call_unused_should_credit_deposits() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100.into(), SWAP_MSG.to_string());
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"\"0\"".to_vec())],
+        );
+        let res = contract.handle_swap(accounts(4), accounts(3), 90.into(), 0.into());
+        assert_eq!(res, U128(0));
+        assert_eq!(contract.get_deposits(accounts(4))[&accounts(3)], U128(90));
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_100));
+        assert_eq!(info.token_b_supply, U128(910));
+    }
+
+    #[test]
+    fn test_swap_large_reserves() {
+        const ONE_NEAR: u128 = 10u128.pow(24);
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000_000 * ONE_NEAR, 1_000_000 * ONE_NEAR);
+        assert_eq!(
+            contract.mt_supply("0".to_string()),
+            Some(U128(1_000_000 * ONE_NEAR))
+        );
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), ONE_NEAR.into(), SWAP_MSG.to_string());
+
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_000_001 * ONE_NEAR));
+        assert_eq!(
+            info.token_b_supply,
+            U128(999_999_000_000_999_999_000_001_000_000)
+        );
+    }
+
+    /// Places an order of `account_id` selling `amount` of `token` in pool 0.
+    fn place_order(
+        contract: &mut OrderlyContract,
+        account_id: AccountId,
+        token: AccountId,
+        amount: u128,
+        price: u128,
+    ) -> PromiseOrValue<U128> {
+        if contract.storage_balance_of(account_id.clone()).is_none() {
+            storage_deposit(contract, account_id.clone());
+        }
+        testing_env!(get_context(token).build());
+        contract.ft_on_transfer(
+            account_id,
+            amount.into(),
+            format!(r#"{{"limit_order":{{"pool_id":0,"price":"{}"}}}}"#, price),
+        )
+    }
+
+    #[test]
+    fn test_place_order() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        let res = place_order(&mut contract, accounts(4), accounts(3), 100, PRICE_DIVISOR);
+        assert!(matches!(res, PromiseOrValue::Value(U128(0))));
+        place_order(&mut contract, accounts(5), accounts(3), 200, PRICE_DIVISOR);
+        place_order(
+            &mut contract,
+            accounts(4),
+            accounts(3),
+            300,
+            PRICE_DIVISOR / 2,
+        );
+
+        let orders = contract.get_orders(accounts(4));
+        assert_eq!(orders.len(), 2);
+        assert_eq!(
+            orders[0],
+            OrderInfo {
+                order_id: U64(0),
+                owner_id: accounts(4),
+                pool_id: 0,
+                token_id: accounts(3),
+                price: U128(PRICE_DIVISOR),
+                amount: U128(100),
+                filled: U128(0),
+            }
+        );
+        assert_eq!(
+            contract.get_price_levels(0, accounts(3), 10),
+            vec![
+                PriceLevel {
+                    price: U128(PRICE_DIVISOR / 2),
+                    amount: U128(300),
+                    order_count: 1,
+                },
+                PriceLevel {
+                    price: U128(PRICE_DIVISOR),
+                    amount: U128(300),
+                    order_count: 2,
+                },
+            ]
+        );
+        let orders = contract.get_orders_at_price(0, accounts(3), U128(PRICE_DIVISOR));
+        assert_eq!(
+            orders
+                .iter()
+                .map(|order| order.order_id)
+                .collect::<Vec<_>>(),
+            vec![U64(0), U64(1)]
+        );
+        assert!(contract.get_price_levels(0, accounts(2), 10).is_empty());
+        // orders do not change the reserves
+        assert_eq!(contract.get_pool(0).unwrap().token_b_supply, U128(1_000));
+    }
+
+    #[test]
+    fn test_place_order_invalid_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        let res = place_order(&mut contract, accounts(4), accounts(3), 100, 0);
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+
+        testing_env!(get_context(accounts(3)).build());
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            100.into(),
+            r#"{"limit_order":{"pool_id":1,"price":"1"}}"#.to_string(),
+        );
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+        assert!(contract.get_orders(accounts(4)).is_empty());
+    }
+
+    #[test]
+    fn test_place_order_too_small_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        let res = place_order(
+            &mut contract,
+            accounts(4),
+            accounts(3),
+            MIN_ORDER_AMOUNT - 1,
+            PRICE_DIVISOR,
+        );
+        assert!(
+            matches!(res, PromiseOrValue::Value(U128(amount)) if amount == MIN_ORDER_AMOUNT - 1)
+        );
+        assert!(contract.get_orders(accounts(4)).is_empty());
+    }
+
+    #[test]
+    fn test_place_order_unregistered_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(3)).build());
+        let res = contract.ft_on_transfer(
+            accounts(4),
+            100.into(),
+            format!(
+                r#"{{"limit_order":{{"pool_id":0,"price":"{}"}}}}"#,
+                PRICE_DIVISOR
+            ),
+        );
+        assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+        assert!(contract.get_orders(accounts(4)).is_empty());
+    }
+
+    #[test]
+    fn test_order_storage() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        storage_deposit(&mut contract, accounts(4));
+        let available = contract.storage_balance_of(accounts(4)).unwrap().available;
+
+        place_order(&mut contract, accounts(4), accounts(3), 100, PRICE_DIVISOR);
+        assert!(
+            contract
+                .storage_balance_of(accounts(4))
+                .unwrap()
+                .available
+                .0
+                < available.0
+        );
+
+        // cancelling releases the storage of the order
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.cancel_order(U64(0));
+        assert_eq!(
+            contract.storage_balance_of(accounts(4)).unwrap().available,
+            available
+        );
+    }
+
+    #[test]
+    fn test_swap_fills_at_most_max_orders() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000_000, 1_000_000);
+        for _ in 0..=MAX_FILLS_PER_SWAP {
+            place_order(
+                &mut contract,
+                accounts(5),
+                accounts(3),
+                100,
+                PRICE_DIVISOR / 2,
+            );
+        }
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 2_000.into(), SWAP_MSG.to_string());
+
+        // the last order stays open and the rest of the input is swapped in the pool
+        let orders = contract.get_orders(accounts(5));
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].filled, U128(0));
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(
+            info.token_a_supply,
+            U128(1_000_000 + 2_000 - 50 * MAX_FILLS_PER_SWAP as u128)
+        );
+    }
+
+    #[test]
+    fn test_swap_fills_orders() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        // sells 100 of token b for 0.5 of token a each
+        place_order(
+            &mut contract,
+            accounts(5),
+            accounts(3),
+            100,
+            PRICE_DIVISOR / 2,
+        );
+        assert_eq!(contract.get_return(0, accounts(2), U128(100)), U128(147));
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100.into(), SWAP_MSG.to_string());
+
+        // 50 of token a fill the order for 100 of token b and the other 50
+        // are swapped in the pool for 50 * 1_000 / 1_050 = 47
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_050));
+        assert_eq!(info.token_b_supply, U128(953));
+        assert!(contract.get_orders(accounts(5)).is_empty());
+        assert!(contract.get_price_levels(0, accounts(3), 10).is_empty());
+        assert_eq!(contract.get_deposits(accounts(5))[&accounts(2)], U128(50));
+
+        let receipts = get_created_receipts();
+        let transfer = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id == accounts(3))
+            .unwrap();
+        match &transfer.actions[0] {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                ..
+            } => {
+                assert_eq!(function_name, "ft_transfer");
+                let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+                assert_eq!(args["receiver_id"], accounts(4).to_string());
+                assert_eq!(args["amount"], "147");
+            }
+            _ => panic!("Expected function call"),
+        }
+    }
+
+    #[test]
+    fn test_swap_partially_fills_order() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        place_order(
+            &mut contract,
+            accounts(5),
+            accounts(3),
+            1_000,
+            PRICE_DIVISOR / 2,
+        );
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100.into(), SWAP_MSG.to_string());
+
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_000));
+        assert_eq!(info.token_b_supply, U128(1_000));
+        let order = contract.get_order(U64(0)).unwrap();
+        assert_eq!(order.amount, U128(800));
+        assert_eq!(order.filled, U128(200));
+        assert_eq!(contract.get_deposits(accounts(5))[&accounts(2)], U128(100));
+    }
+
+    #[test]
+    fn test_swap_skips_orders_worse_than_pool() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        // sells token b for 2 of token a each, while the pool returns 90 for 100
+        place_order(
+            &mut contract,
+            accounts(5),
+            accounts(3),
+            100,
+            2 * PRICE_DIVISOR,
+        );
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100.into(), SWAP_MSG.to_string());
+
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_100));
+        assert_eq!(info.token_b_supply, U128(910));
+        assert_eq!(contract.get_order(U64(0)).unwrap().filled, U128(0));
+    }
+
+    #[test]
+    fn test_swap_fills_order_at_marginal_price() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        // sells token b for 1.1 of token a each, which is above the spot price of the pool
+        place_order(
+            &mut contract,
+            accounts(5),
+            accounts(3),
+            1_000,
+            11 * PRICE_DIVISOR / 10,
+        );
+        // only the order would return 1_000 / 1.1 = 909 and only the pool 498
+        assert_eq!(contract.get_return(0, accounts(2), U128(1_000)), U128(910));
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 1_000.into(), SWAP_MSG.to_string());
+
+        // the pool is used, until its marginal price including the fee reaches 1.1,
+        // and the order is filled with the rest
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_050));
+        assert_eq!(info.token_b_supply, U128(953));
+        let order = contract.get_order(U64(0)).unwrap();
+        assert_eq!(order.filled, U128(863));
+        assert_eq!(contract.get_deposits(accounts(5))[&accounts(2)], U128(950));
+    }
+
+    #[test]
+    fn test_swap_fills_orders_between_pool_prices() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        contract.set_fee(0, 100);
+        place_order(
+            &mut contract,
+            accounts(5),
+            accounts(3),
+            100,
+            105 * PRICE_DIVISOR / 100,
+        );
+        place_order(
+            &mut contract,
+            accounts(5),
+            accounts(3),
+            100,
+            12 * PRICE_DIVISOR / 10,
+        );
+
+        // a small swap stays below the price of both orders including the fee of 1%
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 3.into(), SWAP_MSG.to_string());
+        assert_eq!(contract.get_pool(0).unwrap().token_a_supply, U128(1_003));
+        assert_eq!(contract.get_order(U64(0)).unwrap().filled, U128(0));
+
+        // a larger swap fills the cheaper order, once the pool reaches its price,
+        // and stops using the pool at the price of the other one
+        contract.ft_on_transfer(accounts(4), 300.into(), SWAP_MSG.to_string());
+        // the pool ends at a marginal price of 1_092 / 919 / 0.99 = 1.2
+        assert_eq!(contract.get_order(U64(0)), None);
+        assert_eq!(contract.get_order(U64(1)).unwrap().filled, U128(88));
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_092));
+        assert_eq!(info.token_b_supply, U128(919));
+    }
+
+    #[test]
+    fn test_swap_exact_output_ignores_orders() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        place_order(
+            &mut contract,
+            accounts(5),
+            accounts(3),
+            100,
+            PRICE_DIVISOR / 2,
+        );
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(
+            accounts(4),
+            200.into(),
+            r#"{"pool_id":0,"amount_out":"90"}"#.to_string(),
+        );
+        assert_eq!(contract.get_order(U64(0)).unwrap().filled, U128(0));
+        assert_eq!(contract.get_pool(0).unwrap().token_b_supply, U128(910));
+    }
+
+    #[test]
+    fn test_handle_swap_with_orders_failed_should_credit_deposits() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        place_order(
+            &mut contract,
+            accounts(5),
+            accounts(3),
+            100,
+            PRICE_DIVISOR / 2,
+        );
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100.into(), SWAP_MSG.to_string());
+
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        let res = contract.handle_swap(accounts(4), accounts(3), 147.into(), 0.into());
+        // the output of the order and the pool is credited
+        assert_eq!(res, U128(0));
+        assert_eq!(contract.get_deposits(accounts(4))[&accounts(3)], U128(147));
+        // the filled order stays filled
+        assert_eq!(contract.get_order(U64(0)), None);
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_050));
+        assert_eq!(info.token_b_supply, U128(953));
+    }
+
+    #[test]
+    fn test_cancel_order() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        place_order(&mut contract, accounts(4), accounts(3), 100, PRICE_DIVISOR);
+        place_order(&mut contract, accounts(4), accounts(3), 200, PRICE_DIVISOR);
+
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.cancel_order(U64(0));
+        assert_eq!(contract.get_order(U64(0)), None);
+        assert_eq!(contract.get_orders(accounts(4)).len(), 1);
+        assert_eq!(
+            contract.get_price_levels(0, accounts(3), 10)[0].amount,
+            U128(200)
+        );
+
+        let receipts = get_created_receipts();
+        let transfer = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id == accounts(3))
+            .unwrap();
+        match &transfer.actions[0] {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                ..
+            } => {
+                assert_eq!(function_name, "ft_transfer");
+                let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+                assert_eq!(args["receiver_id"], accounts(4).to_string());
+                assert_eq!(args["amount"], "100");
+            }
+            _ => panic!("Expected function call"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Order 0 does not belong to fargo")]
+    fn test_cancel_order_not_owner() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        place_order(&mut contract, accounts(4), accounts(3), 100, PRICE_DIVISOR);
+
+        testing_env!(get_context(accounts(5)).attached_deposit(1).build());
+        contract.cancel_order(U64(0));
+    }
+
+    #[test]
+    fn test_get_twap() {
+        const SECOND: u64 = 1_000_000_000;
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+
+        testing_env!(get_context(accounts(2))
+            .block_timestamp(100 * SECOND)
+            .build());
+        contract.ft_on_transfer(accounts(4), 100.into(), SWAP_MSG.to_string());
+        // the price right after the swap is not part of the average yet
+        assert_eq!(contract.get_twap(0, accounts(2), 100), U128(PRICE_DIVISOR));
+
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(200 * SECOND)
+            .build());
+        // 50 seconds at a price of 1 and 100 seconds at 910 / 1_100
+        assert_eq!(
+            contract.get_twap(0, accounts(2), 150),
+            U128(884_848_484_848_484_848)
+        );
+        // 1_100 / 910
+        assert_eq!(
+            contract.get_twap(0, accounts(3), 100),
+            U128(1_208_791_208_791_208_791)
+        );
+    }
+
+    #[test]
+    fn test_get_concentrated_twap() {
+        const SECOND: u64 = 1_000_000_000;
+        let mut contract = setup_concentrated_pool();
+
+        concentrated_swap(&mut contract, accounts(2), 100_000);
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(100 * SECOND)
+            .build());
+        // the position was opened at price 1 and the swap happened in the same second
+        let sqrt_price = contract.get_concentrated_pool(0).unwrap().sqrt_price.0;
+        let price = U256::from(sqrt_price) * U256::from(sqrt_price);
+        let price_a = (((price >> 64) * U256::from(PRICE_DIVISOR)) >> 64).as_u128();
+        let price_b = ((U256::from(PRICE_DIVISOR) << 128) / price).as_u128();
+        assert!(price_a < PRICE_DIVISOR && price_b > PRICE_DIVISOR);
+        assert_eq!(
+            contract.get_concentrated_twap(0, accounts(2), 50),
+            U128(price_a)
+        );
+
+        testing_env!(get_context(accounts(3))
+            .block_timestamp(100 * SECOND)
+            .build());
+        contract.ft_on_transfer(
+            accounts(4),
+            100_000.into(),
+            r#"{"action":"concentrated_swap","pool_id":0}"#.to_string(),
+        );
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(200 * SECOND)
+            .build());
+        let sqrt_price = contract.get_concentrated_pool(0).unwrap().sqrt_price.0;
+        let price = U256::from(sqrt_price) * U256::from(sqrt_price);
+        let price_b_after = ((U256::from(PRICE_DIVISOR) << 128) / price).as_u128();
+        // 50 seconds before and 100 seconds after the second swap
+        assert_eq!(
+            contract.get_concentrated_twap(0, accounts(3), 150),
+            U128((50 * price_b + 100 * price_b_after) / 150)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough observations for the window")]
+    fn test_get_concentrated_twap_window_too_long() {
+        let contract = setup_concentrated_pool();
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(100_000_000_000)
+            .build());
+        contract.get_concentrated_twap(0, accounts(2), 101);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough observations for the window")]
+    fn test_get_twap_window_too_long() {
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(1_000_000_000_000)
+            .build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        set_metadata_results(&[
+            get_metadata("TokenA", "TKNA"),
+            get_metadata("TokenB", "TKNB"),
+        ]);
+        contract.handle_add_pool(accounts(2), accounts(3), PoolCurve::ConstantProduct);
+        storage_deposit(&mut contract, accounts(1));
+        for token in [accounts(2), accounts(3)] {
+            testing_env!(get_context(token)
+                .block_timestamp(1_000_000_000_000)
+                .build());
+            contract.ft_on_transfer(accounts(1), 1_000.into(), DEPOSIT_MSG.to_string());
+        }
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(1_000_000_000_000)
+            .build());
+        contract.add_liquidity(0, 1_000.into(), 1_000.into(), 0.into());
+        // the first observation is at 1_000 seconds
+        contract.get_twap(0, accounts(2), 500);
+    }
+
+    /// Swaps back and forth every 10 seconds, so that the `count` observations after the one
+    /// of the initial liquidity wrap around the ring buffer. Returns the timestamps in seconds
+    /// and prices of token a of all observations.
+    fn setup_twap_observations(count: u64) -> (OrderlyContract, Vec<(u64, u128)>) {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000_000, 1_000_000);
+        let mut observations = vec![(0, PRICE_DIVISOR)];
+        for i in 1..=count {
+            let timestamp = i * 10;
+            let token = if i % 2 == 0 { accounts(3) } else { accounts(2) };
+            testing_env!(get_context(token)
+                .block_timestamp(timestamp * 1_000_000_000)
+                .build());
+            let amount = 1_000 + (i % 7) as u128 * 100;
+            contract.ft_on_transfer(accounts(4), amount.into(), SWAP_MSG.to_string());
+            let pool = contract.get_pool(0).unwrap();
+            let price = PRICE_DIVISOR * pool.token_b_supply.0 / pool.token_a_supply.0;
+            observations.push((timestamp, price));
+        }
+        (contract, observations)
+    }
+
+    /// Average of the prices of token a between `start` and `end`, which are constant
+    /// between the observations.
+    fn expected_twap(observations: &[(u64, u128)], start: u64, end: u64) -> u128 {
+        let mut sum = 0;
+        for (i, (timestamp, price)) in observations.iter().enumerate() {
+            let next = observations.get(i + 1).map_or(end, |(next, _)| *next);
+            let (from, to) = (std::cmp::max(*timestamp, start), std::cmp::min(next, end));
+            if from < to {
+                sum += price * (to - from) as u128;
+            }
+        }
+        sum / (end - start) as u128
+    }
+
+    #[test]
+    fn test_get_twap_ring_buffer() {
+        let count = oracle::MAX_OBSERVATIONS + 10;
+        let (contract, observations) = setup_twap_observations(count);
+        // the first 11 observations were overwritten, the oldest one is at 110 seconds
+        let now = count * 10 + 5;
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(now * 1_000_000_000)
+            .build());
+        // starts between two observations right after the overwritten ones
+        // and ends after the latest one, that was written to the start of the buffer
+        for start in [110, 115, 5_003, now - 3] {
+            assert_eq!(
+                contract.get_twap(0, accounts(2), now - start),
+                U128(expected_twap(&observations, start, now)),
+                "window starting at {}",
+                start
+            );
+        }
+        assert_eq!(
+            contract.get_twap(0, accounts(2), 3),
+            U128(observations.last().unwrap().1)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough observations for the window")]
+    fn test_get_twap_overwritten_observations() {
+        let count = oracle::MAX_OBSERVATIONS + 10;
+        let (contract, _) = setup_twap_observations(count);
+        let now = count * 10 + 5;
+        testing_env!(get_context(accounts(1))
+            .block_timestamp(now * 1_000_000_000)
+            .build());
+        // the observation at 100 seconds was overwritten
+        contract.get_twap(0, accounts(2), now - 105);
+    }
+
+    #[test]
+    fn test_transfer_ownership() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(1)).build());
+        contract.propose_owner(accounts(4));
+        assert_eq!(contract.get_pending_owner(), Some(accounts(4)));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"orderly","version":"1.0.0","event":"owner_proposed","data":[{"owner_id":"bob","new_owner_id":"eugene"}]}"#
+            ]
+        );
+        // the current owner stays in control until the proposal is accepted
+        contract.set_fee(0, 30);
+
+        testing_env!(get_context(accounts(4)).build());
+        contract.accept_ownership();
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"orderly","version":"1.0.0","event":"ownership_transferred","data":[{"old_owner_id":"bob","new_owner_id":"eugene"}]}"#
+            ]
+        );
+        assert_eq!(contract.get_contract_info().owner, accounts(4));
+        assert_eq!(contract.get_pending_owner(), None);
+        contract.set_fee(0, 10);
+        assert_eq!(contract.get_pool(0).unwrap().fee, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_transfer_ownership_old_owner() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(1)).build());
+        contract.propose_owner(accounts(4));
+        testing_env!(get_context(accounts(4)).build());
+        contract.accept_ownership();
+
+        testing_env!(get_context(accounts(1)).build());
+        contract.set_fee(0, 30);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_propose_owner_not_owner() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        testing_env!(get_context(accounts(4)).build());
+        contract.propose_owner(accounts(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the proposed owner can accept the ownership")]
+    fn test_accept_ownership_not_proposed() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        contract.propose_owner(accounts(4));
+        testing_env!(get_context(accounts(5)).build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    fn test_pause_should_refund_transfers() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        deposit(&mut contract, accounts(4), accounts(2), 100);
+        testing_env!(get_context(accounts(1)).build());
+        contract.add_guardians(vec![accounts(5)]);
+        assert_eq!(contract.get_guardians(), vec![accounts(5)]);
+
+        testing_env!(get_context(accounts(5)).build());
+        contract.pause();
+        assert!(contract.get_contract_info().paused);
+
+        testing_env!(get_context(accounts(2)).build());
+        for msg in [SWAP_MSG, DEPOSIT_MSG] {
+            let res = contract.ft_on_transfer(accounts(4), 100.into(), msg.to_string());
+            assert!(matches!(res, PromiseOrValue::Value(U128(100))));
+        }
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_000));
+        assert_eq!(info.token_b_supply, U128(1_000));
+
+        // withdrawals are still possible
+        testing_env!(get_context(accounts(4)).attached_deposit(1).build());
+        contract.withdraw(accounts(2), 100.into());
+        assert!(contract.get_deposits(accounts(4)).is_empty());
+
+        testing_env!(get_context(accounts(1)).build());
+        contract.resume();
+        assert!(!contract.get_contract_info().paused);
+        testing_env!(get_context(accounts(2)).build());
+        let res = contract.ft_on_transfer(accounts(4), 100.into(), SWAP_MSG.to_string());
+        assert!(matches!(res, PromiseOrValue::Promise(_)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract is paused")]
+    fn test_add_liquidity_paused() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = setup_contract(1_000, 1_000);
+        deposit(&mut contract, accounts(1), accounts(2), 100);
+        deposit(&mut contract, accounts(1), accounts(3), 100);
+        testing_env!(get_context(accounts(1)).build());
+        contract.pause();
+        contract.add_liquidity(0, 100.into(), 100.into(), 0.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner or a guardian can call this method")]
+    fn test_pause_not_guardian() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        contract.add_guardians(vec![accounts(5)]);
+        contract.remove_guardians(vec![accounts(5)]);
+        testing_env!(get_context(accounts(5)).build());
+        contract.pause();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_resume_guardian() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyContract::new(accounts(1));
+        contract.add_guardians(vec![accounts(5)]);
+        testing_env!(get_context(accounts(5)).build());
+        contract.pause();
+        contract.resume();
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        use near_sdk::collections::LazyOption;
+        use upgrade::{OrderlyContractV1, TokenPairV1};
+
+        testing_env!(get_context(accounts(0)).build());
+        // the first version stored its tokens under the storage keys 0 and 1
+        env::state_write(&OrderlyContractV1 {
+            owner: accounts(1),
+            token_a: LazyOption::new(
+                vec![0],
+                Some(&TokenPairV1 {
+                    account_id: accounts(2),
+                    metadata: get_metadata("TokenA", "TKNA"),
+                    supply: 1_000.into(),
+                }),
+            ),
+            token_b: LazyOption::new(
+                vec![1],
+                Some(&TokenPairV1 {
+                    account_id: accounts(3),
+                    metadata: get_metadata("TokenB", "TKNB"),
+                    supply: 4_000.into(),
+                }),
+            ),
+        });
+
+        let mut contract = OrderlyContract::migrate();
+        assert!(!env::storage_has_key(&[0]));
+        assert!(!env::storage_has_key(&[1]));
+        assert_eq!(contract.get_contract_info().owner, accounts(1));
+        let info = contract.get_pool(0).unwrap();
+        assert_eq!(info.token_a_supply, U128(1_000));
+        assert_eq!(info.token_b_supply, U128(4_000));
+        assert_eq!(info.curve, PoolCurve::ConstantProduct);
+        // the owner provided all liquidity of the first version
+        assert_eq!(
+            contract.mt_balance_of(accounts(1), "0".to_string()),
+            U128(2_000)
+        );
+
+        testing_env!(get_context(accounts(2)).build());
+        contract.ft_on_transfer(accounts(4), 100.into(), SWAP_MSG.to_string());
+        assert_eq!(contract.get_pool(0).unwrap().token_b_supply, U128(3_637));
+    }
+
+    #[test]
+    fn test_migrate_current_version() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = setup_contract(1_000, 1_000);
+        env::state_write(&contract);
+
+        let contract = OrderlyContract::migrate();
+        assert_eq!(contract.get_contract_info().pool_count, 1);
+        assert_eq!(
+            contract.mt_balance_of(accounts(1), "0".to_string()),
+            U128(1_000)
+        );
+    }
+
+    #[test]
+    fn test_upgrade() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = OrderlyContract::new(accounts(1));
+        let mut context = get_context(accounts(1)).build();
+        context.input = vec![0, 97, 115, 109];
+        testing_env!(context);
+        contract.upgrade();
+
+        let receipts = get_created_receipts();
+        let upgrade = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id == accounts(0))
+            .unwrap();
+        assert!(matches!(
+            &upgrade.actions[0],
+            VmAction::DeployContract { code } if code == &vec![0, 97, 115, 109]
+        ));
+        match &upgrade.actions[1] {
+            VmAction::FunctionCall { function_name, .. } => assert_eq!(function_name, "migrate"),
+            _ => panic!("Expected function call"),
+        }
+    }
+
+    #[test]
+    fn test_delete_account() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = OrderlyContract::new(accounts(1));
+        contract.delete_account(accounts(4));
+
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, accounts(0));
+        assert!(matches!(
+            &receipts[0].actions[0],
+            VmAction::DeleteAccount { beneficiary_id } if beneficiary_id == &accounts(4)
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Contract with pools cannot be deleted")]
+    fn test_delete_account_with_pools() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = setup_contract(1_000, 1_000);
+        testing_env!(get_context(accounts(1)).build());
+        contract.delete_account(accounts(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_delete_account_not_owner() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = OrderlyContract::new(accounts(1));
+        testing_env!(get_context(accounts(4)).build());
+        contract.delete_account(accounts(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_upgrade_not_owner() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = OrderlyContract::new(accounts(1));
+        testing_env!(get_context(accounts(4)).build());
+        contract.upgrade();
+    }
 }
@@ -0,0 +1,262 @@
+use crate::{
+    math::{mul_div, sqrt_mul},
+    *,
+};
+use near_sdk::assert_one_yocto;
+
+#[near_bindgen]
+impl OrderlyContract {
+    /// Adds liquidity from the pending deposits of the caller.
+    /// Only the amounts matching the current ratio of the reserves are added,
+    /// the remainder stays in the deposits and can be withdrawn.
+    /// Returns the amount of minted shares.
+    pub fn add_liquidity(
+        &mut self,
+        pool_id: u64,
+        amount_a: U128,
+        amount_b: U128,
+        min_shares: U128,
+    ) -> U128 {
+        self.assert_not_paused();
+        let account_id = env::predecessor_account_id();
+        let mut pool = self.internal_get_pool(pool_id);
+        let (pair_a, pair_b) = (&pool.token_a, &pool.token_b);
+
+        let total_shares = pool.shares.total_supply;
+        let (amount_a, amount_b, shares) =
+            if total_shares == 0 || pair_a.supply.0 == 0 || pair_b.supply.0 == 0 {
+                assert!(
+                    amount_a.0 > 0 && amount_b.0 > 0,
+                    "Initial liquidity must contain both tokens"
+                );
+                (amount_a.0, amount_b.0, sqrt_mul(amount_a.0, amount_b.0))
+            } else {
+                let optimal_b = mul_div(amount_a.0, pair_b.supply.0, pair_a.supply.0);
+                let (amount_a, amount_b) = if optimal_b <= amount_b.0 {
+                    (amount_a.0, optimal_b)
+                } else {
+                    (
+                        mul_div(amount_b.0, pair_a.supply.0, pair_b.supply.0),
+                        amount_b.0,
+                    )
+                };
+                let shares = std::cmp::min(
+                    mul_div(amount_a, total_shares, pair_a.supply.0),
+                    mul_div(amount_b, total_shares, pair_b.supply.0),
+                );
+                (amount_a, amount_b, shares)
+            };
+        assert!(shares > 0, "Not enough liquidity added to mint shares");
+        assert!(
+            shares >= min_shares.0,
+            "Slippage exceeded: {} shares are less than the minimum of {}",
+            shares,
+            min_shares.0
+        );
+
+        self.internal_withdraw_deposit(&account_id, &pool.token_a.account_id, amount_a);
+        self.internal_withdraw_deposit(&account_id, &pool.token_b.account_id, amount_b);
+        pool.token_a.supply.0 += amount_a;
+        pool.token_b.supply.0 += amount_b;
+        self.internal_mint_shares(&mut pool, &account_id, shares);
+        self.assert_storage_covered(&account_id);
+        multi_token::emit_mint(pool_id, &account_id, shares);
+        log!(
+            "User {} added {} of token {} and {} of token {} as liquidity to pool {} for {} shares",
+            account_id,
+            amount_a,
+            pool.token_a.account_id,
+            amount_b,
+            pool.token_b.account_id,
+            pool_id,
+            shares
+        );
+        pool.update_oracle();
+        self.pools.replace(pool_id, &pool);
+
+        shares.into()
+    }
+
+    /// Burns `shares` of the caller and transfers the proportional amounts of both reserves.
+    /// If the transfer of a token fails, its amount is credited to the deposits of the caller.
+    #[payable]
+    pub fn remove_liquidity(
+        &mut self,
+        pool_id: u64,
+        shares: U128,
+        min_amount_a: U128,
+        min_amount_b: U128,
+    ) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut pool = self.internal_get_pool(pool_id);
+        let (pair_a, pair_b) = (&pool.token_a, &pool.token_b);
+
+        let total_shares = pool.shares.total_supply;
+        assert!(shares.0 > 0, "Shares must be greater than zero");
+        assert!(total_shares > 0, "Pool {} has no liquidity", pool_id);
+        let balance = pool.shares.accounts.get(&account_id).unwrap_or_default();
+        assert!(
+            balance >= shares.0,
+            "Not enough shares of pool {}, {} has {}",
+            pool_id,
+            account_id,
+            balance
+        );
+        let amount_a = mul_div(pair_a.supply.0, shares.0, total_shares);
+        let amount_b = mul_div(pair_b.supply.0, shares.0, total_shares);
+        assert!(
+            amount_a >= min_amount_a.0,
+            "Slippage exceeded: {} of token {} is less than the minimum of {}",
+            amount_a,
+            pair_a.account_id,
+            min_amount_a.0
+        );
+        assert!(
+            amount_b >= min_amount_b.0,
+            "Slippage exceeded: {} of token {} is less than the minimum of {}",
+            amount_b,
+            pair_b.account_id,
+            min_amount_b.0
+        );
+
+        self.internal_burn_shares(&mut pool, &account_id, shares.0);
+        multi_token::emit_burn(pool_id, &account_id, shares.0);
+        pool.token_a.supply.0 -= amount_a;
+        pool.token_b.supply.0 -= amount_b;
+        log!(
+            "User {} removed {} of token {} and {} of token {} as liquidity from pool {} for {} shares",
+            account_id,
+            amount_a,
+            pool.token_a.account_id,
+            amount_b,
+            pool.token_b.account_id,
+            pool_id,
+            shares.0
+        );
+        pool.update_oracle();
+        self.pools.replace(pool_id, &pool);
+
+        self.transfer_with_fallback(&account_id, pool.token_a.account_id, amount_a)
+            .and(self.transfer_with_fallback(&account_id, pool.token_b.account_id, amount_b))
+    }
+
+    /// Withdraws pending deposits of the caller, that have not been added as liquidity.
+    #[payable]
+    pub fn withdraw(&mut self, token_id: AccountId, amount: U128) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.internal_withdraw_deposit(&account_id, &token_id, amount.0);
+        self.transfer_with_fallback(&account_id, token_id, amount.0)
+    }
+
+    /// Credits the deposit of `account_id`, if the outgoing transfer failed.
+    #[private]
+    pub fn handle_withdraw(&mut self, account_id: AccountId, token_id: AccountId, amount: U128) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expected one promise result"
+        );
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+
+        log!(
+            "Withdrawal of {} of token {} failed. Restoring deposit of {}",
+            amount.0,
+            token_id,
+            account_id
+        );
+        self.internal_deposit(&account_id, &token_id, amount.0);
+    }
+
+    pub fn get_deposits(&self, account_id: AccountId) -> HashMap<AccountId, U128> {
+        self.deposits
+            .get(&account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(token_id, amount)| (token_id, amount.into()))
+            .collect()
+    }
+}
+
+impl OrderlyContract {
+    /// Transfers `amount` of `token_id` to `account_id`.
+    /// The amount is credited to the deposits of `account_id`, if the transfer fails.
+    pub(crate) fn transfer_with_fallback(
+        &self,
+        account_id: &AccountId,
+        token_id: AccountId,
+        amount: u128,
+    ) -> Promise {
+        ext_fungible_token::ext(token_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .ft_transfer(
+                account_id.clone(),
+                amount.into(),
+                Some("withdraw".to_string()),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_WITHDRAW)
+                    .handle_withdraw(account_id.clone(), token_id, amount.into()),
+            )
+    }
+
+    /// Credits `amount` of `token_id` to the deposits of `account_id`. Callers, that can
+    /// refuse the deposit, need to check afterwards, that the storage of the account is covered.
+    pub(crate) fn internal_deposit(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: u128,
+    ) {
+        assert!(
+            self.tokens.contains(token_id),
+            "Token {} does not belong to any liquidity pool",
+            token_id
+        );
+        let initial_storage = env::storage_usage();
+        let mut deposits = self.deposits.get(account_id).unwrap_or_default();
+        let created = !deposits.contains_key(token_id);
+        *deposits.entry(token_id.clone()).or_default() += amount;
+        self.deposits.insert(account_id, &deposits);
+        self.internal_update_storage(account_id, initial_storage);
+        if created {
+            self.internal_update_entries(account_id, true);
+        }
+    }
+
+    pub(crate) fn internal_withdraw_deposit(
+        &mut self,
+        account_id: &AccountId,
+        token_id: &AccountId,
+        amount: u128,
+    ) {
+        let initial_storage = env::storage_usage();
+        let mut deposits = self.deposits.get(account_id).unwrap_or_default();
+        let deposit = deposits.get(token_id).copied().unwrap_or_default();
+        assert!(
+            deposit >= amount,
+            "Not enough deposited of token {}",
+            token_id
+        );
+        let removed = if deposit == amount {
+            deposits.remove(token_id).is_some()
+        } else {
+            deposits.insert(token_id.clone(), deposit - amount);
+            false
+        };
+        if deposits.is_empty() {
+            self.deposits.remove(account_id);
+        } else {
+            self.deposits.insert(account_id, &deposits);
+        }
+        self.internal_update_storage(account_id, initial_storage);
+        if removed {
+            self.internal_update_entries(account_id, false);
+        }
+    }
+}
@@ -0,0 +1,1166 @@
+use crate::FEE_DIVISOR;
+
+pub use big_int::{U256, U512};
+
+#[allow(clippy::all)]
+mod big_int {
+    use uint::construct_uint;
+
+    construct_uint! {
+        /// 256 bit unsigned integer for intermediate results, that would overflow `u128`.
+        pub struct U256(4);
+    }
+
+    construct_uint! {
+        /// 512 bit unsigned integer for products of several 256 bit numbers.
+        pub struct U512(8);
+    }
+
+    impl From<U256> for U512 {
+        fn from(value: U256) -> Self {
+            let mut words = [0; 8];
+            words[..4].copy_from_slice(&value.0);
+            U512(words)
+        }
+    }
+
+    impl U512 {
+        /// Converts to U256. Panics on overflow.
+        pub fn as_u256(&self) -> U256 {
+            assert!(
+                self.0[4..].iter().all(|word| *word == 0),
+                "Overflow in U512"
+            );
+            U256([self.0[0], self.0[1], self.0[2], self.0[3]])
+        }
+    }
+}
+
+/// Computes `a * b / c` rounded down, without overflowing on the intermediate product.
+/// Panics if the result does not fit into `u128`.
+pub fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    let res = U256::from(a) * U256::from(b) / U256::from(c);
+    assert!(res <= U256::from(u128::MAX), "Overflow in mul_div");
+    res.as_u128()
+}
+
+/// Computes `a * b / c` rounded up, without overflowing on the intermediate product.
+/// Panics if the result does not fit into `u128`.
+pub fn mul_div_ceil(a: u128, b: u128, c: u128) -> u128 {
+    checked_mul_div_ceil(a, b, c).expect("Overflow in mul_div")
+}
+
+/// Computes `a * b / c` rounded up or `None`, if the result does not fit into `u128`.
+pub fn checked_mul_div_ceil(a: u128, b: u128, c: u128) -> Option<u128> {
+    let (res, rem) = (U256::from(a) * U256::from(b)).div_mod(U256::from(c));
+    let res = if rem.is_zero() { res } else { res + 1 };
+    (res <= U256::from(u128::MAX)).then(|| res.as_u128())
+}
+
+/// Computes `sqrt(a * b)` rounded down.
+pub fn sqrt_mul(a: u128, b: u128) -> u128 {
+    (U256::from(a) * U256::from(b)).integer_sqrt().as_u128()
+}
+
+/// Computes the amount of the output token for swapping `amount_in` with
+/// `fee` in basis points against the given reserves.
+///
+/// The output is `reserve_out * amount_in / (reserve_in + amount_in)` after deducting the fee
+/// and rounded down, so the product of both reserves never decreases by a swap.
+pub fn get_amount_out(amount_in: u128, reserve_in: u128, reserve_out: u128, fee: u32) -> u128 {
+    let amount_in_with_fee = mul_div(amount_in, (FEE_DIVISOR - fee) as u128, FEE_DIVISOR as u128);
+    let numerator = U256::from(reserve_out) * U256::from(amount_in_with_fee);
+    let denominator = U256::from(reserve_in) + U256::from(amount_in_with_fee);
+    (numerator / denominator).as_u128()
+}
+
+/// Computes the amount of the input token, that is needed to receive at least `amount_out`
+/// with `fee` in basis points from the given reserves. This is the inverse of
+/// [`get_amount_out`] rounded up.
+pub fn get_amount_in(amount_out: u128, reserve_in: u128, reserve_out: u128, fee: u32) -> u128 {
+    assert!(
+        amount_out < reserve_out,
+        "Not enough liquidity available for swap"
+    );
+    let amount_in_with_fee = mul_div_ceil(reserve_in, amount_out, reserve_out - amount_out);
+    mul_div_ceil(
+        amount_in_with_fee,
+        FEE_DIVISOR as u128,
+        (FEE_DIVISOR - fee) as u128,
+    )
+}
+
+/// Computes the price impact of swapping `amount_in` in basis points, i.e. by how much
+/// the execution price is worse than the current spot price of the reserves, fees excluded.
+pub fn get_price_impact(amount_in: u128, reserve_in: u128, reserve_out: u128, fee: u32) -> u32 {
+    let amount_in_with_fee = mul_div(amount_in, (FEE_DIVISOR - fee) as u128, FEE_DIVISOR as u128);
+    let amount_out = get_amount_out(amount_in, reserve_in, reserve_out, fee);
+    let spot_amount_out = mul_div(amount_in_with_fee, reserve_out, reserve_in);
+    if spot_amount_out == 0 {
+        return 0;
+    }
+    mul_div_ceil(
+        spot_amount_out - amount_out,
+        FEE_DIVISOR as u128,
+        spot_amount_out,
+    ) as u32
+}
+
+/// Computes the StableSwap invariant `D` of the balances `x` and `y` with amplification `amp`,
+/// which satisfies `4A(x + y) + D = 4AD + D^3 / (4xy)`.
+pub fn stable_invariant(amp: u64, x: U256, y: U256) -> U256 {
+    let sum = x + y;
+    if sum.is_zero() {
+        return sum;
+    }
+    let ann = U256::from(amp) * 4;
+    let mut d = sum;
+    for _ in 0..256 {
+        // D^3 / (4xy)
+        let d_p = d * d / (x * 2) * d / (y * 2);
+        let d_prev = d;
+        d = (ann * sum + d_p * 2) * d / ((ann - 1) * d + d_p * 3);
+        if abs_diff(d, d_prev) <= U256::one() {
+            break;
+        }
+    }
+    d
+}
+
+/// Computes the balance of the other token, that keeps the StableSwap invariant `d`
+/// with a balance of `x`.
+pub fn stable_balance(amp: u64, x: U256, d: U256) -> U256 {
+    let ann = U256::from(amp) * 4;
+    // D^3 / (4x * 4A)
+    let c = d * d / (x * 2) * d / (ann * 2);
+    let b = x + d / ann;
+    let mut y = d;
+    for _ in 0..256 {
+        let y_prev = y;
+        y = (y * y + c) / (y * 2 + b - d);
+        if abs_diff(y, y_prev) <= U256::one() {
+            break;
+        }
+    }
+    y
+}
+
+/// Computes the output of a StableSwap swap without fees. All amounts need to have the same
+/// decimals. The output is rounded down by one more unit, so the invariant never decreases
+/// due to the approximation of the balances.
+pub fn stable_get_amount_out(
+    amp: u64,
+    amount_in: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+) -> U256 {
+    assert!(
+        !reserve_in.is_zero() && !reserve_out.is_zero(),
+        "Not enough liquidity available for swap"
+    );
+    let d = stable_invariant(amp, reserve_in, reserve_out);
+    let balance_out = stable_balance(amp, reserve_in + amount_in, d) + 1;
+    reserve_out.saturating_sub(balance_out)
+}
+
+/// Computes the input of a StableSwap swap without fees, that is needed to receive
+/// at least `amount_out`. This is the inverse of [`stable_get_amount_out`] rounded up.
+pub fn stable_get_amount_in(
+    amp: u64,
+    amount_out: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+) -> U256 {
+    assert!(
+        !reserve_in.is_zero() && amount_out < reserve_out,
+        "Not enough liquidity available for swap"
+    );
+    let d = stable_invariant(amp, reserve_in, reserve_out);
+    let balance_in = stable_balance(amp, reserve_out - amount_out - 1, d) + 1;
+    balance_in.saturating_sub(reserve_in)
+}
+
+/// Computes the output of a StableSwap swap at the current spot price of the reserves,
+/// i.e. without any price impact and without fees.
+pub fn stable_get_spot_amount_out(
+    amp: u64,
+    amount_in: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+) -> U256 {
+    let d = stable_invariant(amp, reserve_in, reserve_out);
+    let d_p = d * d / (reserve_in * 2) * d / (reserve_out * 2);
+    let axy: U256 = U256::from(amp) * 4 * reserve_in * reserve_out;
+    // the spot price is the ratio of the partial derivatives of the invariant
+    let (numerator, denominator) = (axy + d_p * reserve_out, axy + d_p * reserve_in);
+    // keep enough precision, but prevent an overflow on multiplying with the input
+    let shift = denominator.bits().saturating_sub(100);
+    amount_in * (numerator >> shift) / (denominator >> shift)
+}
+
+/// Fixed point number `1` with 18 decimals, that is used for the weighted math.
+pub const ONE: u128 = 1_000_000_000_000_000_000;
+/// `ln(2)` as fixed point number.
+const LN_2: u128 = 693_147_180_559_945_309;
+/// Largest exponent, for which [`exp`] does not overflow.
+const MAX_EXP: i128 = 46 * ONE as i128;
+/// Below this exponent the result of [`exp`] rounds down to zero.
+const MIN_EXP: i128 = -42 * ONE as i128;
+/// Upper bound of the relative error of [`pow`], that is used for rounding its result.
+const MAX_POW_RELATIVE_ERROR: u128 = 10_000;
+
+/// Computes the natural logarithm of the fixed point number `x`.
+///
+/// `x` is split into `m * 2^k` with `1 <= m < 2`, so that `ln(m)` can be computed
+/// from the quickly converging series `ln(m) = 2 * atanh((m - 1) / (m + 1))`.
+pub fn ln(x: u128) -> i128 {
+    assert!(x > 0, "Logarithm of zero is undefined");
+    let (mut m, mut k) = (x, 0i128);
+    while m >= 2 * ONE {
+        m >>= 1;
+        k += 1;
+    }
+    while m < ONE {
+        m <<= 1;
+        k -= 1;
+    }
+    let z = mul_div(m - ONE, ONE, m + ONE);
+    let z_squared = mul_div(z, z, ONE);
+    let (mut term, mut sum) = (z, z);
+    for n in (3..).step_by(2) {
+        term = mul_div(term, z_squared, ONE);
+        if term == 0 {
+            break;
+        }
+        sum += term / n;
+    }
+    k * LN_2 as i128 + 2 * sum as i128
+}
+
+/// Computes `e^x` of the fixed point number `x` rounded down.
+///
+/// `x` is split into `k * ln(2) + r` with `|r| <= ln(2) / 2`, so that `e^r` can be computed
+/// from its Taylor series and `e^x = e^r * 2^k`.
+pub fn exp(x: i128) -> u128 {
+    assert!(x <= MAX_EXP, "Exponent out of bounds");
+    if x < MIN_EXP {
+        return 0;
+    }
+    let ln_2 = LN_2 as i128;
+    // rounded to the nearest integer
+    let k = if x >= 0 {
+        (x + ln_2 / 2) / ln_2
+    } else {
+        (x - ln_2 / 2) / ln_2
+    };
+    let r = x - k * ln_2;
+    let (mut term, mut sum) = (ONE as i128, ONE as i128);
+    for n in 1.. {
+        term = term * r / (n * ONE as i128);
+        if term == 0 {
+            break;
+        }
+        sum += term;
+    }
+    let sum = sum as u128;
+    if k >= 0 {
+        sum << k
+    } else {
+        sum >> -k
+    }
+}
+
+/// Computes `base^exponent` of the fixed point numbers `base` and `exponent`.
+/// The result is an approximation with a relative error of at most
+/// [`MAX_POW_RELATIVE_ERROR`] / [`ONE`].
+pub fn pow(base: u128, exponent: u128) -> u128 {
+    if exponent == ONE || base == ONE {
+        return base;
+    }
+    if exponent == 0 {
+        return ONE;
+    }
+    let ln_base = ln(base);
+    let product = mul_div(ln_base.unsigned_abs(), exponent, ONE) as i128;
+    exp(if ln_base < 0 { -product } else { product })
+}
+
+/// Computes [`pow`], that is rounded up by its maximum error.
+pub fn pow_up(base: u128, exponent: u128) -> u128 {
+    let res = pow(base, exponent);
+    if exponent == ONE {
+        return res;
+    }
+    res + mul_div_ceil(res, MAX_POW_RELATIVE_ERROR, ONE) + 1
+}
+
+/// Computes the output of a weighted swap without fees, where `weight_in` and `weight_out`
+/// are the weights of both tokens. The output is
+/// `reserve_out * (1 - (reserve_in / (reserve_in + amount_in))^(weight_in / weight_out))`,
+/// which keeps `reserve_in^weight_in * reserve_out^weight_out` constant.
+///
+/// All intermediate results are rounded, so that the output is rounded down.
+pub fn weighted_get_amount_out(
+    amount_in: u128,
+    reserve_in: u128,
+    weight_in: u32,
+    reserve_out: u128,
+    weight_out: u32,
+) -> u128 {
+    assert!(
+        reserve_in > 0 && reserve_out > 0,
+        "Not enough liquidity available for swap"
+    );
+    let (numerator, denominator) = (
+        U256::from(reserve_in) * U256::from(ONE),
+        U256::from(reserve_in) + U256::from(amount_in),
+    );
+    let (base, rem) = numerator.div_mod(denominator);
+    let base = if rem.is_zero() { base } else { base + 1 }.as_u128();
+    // a smaller exponent gives a larger power for a base below one
+    let exponent = mul_div(weight_in as u128, ONE, weight_out as u128);
+    let power = pow_up(base, exponent);
+    if power >= ONE {
+        return 0;
+    }
+    mul_div(reserve_out, ONE - power, ONE)
+}
+
+/// Computes the input of a weighted swap without fees, that is needed to receive
+/// `amount_out`. This is the inverse of [`weighted_get_amount_out`] with
+/// `reserve_in * ((reserve_out / (reserve_out - amount_out))^(weight_out / weight_in) - 1)`
+/// rounded up.
+///
+/// Returns `None`, if the input does not fit into `u128`, which happens for outputs close to
+/// `reserve_out`, especially if the weight of the output token is much larger.
+pub fn weighted_get_amount_in(
+    amount_out: u128,
+    reserve_in: u128,
+    weight_in: u32,
+    reserve_out: u128,
+    weight_out: u32,
+) -> Option<u128> {
+    assert!(
+        reserve_in > 0 && amount_out < reserve_out,
+        "Not enough liquidity available for swap"
+    );
+    let base = checked_mul_div_ceil(reserve_out, ONE, reserve_out - amount_out)?;
+    let exponent = mul_div_ceil(weight_out as u128, ONE, weight_in as u128);
+    // the power itself overflows, see `exp`
+    if mul_div(ln(base).unsigned_abs(), exponent, ONE) > MAX_EXP as u128 {
+        return None;
+    }
+    let power = pow_up(base, exponent);
+    checked_mul_div_ceil(reserve_in, power - ONE, ONE)
+}
+
+/// Computes the output of a weighted swap at the current spot price of the reserves,
+/// i.e. without any price impact and without fees.
+pub fn weighted_get_spot_amount_out(
+    amount_in: u128,
+    reserve_in: u128,
+    weight_in: u32,
+    reserve_out: u128,
+    weight_out: u32,
+) -> u128 {
+    let numerator = U256::from(amount_in) * U256::from(reserve_out) * U256::from(weight_in);
+    let denominator = U256::from(reserve_in) * U256::from(weight_out);
+    (numerator / denominator).as_u128()
+}
+
+/// Returns whether `reserve_in^weight_in * reserve_out^weight_out` did not decrease by a swap,
+/// that changed the reserves to `new_reserve_in` and `new_reserve_out`.
+///
+/// The powers are rounded up against the swapper, so the output must be at most the output of
+/// an exact input swap of the input or the input must be at least the input of an exact output
+/// swap of the output. Each on its own implies, that the exact invariant did not decrease.
+pub fn weighted_invariant_holds(
+    (reserve_in, new_reserve_in): (u128, u128),
+    weight_in: u32,
+    (reserve_out, new_reserve_out): (u128, u128),
+    weight_out: u32,
+) -> bool {
+    if new_reserve_in >= reserve_in && new_reserve_out >= reserve_out {
+        return true;
+    }
+    if new_reserve_in < reserve_in || new_reserve_out == 0 {
+        return false;
+    }
+    let (amount_in, amount_out) = (new_reserve_in - reserve_in, reserve_out - new_reserve_out);
+    amount_out <= weighted_get_amount_out(amount_in, reserve_in, weight_in, reserve_out, weight_out)
+        || weighted_get_amount_in(amount_out, reserve_in, weight_in, reserve_out, weight_out)
+            .is_some_and(|needed| amount_in >= needed)
+}
+
+/// Smallest tick of concentrated liquidity, where the price is `1.0001^MIN_TICK`.
+pub const MIN_TICK: i32 = -MAX_TICK;
+/// Largest tick of concentrated liquidity, which keeps its square root price within `2^32`.
+pub const MAX_TICK: i32 = 443_636;
+
+/// `2^128 / sqrt(1.0001)^(2^i)` for each bit `i` of a tick, rounded up.
+const TICK_FACTORS: [u128; 19] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+];
+
+/// Computes the square root of the price `1.0001^tick` as Q64.64 number.
+pub fn sqrt_price_at_tick(tick: i32) -> u128 {
+    assert!(
+        (MIN_TICK..=MAX_TICK).contains(&tick),
+        "Tick {} is out of bounds",
+        tick
+    );
+    let abs_tick = tick.unsigned_abs();
+    // Q128.128 number of `1 / sqrt(1.0001)^abs_tick`
+    let mut ratio = U256::one() << 128;
+    for (i, factor) in TICK_FACTORS.iter().enumerate() {
+        if abs_tick & (1 << i) != 0 {
+            ratio = (ratio * U256::from(*factor)) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+    // rounded up to Q64.64
+    let (res, rem) = ratio.div_mod(U256::one() << 64);
+    (if rem.is_zero() { res } else { res + 1 }).as_u128()
+}
+
+/// Computes the largest tick, whose square root price is at most `sqrt_price`.
+pub fn tick_at_sqrt_price(sqrt_price: u128) -> i32 {
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if sqrt_price_at_tick(mid) <= sqrt_price {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
+fn div_round(numerator: U256, denominator: U256, round_up: bool) -> U256 {
+    let (res, rem) = numerator.div_mod(denominator);
+    if round_up && !rem.is_zero() {
+        res + 1
+    } else {
+        res
+    }
+}
+
+fn div_round_512(numerator: U512, denominator: U512, round_up: bool) -> U512 {
+    let (res, rem) = numerator.div_mod(denominator);
+    if round_up && !rem.is_zero() {
+        res + 1
+    } else {
+        res
+    }
+}
+
+/// Computes the amount of token a between two square root prices for `liquidity`,
+/// i.e. `liquidity * (1 / sqrt_price_lower - 1 / sqrt_price_upper)`.
+pub fn amount_a_delta(
+    sqrt_price_lower: u128,
+    sqrt_price_upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> U256 {
+    let numerator = (U512::from(liquidity) * U512::from(sqrt_price_upper - sqrt_price_lower)) << 64;
+    let denominator = U512::from(sqrt_price_lower) * U512::from(sqrt_price_upper);
+    div_round_512(numerator, denominator, round_up).as_u256()
+}
+
+/// Computes the amount of token b between two square root prices for `liquidity`,
+/// i.e. `liquidity * (sqrt_price_upper - sqrt_price_lower)`.
+pub fn amount_b_delta(
+    sqrt_price_lower: u128,
+    sqrt_price_upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> U256 {
+    let delta = U256::from(liquidity) * U256::from(sqrt_price_upper - sqrt_price_lower);
+    div_round(delta, U256::one() << 64, round_up)
+}
+
+/// Computes the maximum liquidity between two square root prices, that can be provided
+/// with `amount_a` of token a and `amount_b` of token b at the current `sqrt_price`.
+pub fn liquidity_for_amounts(
+    sqrt_price: u128,
+    sqrt_price_lower: u128,
+    sqrt_price_upper: u128,
+    amount_a: u128,
+    amount_b: u128,
+) -> u128 {
+    let liquidity_a = |sqrt_price_lower: u128| {
+        let numerator =
+            U512::from(amount_a) * U512::from(sqrt_price_lower) * U512::from(sqrt_price_upper);
+        let denominator = U512::from(sqrt_price_upper - sqrt_price_lower) << 64;
+        (numerator / denominator).as_u256()
+    };
+    let liquidity_b = |sqrt_price_upper: u128| {
+        (U256::from(amount_b) << 64) / U256::from(sqrt_price_upper - sqrt_price_lower)
+    };
+    let liquidity = if sqrt_price <= sqrt_price_lower {
+        liquidity_a(sqrt_price_lower)
+    } else if sqrt_price >= sqrt_price_upper {
+        liquidity_b(sqrt_price_upper)
+    } else {
+        std::cmp::min(liquidity_a(sqrt_price), liquidity_b(sqrt_price))
+    };
+    assert!(
+        liquidity <= U256::from(i128::MAX as u128),
+        "Liquidity overflow"
+    );
+    liquidity.as_u128()
+}
+
+/// Computes the amounts of both tokens of `liquidity` between two square root prices
+/// at the current `sqrt_price`.
+pub fn amounts_for_liquidity(
+    sqrt_price: u128,
+    sqrt_price_lower: u128,
+    sqrt_price_upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> (u128, u128) {
+    let sqrt_price = sqrt_price.clamp(sqrt_price_lower, sqrt_price_upper);
+    (
+        amount_a_delta(sqrt_price, sqrt_price_upper, liquidity, round_up).as_u128(),
+        amount_b_delta(sqrt_price_lower, sqrt_price, liquidity, round_up).as_u128(),
+    )
+}
+
+/// Computes the square root price after swapping `amount_in` with `liquidity`,
+/// which moves the price down for token a and up for token b.
+/// The price is rounded, so that the output of the swap is rounded down.
+fn next_sqrt_price_from_input(
+    sqrt_price: u128,
+    liquidity: u128,
+    amount_in: u128,
+    a_for_b: bool,
+) -> u128 {
+    if amount_in == 0 {
+        return sqrt_price;
+    }
+    if a_for_b {
+        // liquidity / (liquidity / sqrt_price + amount_in)
+        let liquidity = U256::from(liquidity) << 64;
+        let denominator = liquidity / U256::from(sqrt_price) + U256::from(amount_in);
+        div_round(liquidity, denominator, true).as_u128()
+    } else {
+        // sqrt_price + amount_in / liquidity
+        let delta = (U256::from(amount_in) << 64) / U256::from(liquidity);
+        (U256::from(sqrt_price) + delta).as_u128()
+    }
+}
+
+/// Result of swapping within a single price range of constant liquidity.
+pub struct SwapStep {
+    pub sqrt_price: u128,
+    pub amount_in: u128,
+    pub amount_out: u128,
+    pub fee_amount: u128,
+}
+
+/// Computes a swap of at most `amount_remaining` including the `fee` in basis points
+/// from `sqrt_price` towards `sqrt_price_target` with constant `liquidity`.
+pub fn compute_swap_step(
+    sqrt_price: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_remaining: u128,
+    fee: u32,
+) -> SwapStep {
+    let a_for_b = sqrt_price >= sqrt_price_target;
+    let amount_remaining_less_fee = mul_div(
+        amount_remaining,
+        (FEE_DIVISOR - fee) as u128,
+        FEE_DIVISOR as u128,
+    );
+    let amount_in_to_target = if a_for_b {
+        amount_a_delta(sqrt_price_target, sqrt_price, liquidity, true)
+    } else {
+        amount_b_delta(sqrt_price, sqrt_price_target, liquidity, true)
+    };
+    let reached_target = amount_in_to_target <= U256::from(amount_remaining_less_fee);
+    let next_sqrt_price = if reached_target {
+        sqrt_price_target
+    } else {
+        next_sqrt_price_from_input(sqrt_price, liquidity, amount_remaining_less_fee, a_for_b)
+    };
+    let (amount_in, amount_out) = if a_for_b {
+        (
+            amount_a_delta(next_sqrt_price, sqrt_price, liquidity, true),
+            amount_b_delta(next_sqrt_price, sqrt_price, liquidity, false),
+        )
+    } else {
+        (
+            amount_b_delta(sqrt_price, next_sqrt_price, liquidity, true),
+            amount_a_delta(sqrt_price, next_sqrt_price, liquidity, false),
+        )
+    };
+    let (amount_in, amount_out) = (amount_in.as_u128(), amount_out.as_u128());
+    let fee_amount = if reached_target {
+        mul_div_ceil(amount_in, fee as u128, (FEE_DIVISOR - fee) as u128)
+    } else {
+        // the remainder is taken as fee, because it does not move the price
+        amount_remaining - amount_in
+    };
+    SwapStep {
+        sqrt_price: next_sqrt_price,
+        amount_in,
+        amount_out,
+        fee_amount,
+    }
+}
+
+fn abs_diff(a: U256, b: U256) -> U256 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const ONE_NEAR: u128 = 10u128.pow(24);
+
+    #[test]
+    fn test_mul_div() {
+        assert_eq!(mul_div(6, 7, 2), 21);
+        assert_eq!(mul_div(7, 1, 2), 3);
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), u128::MAX);
+        assert_eq!(mul_div(u128::MAX, 2, 4), u128::MAX / 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Overflow in mul_div")]
+    fn test_mul_div_overflow() {
+        mul_div(u128::MAX, 2, 1);
+    }
+
+    #[test]
+    fn test_sqrt_mul() {
+        assert_eq!(sqrt_mul(0, 1), 0);
+        assert_eq!(sqrt_mul(1, 1), 1);
+        assert_eq!(sqrt_mul(2, 4), 2);
+        assert_eq!(sqrt_mul(1_000, 4_000), 2_000);
+        assert_eq!(sqrt_mul(u128::MAX, u128::MAX), u128::MAX);
+    }
+
+    #[test]
+    fn test_get_amount_out() {
+        assert_eq!(get_amount_out(100, 1_000, 1_000, 0), 90);
+        assert_eq!(get_amount_out(1_000, 10_000, 10_000, 100), 900);
+        assert_eq!(get_amount_out(1_000_000, 1_000, 1_000, 0), 999);
+        assert_eq!(get_amount_out(1, 1_000, 1_000, 0), 0);
+    }
+
+    #[test]
+    fn test_get_amount_out_large_reserves() {
+        // the product of both reserves overflows u128
+        let reserve = 1_000_000 * ONE_NEAR;
+        assert_eq!(
+            get_amount_out(ONE_NEAR, reserve, reserve, 0),
+            999_999_000_000_999_999_000_000
+        );
+        assert_eq!(
+            get_amount_out(u128::MAX / 2, u128::MAX / 2, u128::MAX, 0),
+            u128::MAX / 2
+        );
+    }
+
+    #[test]
+    fn test_mul_div_ceil() {
+        assert_eq!(mul_div_ceil(6, 7, 2), 21);
+        assert_eq!(mul_div_ceil(7, 1, 2), 4);
+        assert_eq!(mul_div_ceil(u128::MAX, u128::MAX, u128::MAX), u128::MAX);
+    }
+
+    #[test]
+    fn test_get_amount_in() {
+        assert_eq!(get_amount_in(90, 1_000, 1_000, 0), 99);
+        assert_eq!(get_amount_out(99, 1_000, 1_000, 0), 90);
+        assert_eq!(get_amount_in(900, 10_000, 10_000, 100), 1_000);
+        assert_eq!(get_amount_out(1_000, 10_000, 10_000, 100), 900);
+        assert_eq!(get_amount_out(999, 10_000, 10_000, 100), 899);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough liquidity available for swap")]
+    fn test_get_amount_in_all_reserves() {
+        get_amount_in(1_000, 1_000, 1_000, 0);
+    }
+
+    #[test]
+    fn test_get_price_impact() {
+        assert_eq!(get_price_impact(100, 1_000, 1_000, 0), 1_000);
+        assert_eq!(get_price_impact(1_000, 1_000_000, 1_000_000, 0), 10);
+        assert_eq!(get_price_impact(1_000, 10_000, 10_000, 100), 910);
+        assert_eq!(get_price_impact(0, 1_000, 1_000, 0), 0);
+    }
+
+    #[test]
+    fn test_stable_invariant() {
+        let (x, y) = (U256::from(1_000_000), U256::from(1_000_000));
+        // the invariant of balanced reserves is their sum
+        assert_eq!(stable_invariant(100, x, y), U256::from(2_000_000));
+        assert_eq!(
+            stable_invariant(100, U256::zero(), U256::zero()),
+            U256::zero()
+        );
+        let d = stable_invariant(100, U256::from(1_500_000), U256::from(500_000));
+        assert!(d < U256::from(2_000_000) && d > U256::from(1_990_000));
+        assert_eq!(
+            stable_balance(100, U256::from(1_000_000), U256::from(2_000_000)),
+            y
+        );
+    }
+
+    #[test]
+    fn test_stable_get_amount_out() {
+        let reserve = U256::from(1_000_000);
+        let amount_out = stable_get_amount_out(100, U256::from(100_000), reserve, reserve);
+        // much less slippage than the 90_909 of the constant product curve
+        assert!(amount_out > U256::from(99_900) && amount_out < U256::from(100_000));
+        // a low amplification gets closer to the constant product curve
+        let amount_out = stable_get_amount_out(1, U256::from(100_000), reserve, reserve);
+        assert!(amount_out > U256::from(90_909) && amount_out < U256::from(99_000));
+    }
+
+    #[test]
+    fn test_stable_get_spot_amount_out() {
+        let reserve = U256::from(1_000_000);
+        assert_eq!(
+            stable_get_spot_amount_out(100, U256::from(1_000), reserve, reserve),
+            U256::from(1_000)
+        );
+        let amount_out =
+            stable_get_spot_amount_out(100, U256::from(1_000), U256::from(1_500_000), reserve / 2);
+        assert!(amount_out < U256::from(1_000));
+    }
+
+    #[test]
+    fn test_ln() {
+        assert_eq!(ln(ONE), 0);
+        assert_eq!(ln(2 * ONE), LN_2 as i128);
+        assert_eq!(ln(ONE / 2), -(LN_2 as i128));
+        // ln(10) = 2.302585092994045684
+        assert!((ln(10 * ONE) - 2_302_585_092_994_045_684).abs() <= 100);
+        // ln(0.8) = -0.223143551314209755
+        assert!((ln(ONE / 10 * 8) + 223_143_551_314_209_756).abs() <= 100);
+    }
+
+    #[test]
+    fn test_exp() {
+        assert_eq!(exp(0), ONE);
+        // e = 2.718281828459045235
+        assert!(exp(ONE as i128).abs_diff(2_718_281_828_459_045_235) <= 100);
+        // e^-1 = 0.367879441171442321
+        assert!(exp(-(ONE as i128)).abs_diff(367_879_441_171_442_321) <= 100);
+        assert_eq!(exp(-50 * ONE as i128), 0);
+        assert!(exp(MAX_EXP) > 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Exponent out of bounds")]
+    fn test_exp_overflow() {
+        exp(MAX_EXP + 1);
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(pow(3 * ONE, ONE), 3 * ONE);
+        assert_eq!(pow(3 * ONE, 0), ONE);
+        assert!(pow(3 * ONE, 2 * ONE).abs_diff(9 * ONE) <= 1_000);
+        assert!(pow(4 * ONE, ONE / 2).abs_diff(2 * ONE) <= 1_000);
+        // 0.9^4 = 0.6561
+        assert!(pow(ONE / 10 * 9, 4 * ONE).abs_diff(ONE / 10_000 * 6_561) <= 1_000);
+        assert!(pow_up(ONE / 10 * 9, 4 * ONE) > ONE / 10_000 * 6_561);
+    }
+
+    #[test]
+    fn test_weighted_get_amount_out() {
+        // equal weights are the constant product curve
+        assert_eq!(
+            weighted_get_amount_out(100_000, 1_000_000, 50, 1_000_000, 50),
+            get_amount_out(100_000, 1_000_000, 1_000_000, 0)
+        );
+        // 1_000_000 * (1 - (1 / 1.1)^4) = 316_986.5
+        assert_eq!(
+            weighted_get_amount_out(100_000, 1_000_000, 80, 1_000_000, 20),
+            316_986
+        );
+        // 1_000_000 * (1 - (1 / 1.1)^0.25) = 23_545.6
+        assert_eq!(
+            weighted_get_amount_out(100_000, 1_000_000, 20, 1_000_000, 80),
+            23_545
+        );
+        assert_eq!(weighted_get_amount_out(0, 1_000_000, 80, 1_000_000, 20), 0);
+    }
+
+    #[test]
+    fn test_weighted_get_amount_in() {
+        assert_eq!(
+            weighted_get_amount_in(316_986, 1_000_000, 80, 1_000_000, 20),
+            Some(100_000)
+        );
+        assert_eq!(
+            weighted_get_amount_in(23_545, 1_000_000, 20, 1_000_000, 80),
+            Some(99_996)
+        );
+        // 2^49 times the reserve does not fit
+        assert_eq!(
+            weighted_get_amount_in(1 << 95, 1 << 96, 200, 1 << 96, 9_800),
+            None
+        );
+        // neither does a base of 2^100, nor its power
+        assert_eq!(
+            weighted_get_amount_in((1 << 100) - 1, 1, 200, 1 << 100, 9_800),
+            None
+        );
+        assert_eq!(
+            weighted_get_amount_in((1 << 60) - 1, 1, 200, 1 << 60, 9_800),
+            None
+        );
+        // with the weights the other way around 2^(60 / 49) - 1 times the reserve is enough
+        assert!(
+            weighted_get_amount_in((1 << 60) - 1, 1 << 60, 9_800, 1 << 60, 200).unwrap() < 2 << 60
+        );
+    }
+
+    #[test]
+    fn test_weighted_invariant_holds() {
+        // 1_000_000 * (1 - (1 / 1.1)^4) = 316_986.5
+        assert!(weighted_invariant_holds(
+            (1_000_000, 1_100_000),
+            80,
+            (1_000_000, 1_000_000 - 316_986),
+            20
+        ));
+        assert!(!weighted_invariant_holds(
+            (1_000_000, 1_100_000),
+            80,
+            (1_000_000, 1_000_000 - 316_990),
+            20
+        ));
+        assert!(weighted_invariant_holds(
+            (1_000_000, 1_000_001),
+            80,
+            (1_000_000, 1_000_000),
+            20
+        ));
+        assert!(!weighted_invariant_holds(
+            (1_000_000, 1_100_000),
+            80,
+            (1_000_000, 0),
+            20
+        ));
+    }
+
+    #[test]
+    fn test_weighted_get_spot_amount_out() {
+        assert_eq!(
+            weighted_get_spot_amount_out(1_000, 1_000_000, 80, 1_000_000, 20),
+            4_000
+        );
+        assert_eq!(
+            weighted_get_spot_amount_out(1_000, 4_000_000, 80, 1_000_000, 20),
+            1_000
+        );
+    }
+
+    #[test]
+    fn test_sqrt_price_at_tick() {
+        assert_eq!(sqrt_price_at_tick(0), 1 << 64);
+        // sqrt(1.0001) = 1.0000499987500624960940234169938
+        assert_eq!(sqrt_price_at_tick(1), 18_447_666_387_855_959_851);
+        assert_eq!(sqrt_price_at_tick(-1), 18_445_821_805_675_392_312);
+        // sqrt(1.0001^443636) is just below 2^32
+        assert!(sqrt_price_at_tick(MAX_TICK) < 1 << 96);
+        assert!(sqrt_price_at_tick(MIN_TICK) > 1 << 32);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tick 443637 is out of bounds")]
+    fn test_sqrt_price_at_tick_out_of_bounds() {
+        sqrt_price_at_tick(MAX_TICK + 1);
+    }
+
+    #[test]
+    fn test_tick_at_sqrt_price() {
+        assert_eq!(tick_at_sqrt_price(1 << 64), 0);
+        assert_eq!(tick_at_sqrt_price((1 << 64) - 1), -1);
+        assert_eq!(tick_at_sqrt_price(sqrt_price_at_tick(MAX_TICK)), MAX_TICK);
+        assert_eq!(tick_at_sqrt_price(sqrt_price_at_tick(MIN_TICK)), MIN_TICK);
+    }
+
+    #[test]
+    fn test_liquidity_for_amounts() {
+        let (lower, upper) = (sqrt_price_at_tick(-1_000), sqrt_price_at_tick(1_000));
+        let liquidity = liquidity_for_amounts(1 << 64, lower, upper, 1_000_000, 1_000_000);
+        // 1_000_000 / (1 - 1.0001^-500) = 20_505_166.3
+        assert_eq!(liquidity, 20_505_166);
+        let (amount_a, amount_b) = amounts_for_liquidity(1 << 64, lower, upper, liquidity, true);
+        assert!(amount_a <= 1_000_000 && amount_a > 999_990);
+        assert_eq!(amount_a, amount_b);
+
+        // only token a is needed below the range and only token b above
+        assert_eq!(
+            amounts_for_liquidity(lower - 1, lower, upper, liquidity, true).1,
+            0
+        );
+        assert_eq!(
+            amounts_for_liquidity(upper + 1, lower, upper, liquidity, true).0,
+            0
+        );
+    }
+
+    #[test]
+    fn test_compute_swap_step() {
+        let (price, target) = (1 << 64, sqrt_price_at_tick(-1_000));
+        let liquidity = 20_505_166;
+        let step = compute_swap_step(price, target, liquidity, 10_000, 30);
+        assert!(step.sqrt_price < price && step.sqrt_price > target);
+        assert_eq!(step.amount_in + step.fee_amount, 10_000);
+        assert_eq!(step.fee_amount, 30);
+        // 20_505_166 * (1 - 1 / (1 + 9_970 / 20_505_166)) = 9_965.2
+        assert_eq!(step.amount_out, 9_965);
+
+        let step = compute_swap_step(price, target, liquidity, 10_000_000, 30);
+        assert_eq!(step.sqrt_price, target);
+        // all of token b of the range is swapped
+        assert!(step.amount_out <= 1_000_000 && step.amount_out > 999_990);
+        assert!(step.amount_in > step.amount_out);
+        assert_eq!(
+            step.fee_amount,
+            mul_div_ceil(step.amount_in, 30, FEE_DIVISOR as u128 - 30)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_tick_at_sqrt_price_inverse(tick in MIN_TICK..=MAX_TICK) {
+            let sqrt_price = sqrt_price_at_tick(tick);
+            prop_assert_eq!(tick_at_sqrt_price(sqrt_price), tick);
+            if tick < MAX_TICK {
+                prop_assert!(sqrt_price_at_tick(tick + 1) > sqrt_price);
+                prop_assert_eq!(tick_at_sqrt_price(sqrt_price_at_tick(tick + 1) - 1), tick);
+            }
+        }
+
+        #[test]
+        fn test_compute_swap_step_never_exceeds_amount(
+            tick in -100_000..100_000i32,
+            target_tick in -100_000..100_000i32,
+            liquidity in 1..u64::MAX as u128,
+            amount_remaining in 0..u64::MAX as u128,
+            fee in 0..FEE_DIVISOR / 2,
+        ) {
+            let (sqrt_price, sqrt_price_target) =
+                (sqrt_price_at_tick(tick), sqrt_price_at_tick(target_tick));
+            let step =
+                compute_swap_step(sqrt_price, sqrt_price_target, liquidity, amount_remaining, fee);
+            prop_assert!(step.amount_in + step.fee_amount <= amount_remaining);
+            // the liquidity covers the output between both prices
+            let (lower, upper) = if sqrt_price < step.sqrt_price {
+                (sqrt_price, step.sqrt_price)
+            } else {
+                (step.sqrt_price, sqrt_price)
+            };
+            let (amount_in, amount_out) = if tick >= target_tick {
+                (amount_a_delta(lower, upper, liquidity, false), amount_b_delta(lower, upper, liquidity, false))
+            } else {
+                (amount_b_delta(lower, upper, liquidity, false), amount_a_delta(lower, upper, liquidity, false))
+            };
+            prop_assert!(U256::from(step.amount_out) <= amount_out);
+            prop_assert!(U256::from(step.amount_in) >= amount_in);
+        }
+
+        #[test]
+        fn test_get_amount_in_inverse(
+            reserve_in in 1..u128::MAX / 4,
+            reserve_out in 2..u128::MAX / 4,
+            amount_out in 1..u128::MAX / 4,
+            fee in 0..FEE_DIVISOR / 2,
+        ) {
+            let amount_out = amount_out % (reserve_out - 1) + 1;
+            let amount_in =
+                U256::from(reserve_in) * U256::from(amount_out) / U256::from(reserve_out - amount_out);
+            prop_assume!(amount_in < U256::from(u128::MAX / 4));
+
+            let amount_in = get_amount_in(amount_out, reserve_in, reserve_out, fee);
+            prop_assert!(get_amount_out(amount_in, reserve_in, reserve_out, fee) >= amount_out);
+            if fee == 0 {
+                prop_assert!(get_amount_out(amount_in - 1, reserve_in, reserve_out, fee) < amount_out);
+            }
+        }
+
+        #[test]
+        fn test_get_amount_out_never_decreases_product(
+            reserve_in in 1..u128::MAX / 2,
+            reserve_out in 1..u128::MAX,
+            amount_in in 0..u128::MAX / 2,
+            fee in 0..FEE_DIVISOR,
+        ) {
+            let amount_out = get_amount_out(amount_in, reserve_in, reserve_out, fee);
+            prop_assert!(amount_out < reserve_out);
+
+            let prod = U256::from(reserve_in) * U256::from(reserve_out);
+            let new_prod =
+                U256::from(reserve_in + amount_in) * U256::from(reserve_out - amount_out);
+            prop_assert!(new_prod >= prod);
+        }
+
+        #[test]
+        fn test_get_amount_out_small_reserves(
+            reserve_in in 1..1_000_000u128,
+            reserve_out in 1..1_000_000u128,
+            amount_in in 0..1_000_000u128,
+        ) {
+            let amount_out = get_amount_out(amount_in, reserve_in, reserve_out, 0);
+            // exact result of the constant product formula, rounded down
+            prop_assert_eq!(amount_out, reserve_out * amount_in / (reserve_in + amount_in));
+            prop_assert!(
+                (reserve_in + amount_in) * (reserve_out - amount_out) >= reserve_in * reserve_out
+            );
+        }
+
+        #[test]
+        fn test_stable_swap_never_decreases_invariant(
+            amp in 1..1_000_000u64,
+            reserve_in in 1_000..u64::MAX as u128,
+            reserve_out in 1_000..u64::MAX as u128,
+            amount_in in 0..u64::MAX as u128,
+        ) {
+            let (reserve_in, reserve_out) = (U256::from(reserve_in), U256::from(reserve_out));
+            let amount_in = U256::from(amount_in);
+            let amount_out = stable_get_amount_out(amp, amount_in, reserve_in, reserve_out);
+            prop_assert!(amount_out < reserve_out);
+
+            let d = stable_invariant(amp, reserve_in, reserve_out);
+            let new_d = stable_invariant(amp, reserve_in + amount_in, reserve_out - amount_out);
+            prop_assert!(new_d >= d);
+        }
+
+        #[test]
+        fn test_stable_get_amount_in_inverse(
+            amp in 1..1_000_000u64,
+            reserve_in in 1_000..u64::MAX as u128,
+            reserve_out in 1_000..u64::MAX as u128,
+            amount_out in 1..u64::MAX as u128,
+        ) {
+            let amount_out = U256::from(amount_out % (reserve_out - 1) + 1);
+            let (reserve_in, reserve_out) = (U256::from(reserve_in), U256::from(reserve_out));
+            let amount_in = stable_get_amount_in(amp, amount_out, reserve_in, reserve_out);
+            prop_assume!(amount_in < U256::from(u64::MAX) * 1_000);
+
+            prop_assert!(
+                stable_get_amount_out(amp, amount_in, reserve_in, reserve_out) >= amount_out
+            );
+        }
+
+        #[test]
+        fn test_weighted_get_amount_out_matches_float(
+            reserve_in in 1_000..u64::MAX as u128,
+            reserve_out in 1_000..u64::MAX as u128,
+            amount_in in 0..u64::MAX as u128,
+            weight_in in 200..9_800u32,
+        ) {
+            let weight_out = 10_000 - weight_in;
+            let amount_out =
+                weighted_get_amount_out(amount_in, reserve_in, weight_in, reserve_out, weight_out);
+            let ratio = reserve_in as f64 / (reserve_in as f64 + amount_in as f64);
+            let expected =
+                reserve_out as f64 * (1. - ratio.powf(weight_in as f64 / weight_out as f64));
+            prop_assert!(amount_out < reserve_out);
+            prop_assert!(amount_out as f64 <= expected * (1. + 1e-9) + 1.);
+            prop_assert!(amount_out as f64 >= expected * (1. - 1e-9) - reserve_out as f64 * 1e-12 - 1.);
+        }
+
+        #[test]
+        fn test_weighted_get_amount_in_matches_float(
+            reserve_in in 1_000..u64::MAX as u128,
+            reserve_out in 1_000..u64::MAX as u128,
+            amount_out in 1..u64::MAX as u128,
+            weight_in in 200..9_800u32,
+        ) {
+            let weight_out = 10_000 - weight_in;
+            let amount_out = amount_out % (reserve_out / 2) + 1;
+            let amount_in =
+                weighted_get_amount_in(amount_out, reserve_in, weight_in, reserve_out, weight_out)
+                    .unwrap();
+            let ratio = reserve_out as f64 / (reserve_out as f64 - amount_out as f64);
+            let expected =
+                reserve_in as f64 * (ratio.powf(weight_out as f64 / weight_in as f64) - 1.);
+            prop_assert!(amount_in as f64 >= expected * (1. - 1e-9) - 1.);
+            prop_assert!(amount_in as f64 <= expected * (1. + 1e-9) + reserve_in as f64 * 1e-12 + 1.);
+        }
+
+        #[test]
+        fn test_weighted_swaps_keep_invariant(
+            reserve_in in 1_000..u128::MAX >> 32,
+            reserve_out in 1_000..u128::MAX >> 32,
+            amount in 1..u128::MAX >> 32,
+            weight_in in 200..9_800u32,
+        ) {
+            let weight_out = 10_000 - weight_in;
+            let amount_in = amount % reserve_in + 1;
+            let amount_out =
+                weighted_get_amount_out(amount_in, reserve_in, weight_in, reserve_out, weight_out);
+            prop_assert!(weighted_invariant_holds(
+                (reserve_in, reserve_in + amount_in),
+                weight_in,
+                (reserve_out, reserve_out - amount_out),
+                weight_out,
+            ));
+            let amount_out = amount % (reserve_out / 2) + 1;
+            // large outputs need more input than fits for extreme weights
+            let new_reserve_in =
+                weighted_get_amount_in(amount_out, reserve_in, weight_in, reserve_out, weight_out)
+                    .and_then(|amount_in| reserve_in.checked_add(amount_in));
+            if let Some(new_reserve_in) = new_reserve_in {
+                prop_assert!(weighted_invariant_holds(
+                    (reserve_in, new_reserve_in),
+                    weight_in,
+                    (reserve_out, reserve_out - amount_out),
+                    weight_out,
+                ));
+            }
+        }
+
+        #[test]
+        fn test_weighted_equal_weights_is_constant_product(
+            reserve_in in 1_000..u64::MAX as u128,
+            reserve_out in 1_000..u64::MAX as u128,
+            amount_in in 0..u64::MAX as u128,
+        ) {
+            let amount_out = weighted_get_amount_out(amount_in, reserve_in, 50, reserve_out, 50);
+            let expected = get_amount_out(amount_in, reserve_in, reserve_out, 0);
+            prop_assert!(amount_out <= expected);
+            prop_assert!(amount_out + reserve_out / 10u128.pow(13) + 1 >= expected);
+        }
+    }
+}
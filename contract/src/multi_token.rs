@@ -0,0 +1,389 @@
+use crate::{
+    events::{MtBurn, MtMint, MtTransfer},
+    *,
+};
+use near_sdk::assert_one_yocto;
+
+const GAS_FOR_MT_ON_TRANSFER: Gas = Gas(50_000_000_000_000);
+const GAS_FOR_RESOLVE_MT_TRANSFER: Gas = Gas(20_000_000_000_000);
+
+pub const MT_METADATA_SPEC: &str = "mt-1.0.0";
+
+/// Approvals of a token, that were cleared by a transfer, as `(account_id, approval_id, amount)`.
+type ClearedApprovals = Option<Vec<(AccountId, u64, u64)>>;
+
+#[ext_contract]
+pub trait ExtMultiTokenReceiver {
+    fn mt_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_ids: Vec<AccountId>,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>>;
+}
+
+/// Liquidity shares of the pools as NEP-245 multi tokens, where the token id is the pool id.
+/// Approvals are not supported.
+#[near_bindgen]
+impl OrderlyContract {
+    /// Transfers shares of the caller to `receiver_id`, who needs to be registered
+    /// with `storage_deposit`.
+    #[payable]
+    pub fn mt_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        amount: U128,
+        approval: Option<(AccountId, u64)>,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        assert!(approval.is_none(), "Approvals are not supported");
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer_shares(
+            &sender_id,
+            &receiver_id,
+            &[token_id],
+            &[amount],
+            memo.as_deref(),
+        );
+    }
+
+    #[payable]
+    pub fn mt_batch_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        approvals: Option<Vec<Option<(AccountId, u64)>>>,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        assert!(approvals.is_none(), "Approvals are not supported");
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer_shares(
+            &sender_id,
+            &receiver_id,
+            &token_ids,
+            &amounts,
+            memo.as_deref(),
+        );
+    }
+
+    /// Transfers shares of the caller to the contract `receiver_id` and calls its
+    /// `mt_on_transfer`. The amounts it returns as unused are transferred back.
+    #[payable]
+    pub fn mt_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        amount: U128,
+        approval: Option<(AccountId, u64)>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>> {
+        assert_one_yocto();
+        assert!(approval.is_none(), "Approvals are not supported");
+        self.internal_transfer_shares_call(receiver_id, vec![token_id], vec![amount], memo, msg)
+    }
+
+    #[payable]
+    pub fn mt_batch_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        approvals: Option<Vec<Option<(AccountId, u64)>>>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>> {
+        assert_one_yocto();
+        assert!(approvals.is_none(), "Approvals are not supported");
+        self.internal_transfer_shares_call(receiver_id, token_ids, amounts, memo, msg)
+    }
+
+    /// Transfers the unused amounts back to the sender after `mt_on_transfer`
+    /// and returns the amounts, that were used by the receiver.
+    #[private]
+    pub fn mt_resolve_transfer(
+        &mut self,
+        previous_owner_ids: Vec<AccountId>,
+        receiver_id: AccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        approvals: Option<Vec<ClearedApprovals>>,
+    ) -> Vec<U128> {
+        assert!(approvals.is_none(), "Approvals are not supported");
+        let unused_amounts = match env::promise_result(0) {
+            PromiseResult::Successful(value) => serde_json::from_slice::<Vec<U128>>(&value)
+                .ok()
+                .filter(|unused_amounts| unused_amounts.len() == amounts.len())
+                .unwrap_or_else(|| amounts.clone()),
+            _ => amounts.clone(),
+        };
+        let sender_id = &previous_owner_ids[0];
+        token_ids
+            .iter()
+            .zip(amounts)
+            .zip(unused_amounts)
+            .map(|((token_id, amount), unused_amount)| {
+                let pool_id = parse_token_id(token_id);
+                let mut pool = self.internal_get_pool(pool_id);
+                // the receiver might have transferred the shares in the meantime
+                let refund = std::cmp::min(
+                    std::cmp::min(unused_amount.0, amount.0),
+                    pool.shares.accounts.get(&receiver_id).unwrap_or_default(),
+                );
+                if refund > 0 {
+                    self.internal_burn_shares(&mut pool, &receiver_id, refund);
+                    self.internal_mint_shares(&mut pool, sender_id, refund);
+                    MtTransfer {
+                        old_owner_id: &receiver_id,
+                        new_owner_id: sender_id,
+                        token_ids: &[token_id],
+                        amounts: &[refund.into()],
+                        memo: Some("refund"),
+                    }
+                    .emit();
+                    self.pools.replace(pool_id, &pool);
+                }
+                (amount.0 - refund).into()
+            })
+            .collect()
+    }
+
+    /// Returns the pools of the given token ids, which are owned by no single account.
+    pub fn mt_token(&self, token_ids: Vec<String>) -> Vec<Option<Token>> {
+        token_ids
+            .into_iter()
+            .map(|token_id| {
+                token_id
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|pool_id| *pool_id < self.pools.len())
+                    .map(|_| Token {
+                        token_id,
+                        owner_id: None,
+                    })
+            })
+            .collect()
+    }
+
+    pub fn mt_balance_of(&self, account_id: AccountId, token_id: String) -> U128 {
+        self.internal_get_pool(parse_token_id(&token_id))
+            .shares
+            .accounts
+            .get(&account_id)
+            .unwrap_or_default()
+            .into()
+    }
+
+    pub fn mt_batch_balance_of(&self, account_id: AccountId, token_ids: Vec<String>) -> Vec<U128> {
+        token_ids
+            .into_iter()
+            .map(|token_id| self.mt_balance_of(account_id.clone(), token_id))
+            .collect()
+    }
+
+    pub fn mt_supply(&self, token_id: String) -> Option<U128> {
+        token_id
+            .parse::<u64>()
+            .ok()
+            .and_then(|pool_id| self.pools.get(pool_id))
+            .map(|pool| pool.shares.total_supply.into())
+    }
+
+    pub fn mt_batch_supply(&self, token_ids: Vec<String>) -> Vec<Option<U128>> {
+        token_ids
+            .into_iter()
+            .map(|token_id| self.mt_supply(token_id))
+            .collect()
+    }
+
+    pub fn mt_metadata_contract(&self) -> MtContractMetadata {
+        MtContractMetadata {
+            spec: MT_METADATA_SPEC.to_string(),
+            name: "Orderly LP".to_string(),
+        }
+    }
+
+    /// Returns the metadata of the liquidity shares of the given pools.
+    pub fn mt_metadata_base_by_token_id(
+        &self,
+        token_ids: Vec<String>,
+    ) -> Vec<Option<MtBaseTokenMetadata>> {
+        token_ids
+            .into_iter()
+            .map(|token_id| {
+                let pool = token_id
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|pool_id| self.pools.get(pool_id))?;
+                Some(pool.share_metadata(token_id))
+            })
+            .collect()
+    }
+}
+
+impl OrderlyContract {
+    /// Mints shares of `pool` to `account_id`. Callers, that can refuse the shares,
+    /// need to check afterwards, that the storage of the account is covered.
+    pub(crate) fn internal_mint_shares(
+        &mut self,
+        pool: &mut Pool,
+        account_id: &AccountId,
+        shares: u128,
+    ) {
+        let initial_storage = env::storage_usage();
+        let created = !pool.shares.accounts.contains_key(account_id);
+        pool.mint_shares(account_id, shares);
+        self.internal_update_storage(account_id, initial_storage);
+        if created {
+            self.internal_update_entries(account_id, true);
+        }
+    }
+
+    pub(crate) fn internal_burn_shares(
+        &mut self,
+        pool: &mut Pool,
+        account_id: &AccountId,
+        shares: u128,
+    ) {
+        let initial_storage = env::storage_usage();
+        pool.burn_shares(account_id, shares);
+        self.internal_update_storage(account_id, initial_storage);
+        if !pool.shares.accounts.contains_key(account_id) {
+            self.internal_update_entries(account_id, false);
+        }
+    }
+
+    fn internal_transfer_shares(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        token_ids: &[String],
+        amounts: &[U128],
+        memo: Option<&str>,
+    ) {
+        assert_ne!(
+            sender_id, receiver_id,
+            "Sender and receiver should be different"
+        );
+        assert!(!token_ids.is_empty(), "Token ids must not be empty");
+        assert_eq!(
+            token_ids.len(),
+            amounts.len(),
+            "Token ids and amounts must have the same length"
+        );
+        assert!(
+            self.is_registered(receiver_id),
+            "Account {} is not registered with storage_deposit",
+            receiver_id
+        );
+        for (token_id, amount) in token_ids.iter().zip(amounts) {
+            assert!(amount.0 > 0, "Shares must be greater than zero");
+            let pool_id = parse_token_id(token_id);
+            let mut pool = self.internal_get_pool(pool_id);
+            self.internal_burn_shares(&mut pool, sender_id, amount.0);
+            self.internal_mint_shares(&mut pool, receiver_id, amount.0);
+            self.pools.replace(pool_id, &pool);
+        }
+        self.assert_storage_covered(receiver_id);
+        MtTransfer {
+            old_owner_id: sender_id,
+            new_owner_id: receiver_id,
+            token_ids: &token_ids.iter().map(String::as_str).collect::<Vec<_>>(),
+            amounts,
+            memo,
+        }
+        .emit();
+    }
+
+    fn internal_transfer_shares_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_ids: Vec<String>,
+        amounts: Vec<U128>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<Vec<U128>> {
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer_shares(
+            &sender_id,
+            &receiver_id,
+            &token_ids,
+            &amounts,
+            memo.as_deref(),
+        );
+        ext_multi_token_receiver::ext(receiver_id.clone())
+            .with_static_gas(GAS_FOR_MT_ON_TRANSFER)
+            .mt_on_transfer(
+                sender_id.clone(),
+                vec![sender_id.clone()],
+                token_ids.clone(),
+                amounts.clone(),
+                msg,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_MT_TRANSFER)
+                    .mt_resolve_transfer(vec![sender_id], receiver_id, token_ids, amounts, None),
+            )
+            .into()
+    }
+}
+
+/// Emits the event for minting liquidity shares of `pool_id`.
+pub(crate) fn emit_mint(pool_id: u64, owner_id: &AccountId, shares: u128) {
+    MtMint {
+        owner_id,
+        token_ids: &[&pool_id.to_string()],
+        amounts: &[shares.into()],
+        memo: None,
+    }
+    .emit();
+}
+
+/// Emits the event for burning liquidity shares of `pool_id`.
+pub(crate) fn emit_burn(pool_id: u64, owner_id: &AccountId, shares: u128) {
+    MtBurn {
+        owner_id,
+        token_ids: &[&pool_id.to_string()],
+        amounts: &[shares.into()],
+        memo: None,
+    }
+    .emit();
+}
+
+fn parse_token_id(token_id: &str) -> u64 {
+    token_id
+        .parse()
+        .unwrap_or_else(|_| panic!("Invalid token id {}", token_id))
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub token_id: String,
+    pub owner_id: Option<AccountId>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct MtContractMetadata {
+    pub spec: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
+pub struct MtBaseTokenMetadata {
+    pub name: String,
+    pub id: String,
+    pub symbol: Option<String>,
+    pub icon: Option<String>,
+    pub decimals: Option<String>,
+    pub base_uri: Option<String>,
+    pub reference: Option<String>,
+    pub copies: Option<u64>,
+    pub reference_hash: Option<String>,
+}
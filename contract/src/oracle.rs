@@ -0,0 +1,207 @@
+use crate::{math::mul_div, *};
+use near_sdk::{Balance, StorageUsage};
+
+/// Number of observations, that are kept per pool. Older observations are overwritten.
+pub const MAX_OBSERVATIONS: u64 = 1_000;
+/// Storage in bytes of the observations of a pool, once the ring buffer is full.
+/// Each observation has a key of 17 bytes with the index, a value of 40 bytes
+/// and 40 bytes of overhead for the record.
+pub const OBSERVATIONS_STORAGE: StorageUsage = MAX_OBSERVATIONS * (17 + 40 + 40);
+
+/// Accumulators of the spot prices of a pool, from which time-weighted average prices
+/// are computed. The prices are multiplied by [`PRICE_DIVISOR`].
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct Oracle {
+    /// Sums of the spot prices times the seconds they were valid, which are allowed to overflow.
+    pub price_a_cumulative: u128,
+    pub price_b_cumulative: u128,
+    /// Spot prices of token a in token b and vice versa since the last update.
+    pub price_a: u128,
+    pub price_b: u128,
+    /// Block timestamp in seconds of the last update.
+    pub timestamp: u64,
+    /// Ring buffer of the accumulators, which has at most one observation per second.
+    pub observations: Vector<Observation>,
+    /// Index of the next observation, that gets overwritten once the buffer is full.
+    pub next_index: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct Observation {
+    pub timestamp: u64,
+    pub price_a_cumulative: u128,
+    pub price_b_cumulative: u128,
+}
+
+impl Oracle {
+    pub fn new(storage_key: StorageKey) -> Self {
+        Self {
+            price_a_cumulative: 0,
+            price_b_cumulative: 0,
+            price_a: 0,
+            price_b: 0,
+            timestamp: 0,
+            observations: Vector::new(storage_key.try_to_vec().unwrap()),
+            next_index: 0,
+        }
+    }
+
+    /// Accumulates the previous prices until `timestamp` and sets the new prices.
+    pub fn update(&mut self, price_a: u128, price_b: u128, timestamp: u64) {
+        if timestamp > self.timestamp || self.observations.is_empty() {
+            let elapsed = (timestamp - self.timestamp) as u128;
+            self.price_a_cumulative = self
+                .price_a_cumulative
+                .wrapping_add(self.price_a.wrapping_mul(elapsed));
+            self.price_b_cumulative = self
+                .price_b_cumulative
+                .wrapping_add(self.price_b.wrapping_mul(elapsed));
+            self.timestamp = timestamp;
+
+            let observation = Observation {
+                timestamp,
+                price_a_cumulative: self.price_a_cumulative,
+                price_b_cumulative: self.price_b_cumulative,
+            };
+            if self.observations.len() < MAX_OBSERVATIONS {
+                self.observations.push(&observation);
+            } else {
+                self.observations.replace(self.next_index, &observation);
+            }
+            self.next_index = (self.next_index + 1) % MAX_OBSERVATIONS;
+        }
+        self.price_a = price_a;
+        self.price_b = price_b;
+    }
+
+    /// Returns the time-weighted average price of token a, or of token b if `is_a` is false,
+    /// over the last `window` seconds before `timestamp`.
+    pub fn twap(&self, is_a: bool, window: u64, timestamp: u64) -> u128 {
+        assert!(window > 0, "Window must be greater than zero");
+        let start = timestamp
+            .checked_sub(window)
+            .expect("Not enough observations for the window");
+        let cumulative = self.cumulative_at(is_a, timestamp);
+        cumulative.wrapping_sub(self.cumulative_at(is_a, start)) / window as u128
+    }
+
+    /// Returns the observation, that is `index` positions after the oldest one.
+    fn observation(&self, index: u64) -> Observation {
+        let len = self.observations.len();
+        let oldest = if len < MAX_OBSERVATIONS {
+            0
+        } else {
+            self.next_index
+        };
+        self.observations.get((oldest + index) % len).unwrap()
+    }
+
+    /// Returns the accumulator at `timestamp`, which is interpolated between the observations.
+    /// The prices are constant between two observations, so the interpolation is exact.
+    fn cumulative_at(&self, is_a: bool, timestamp: u64) -> u128 {
+        let cumulative = |observation: &Observation| {
+            if is_a {
+                observation.price_a_cumulative
+            } else {
+                observation.price_b_cumulative
+            }
+        };
+        let len = self.observations.len();
+        assert!(
+            len > 0 && self.observation(0).timestamp <= timestamp,
+            "Not enough observations for the window"
+        );
+        // binary search for the last observation at or before `timestamp`
+        let (mut low, mut high) = (0, len - 1);
+        while low < high {
+            let mid = high - (high - low) / 2;
+            if self.observation(mid).timestamp <= timestamp {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        let before = self.observation(low);
+        let elapsed = (timestamp - before.timestamp) as u128;
+        if low == len - 1 {
+            let price = if is_a { self.price_a } else { self.price_b };
+            return cumulative(&before).wrapping_add(price.wrapping_mul(elapsed));
+        }
+        let after = self.observation(low + 1);
+        let diff = cumulative(&after).wrapping_sub(cumulative(&before));
+        cumulative(&before).wrapping_add(mul_div(
+            diff,
+            elapsed,
+            (after.timestamp - before.timestamp) as u128,
+        ))
+    }
+}
+
+#[near_bindgen]
+impl OrderlyContract {
+    /// Returns the deposit, that `add_pool` and `add_concentrated_pool` require
+    /// for the storage of the observations of a pool.
+    pub fn get_pool_deposit(&self) -> U128 {
+        observations_deposit().into()
+    }
+
+    /// Returns the time-weighted average price of `token_id` in the other token of a pool
+    /// over the last `window_seconds`, multiplied by [`PRICE_DIVISOR`].
+    /// Panics, if the observations of the pool do not cover the window.
+    pub fn get_twap(&self, pool_id: u64, token_id: AccountId, window_seconds: u64) -> U128 {
+        let pool = self.internal_get_pool(pool_id);
+        let is_a = pool.pairs(&token_id).0.account_id == pool.token_a.account_id;
+        pool.oracle
+            .twap(is_a, window_seconds, timestamp_seconds())
+            .into()
+    }
+
+    /// Returns the time-weighted average price of `token_id` in the other token of a
+    /// concentrated pool over the last `window_seconds`, multiplied by [`PRICE_DIVISOR`].
+    /// Panics, if the observations of the pool do not cover the window.
+    pub fn get_concentrated_twap(
+        &self,
+        pool_id: u64,
+        token_id: AccountId,
+        window_seconds: u64,
+    ) -> U128 {
+        let pool = self.internal_get_concentrated_pool(pool_id);
+        let is_a = pool.pairs(&token_id).0.account_id == pool.token_a.account_id;
+        pool.oracle
+            .twap(is_a, window_seconds, timestamp_seconds())
+            .into()
+    }
+}
+
+impl OrderlyContract {
+    /// Keeps the deposit for the storage of the observations of a new pool
+    /// from the attached deposit and refunds the rest to the caller.
+    pub(crate) fn internal_reserve_observations(&self) {
+        let deposit = env::attached_deposit();
+        let required_deposit = observations_deposit();
+        assert!(
+            deposit >= required_deposit,
+            "Attached deposit of {} is less than the required {} for the observations of the pool",
+            deposit,
+            required_deposit
+        );
+        if deposit > required_deposit {
+            Promise::new(env::predecessor_account_id()).transfer(deposit - required_deposit);
+        }
+    }
+
+    /// Refunds the deposit for the observations of a pool, that was not added, to the owner.
+    pub(crate) fn internal_refund_observations(&self) {
+        Promise::new(self.owner.clone()).transfer(observations_deposit());
+    }
+}
+
+/// Returns the deposit, that covers the storage of the observations of a pool.
+pub fn observations_deposit() -> Balance {
+    Balance::from(OBSERVATIONS_STORAGE) * env::storage_byte_cost()
+}
+
+/// Returns the block timestamp in seconds.
+pub(crate) fn timestamp_seconds() -> u64 {
+    env::block_timestamp() / 1_000_000_000
+}
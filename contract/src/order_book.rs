@@ -0,0 +1,418 @@
+use crate::{math::mul_div_ceil, *};
+use near_sdk::{assert_one_yocto, collections::TreeMap};
+
+/// Prices of limit orders are fixed-point numbers with 18 decimals.
+pub const PRICE_DIVISOR: u128 = 1_000_000_000_000_000_000;
+/// Orders of smaller amounts are refunded. Besides that each order pays for its storage,
+/// so that the order books cannot be filled with dust orders for free.
+pub const MIN_ORDER_AMOUNT: u128 = 100;
+/// Maximum number of orders, that are filled by a single swap. The remaining amount
+/// is swapped in the pool, so that many small orders cannot block swaps.
+pub const MAX_FILLS_PER_SWAP: usize = 20;
+
+/// Resting order, that sells a token of a pool for the other token at a minimum price.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct Order {
+    pub owner_id: AccountId,
+    pub pool_id: u64,
+    /// Token, that is sold by the order.
+    pub token_id: AccountId,
+    /// Amount of the other token per sold token multiplied by [`PRICE_DIVISOR`].
+    pub price: u128,
+    /// Amount of the sold token, that has not been filled yet.
+    pub amount: u128,
+    /// Amount of the sold token, when the order was placed.
+    pub original_amount: u128,
+}
+
+/// Orders, that would be filled by a swap, but have not been written yet.
+#[derive(Default)]
+pub(crate) struct OrderFills {
+    pub fills: Vec<Fill>,
+    /// Sum of the input of all fills.
+    pub amount_in: u128,
+    /// Sum of the output of all fills.
+    pub amount_out: u128,
+}
+
+pub(crate) struct Fill {
+    pub order_id: u64,
+    /// Amount of the swapped token, that is paid to the owner of the order.
+    pub amount_in: u128,
+    /// Amount of the sold token, that is taken from the order.
+    pub amount_out: u128,
+}
+
+#[near_bindgen]
+impl OrderlyContract {
+    /// Cancels an order of the caller and transfers the unfilled amount back.
+    /// If the transfer fails, the amount is credited to the deposits of the caller.
+    #[payable]
+    pub fn cancel_order(&mut self, order_id: U64) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let order = self
+            .orders
+            .get(&order_id.0)
+            .unwrap_or_else(|| panic!("Order {} does not exist", order_id.0));
+        assert_eq!(
+            order.owner_id, account_id,
+            "Order {} does not belong to {}",
+            order_id.0, account_id
+        );
+        self.internal_remove_order(order_id.0, &order);
+        log!(
+            "User {} cancelled order {} with {} of token {} left",
+            account_id,
+            order_id.0,
+            order.amount,
+            order.token_id
+        );
+        self.transfer_with_fallback(&account_id, order.token_id, order.amount)
+    }
+
+    pub fn get_order(&self, order_id: U64) -> Option<OrderInfo> {
+        self.orders
+            .get(&order_id.0)
+            .map(|order| order_info(order_id.0, order))
+    }
+
+    /// Returns all open orders of `account_id`.
+    pub fn get_orders(&self, account_id: AccountId) -> Vec<OrderInfo> {
+        self.account_orders
+            .get(&account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|order_id| order_info(order_id, self.orders.get(&order_id).unwrap()))
+            .collect()
+    }
+
+    /// Returns up to `limit` price levels of the orders, that sell `token_id` in a pool,
+    /// starting at the lowest price, which gets filled first.
+    pub fn get_price_levels(
+        &self,
+        pool_id: u64,
+        token_id: AccountId,
+        limit: u64,
+    ) -> Vec<PriceLevel> {
+        self.order_books
+            .get(&(pool_id, token_id))
+            .map(|levels| {
+                levels
+                    .iter()
+                    .take(limit as usize)
+                    .map(|(price, order_ids)| PriceLevel {
+                        price: price.into(),
+                        amount: order_ids
+                            .iter()
+                            .map(|order_id| self.orders.get(order_id).unwrap().amount)
+                            .sum::<u128>()
+                            .into(),
+                        order_count: order_ids.len() as u64,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the orders, that sell `token_id` in a pool at `price`, in the order they get filled.
+    pub fn get_orders_at_price(
+        &self,
+        pool_id: u64,
+        token_id: AccountId,
+        price: U128,
+    ) -> Vec<OrderInfo> {
+        self.order_books
+            .get(&(pool_id, token_id))
+            .and_then(|levels| levels.get(&price.0))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|order_id| order_info(order_id, self.orders.get(&order_id).unwrap()))
+            .collect()
+    }
+}
+
+impl OrderlyContract {
+    pub(crate) fn internal_place_order(
+        &mut self,
+        sender_id: AccountId,
+        token_in: AccountId,
+        amount: U128,
+        transfer_msg: TransferMsg,
+    ) -> PromiseOrValue<U128> {
+        let limit_order = transfer_msg.limit_order.unwrap();
+        if transfer_msg.pool_id.is_some() || transfer_msg.route.is_some() {
+            log!("Limit order cannot be combined with a swap");
+            return PromiseOrValue::Value(amount);
+        }
+        match self.pools.get(limit_order.pool_id) {
+            Some(pool) if pool.contains(&token_in) => {}
+            Some(_) => {
+                log!("Deposited token address does not belong to liquidity pool");
+                return PromiseOrValue::Value(amount);
+            }
+            None => {
+                log!("Pool {} does not exist", limit_order.pool_id);
+                return PromiseOrValue::Value(amount);
+            }
+        }
+        if limit_order.price.0 == 0 {
+            log!("Price of a limit order must be greater than zero");
+            return PromiseOrValue::Value(amount);
+        }
+        if amount.0 < MIN_ORDER_AMOUNT {
+            log!(
+                "Amount of a limit order must be at least {}",
+                MIN_ORDER_AMOUNT
+            );
+            return PromiseOrValue::Value(amount);
+        }
+        if !self.is_registered(&sender_id) {
+            log!(
+                "Account {} is not registered with storage_deposit",
+                sender_id
+            );
+            return PromiseOrValue::Value(amount);
+        }
+
+        let initial_storage = env::storage_usage();
+        let order_id = self.next_order_id;
+        self.next_order_id += 1;
+        let key = (limit_order.pool_id, token_in.clone());
+        let mut levels = self.order_books.get(&key).unwrap_or_else(|| {
+            TreeMap::new(
+                StorageKey::PriceLevels {
+                    pool_id: limit_order.pool_id,
+                    token_id: token_in.clone(),
+                }
+                .try_to_vec()
+                .unwrap(),
+            )
+        });
+        let mut order_ids = levels.get(&limit_order.price.0).unwrap_or_default();
+        order_ids.push(order_id);
+        levels.insert(&limit_order.price.0, &order_ids);
+        self.order_books.insert(&key, &levels);
+        self.orders.insert(
+            &order_id,
+            &Order {
+                owner_id: sender_id.clone(),
+                pool_id: limit_order.pool_id,
+                token_id: token_in.clone(),
+                price: limit_order.price.0,
+                amount: amount.0,
+                original_amount: amount.0,
+            },
+        );
+        let mut order_ids = self.account_orders.get(&sender_id).unwrap_or_default();
+        order_ids.push(order_id);
+        self.account_orders.insert(&sender_id, &order_ids);
+        self.internal_update_storage(&sender_id, initial_storage);
+        // panicking refunds the transfer
+        self.assert_storage_covered(&sender_id);
+        log!(
+            "User {} placed order {} selling {} of token {} at price {} in pool {}",
+            sender_id,
+            order_id,
+            amount.0,
+            token_in,
+            limit_order.price.0,
+            limit_order.pool_id
+        );
+        PromiseOrValue::Value(0.into())
+    }
+
+    /// Matches `amount_in` of `token_in` with the orders of a pool, that sell the other token.
+    /// The amount is split between the orders and the pool, so that the orders are filled
+    /// starting at the lowest price, as long as their price is below the marginal price of the
+    /// pool after swapping the rest of the amount in it. The marginal price includes the fee.
+    /// At most [`MAX_FILLS_PER_SWAP`] orders are filled.
+    pub(crate) fn internal_match_orders(
+        &self,
+        pool_id: u64,
+        pool: &Pool,
+        token_in: &AccountId,
+        amount_in: u128,
+    ) -> OrderFills {
+        let (in_pair, out_pair) = pool.pairs(token_in);
+        let levels = match self
+            .order_books
+            .get(&(pool_id, out_pair.account_id.clone()))
+        {
+            Some(levels) => levels,
+            None => return OrderFills::default(),
+        };
+        let orders: Vec<_> = levels
+            .iter()
+            .flat_map(|(price, order_ids)| {
+                order_ids.into_iter().map(move |order_id| (order_id, price))
+            })
+            .take(MAX_FILLS_PER_SWAP)
+            .map(|(order_id, price)| (order_id, price, self.orders.get(&order_id).unwrap().amount))
+            .collect();
+        if in_pair.supply.0 == 0 || out_pair.supply.0 == 0 {
+            return fill_orders(&orders, amount_in, u128::MAX);
+        }
+
+        // the pool gets more expensive the more is swapped in it, so the smallest part for the
+        // pool is searched, for which the orders below its marginal price can take the rest
+        let (mut pool_in_pair, mut pool_out_pair) = (in_pair.clone(), out_pair.clone());
+        let mut pool_price = |pool_amount_in: u128| {
+            let amount_out = pool
+                .curve
+                .get_amount_out(pool_amount_in, in_pair, out_pair, pool.fee);
+            pool_in_pair.supply.0 = in_pair.supply.0 + pool_amount_in;
+            pool_out_pair.supply.0 = out_pair.supply.0 - amount_out;
+            if pool_out_pair.supply.0 == 0 {
+                return u128::MAX;
+            }
+            let spot_price = pool.curve.get_spot_price(&pool_out_pair, &pool_in_pair);
+            std::cmp::min(
+                U256::from(spot_price) * U256::from(FEE_DIVISOR)
+                    / U256::from(FEE_DIVISOR - pool.fee),
+                U256::from(u128::MAX),
+            )
+            .as_u128()
+        };
+        let orders_amount_in = |pool_price: u128| -> U256 {
+            orders
+                .iter()
+                .take_while(|(_, price, _)| *price < pool_price)
+                .map(|(_, price, amount)| U256::from(mul_div_ceil(*amount, *price, PRICE_DIVISOR)))
+                .fold(U256::zero(), |sum, amount_in| sum + amount_in)
+        };
+        let (mut low, mut high) = (0, amount_in);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if U256::from(mid) + orders_amount_in(pool_price(mid)) >= U256::from(amount_in) {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        fill_orders(&orders, amount_in - low, pool_price(low))
+    }
+
+    /// Writes the fills of a swap and credits the input to the deposits of the order owners.
+    pub(crate) fn internal_fill_orders(&mut self, token_in: &AccountId, fills: OrderFills) {
+        for fill in fills.fills {
+            let mut order = self.orders.get(&fill.order_id).unwrap();
+            order.amount -= fill.amount_out;
+            self.internal_deposit(&order.owner_id, token_in, fill.amount_in);
+            log!(
+                "Order {} of user {} filled {} of token {} for {} of token {}",
+                fill.order_id,
+                order.owner_id,
+                fill.amount_out,
+                order.token_id,
+                fill.amount_in,
+                token_in
+            );
+            if order.amount == 0 {
+                self.internal_remove_order(fill.order_id, &order);
+            } else {
+                self.orders.insert(&fill.order_id, &order);
+            }
+        }
+    }
+
+    /// Removes an order and releases its storage to the owner.
+    fn internal_remove_order(&mut self, order_id: u64, order: &Order) {
+        let initial_storage = env::storage_usage();
+        let key = (order.pool_id, order.token_id.clone());
+        let mut levels = self.order_books.get(&key).unwrap();
+        let mut order_ids = levels.get(&order.price).unwrap();
+        order_ids.retain(|id| id != &order_id);
+        if order_ids.is_empty() {
+            levels.remove(&order.price);
+        } else {
+            levels.insert(&order.price, &order_ids);
+        }
+        if levels.is_empty() {
+            self.order_books.remove(&key);
+        } else {
+            self.order_books.insert(&key, &levels);
+        }
+        self.orders.remove(&order_id);
+
+        let mut order_ids = self.account_orders.get(&order.owner_id).unwrap_or_default();
+        order_ids.retain(|id| id != &order_id);
+        if order_ids.is_empty() {
+            self.account_orders.remove(&order.owner_id);
+        } else {
+            self.account_orders.insert(&order.owner_id, &order_ids);
+        }
+        self.internal_update_storage(&order.owner_id, initial_storage);
+    }
+}
+
+/// Fills the orders, whose price is below `max_price`, with `amount_in` starting
+/// at the first one. The last filled order can be filled partially.
+fn fill_orders(orders: &[(u64, u128, u128)], amount_in: u128, max_price: u128) -> OrderFills {
+    let mut fills = OrderFills::default();
+    for &(order_id, price, amount) in orders {
+        let amount_remaining = amount_in - fills.amount_in;
+        if price >= max_price || amount_remaining == 0 {
+            break;
+        }
+        let amount_out = std::cmp::min(
+            U256::from(amount_remaining) * U256::from(PRICE_DIVISOR) / U256::from(price),
+            U256::from(amount),
+        )
+        .as_u128();
+        if amount_out == 0 {
+            break;
+        }
+        let amount_in = mul_div_ceil(amount_out, price, PRICE_DIVISOR);
+        fills.fills.push(Fill {
+            order_id,
+            amount_in,
+            amount_out,
+        });
+        fills.amount_in += amount_in;
+        fills.amount_out += amount_out;
+    }
+    fills
+}
+
+fn order_info(order_id: u64, order: Order) -> OrderInfo {
+    OrderInfo {
+        order_id: order_id.into(),
+        owner_id: order.owner_id,
+        pool_id: order.pool_id,
+        token_id: order.token_id,
+        price: order.price.into(),
+        amount: order.amount.into(),
+        filled: (order.original_amount - order.amount).into(),
+    }
+}
+
+/// Places a limit order with the transferred tokens instead of swapping them.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct LimitOrder {
+    pub pool_id: u64,
+    /// Minimum amount of the other token per transferred token multiplied by 10^18.
+    pub price: U128,
+}
+
+#[derive(Deserialize, Serialize, Eq, PartialEq, Debug)]
+pub struct OrderInfo {
+    pub order_id: U64,
+    pub owner_id: AccountId,
+    pub pool_id: u64,
+    /// Token, that is sold by the order.
+    pub token_id: AccountId,
+    pub price: U128,
+    /// Amount of the sold token, that has not been filled yet.
+    pub amount: U128,
+    /// Amount of the sold token, that has been filled.
+    pub filled: U128,
+}
+
+#[derive(Deserialize, Serialize, Eq, PartialEq, Debug)]
+pub struct PriceLevel {
+    pub price: U128,
+    /// Unfilled amount of all orders at this price.
+    pub amount: U128,
+    pub order_count: u64,
+}
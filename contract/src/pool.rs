@@ -0,0 +1,128 @@
+use crate::{
+    oracle::{timestamp_seconds, Oracle},
+    *,
+};
+
+/// Liquidity pool of two tokens with its own swap fee and liquidity shares.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct Pool {
+    pub token_a: TokenPair,
+    pub token_b: TokenPair,
+    pub fee: u32,
+    pub curve: Curve,
+    pub shares: FungibleToken,
+    pub oracle: Oracle,
+}
+
+impl Pool {
+    pub fn new(pool_id: u64, token_a: TokenPair, token_b: TokenPair, curve: Curve) -> Self {
+        Self {
+            token_a,
+            token_b,
+            fee: 0,
+            curve,
+            shares: FungibleToken::new(StorageKey::Shares { pool_id }.try_to_vec().unwrap()),
+            oracle: Oracle::new(StorageKey::Observations { pool_id }),
+        }
+    }
+
+    pub fn contains(&self, token_id: &AccountId) -> bool {
+        token_id == &self.token_a.account_id || token_id == &self.token_b.account_id
+    }
+
+    /// Returns the pair of `token_id` and the other pair of the pool.
+    pub fn pairs(&self, token_id: &AccountId) -> (&TokenPair, &TokenPair) {
+        if token_id == &self.token_a.account_id {
+            (&self.token_a, &self.token_b)
+        } else if token_id == &self.token_b.account_id {
+            (&self.token_b, &self.token_a)
+        } else {
+            panic!("Token {} does not belong to liquidity pool", token_id);
+        }
+    }
+
+    /// Returns the pair of `token_id` and the other pair of the pool.
+    pub fn pairs_mut(&mut self, token_id: &AccountId) -> (&mut TokenPair, &mut TokenPair) {
+        if token_id == &self.token_a.account_id {
+            (&mut self.token_a, &mut self.token_b)
+        } else if token_id == &self.token_b.account_id {
+            (&mut self.token_b, &mut self.token_a)
+        } else {
+            panic!("Token {} does not belong to liquidity pool", token_id);
+        }
+    }
+
+    /// Mints liquidity shares to `account_id`.
+    pub fn mint_shares(&mut self, account_id: &AccountId, shares: u128) {
+        if !self.shares.accounts.contains_key(account_id) {
+            self.shares.internal_register_account(account_id);
+        }
+        self.shares.internal_deposit(account_id, shares);
+    }
+
+    /// Burns liquidity shares of `account_id`. Accounts without shares are removed
+    /// to release their storage.
+    pub fn burn_shares(&mut self, account_id: &AccountId, shares: u128) {
+        self.shares.internal_withdraw(account_id, shares);
+        if self.shares.internal_unwrap_balance_of(account_id) == 0 {
+            self.shares.accounts.remove(account_id);
+        }
+    }
+
+    /// Records the spot prices of the current reserves in the oracle.
+    /// This needs to be called after every change of the reserves.
+    pub fn update_oracle(&mut self) {
+        let (pair_a, pair_b) = (&self.token_a, &self.token_b);
+        let (price_a, price_b) = if pair_a.supply.0 == 0 || pair_b.supply.0 == 0 {
+            (0, 0)
+        } else {
+            (
+                self.curve.get_spot_price(pair_a, pair_b),
+                self.curve.get_spot_price(pair_b, pair_a),
+            )
+        };
+        self.oracle.update(price_a, price_b, timestamp_seconds());
+    }
+
+    /// Returns the metadata of the liquidity shares, whose multi token id is `token_id`.
+    pub fn share_metadata(&self, token_id: String) -> MtBaseTokenMetadata {
+        let (pair_a, pair_b) = (&self.token_a, &self.token_b);
+        // shares are denominated in the geometric mean of both tokens
+        let decimals = (pair_a.metadata.decimals as u16 + pair_b.metadata.decimals as u16) / 2;
+        MtBaseTokenMetadata {
+            name: format!(
+                "Orderly LP {}-{}",
+                pair_a.metadata.symbol, pair_b.metadata.symbol
+            ),
+            id: token_id,
+            symbol: Some(format!(
+                "{}-{}",
+                pair_a.metadata.symbol, pair_b.metadata.symbol
+            )),
+            icon: None,
+            decimals: Some(decimals.to_string()),
+            base_uri: None,
+            reference: None,
+            copies: None,
+            reference_hash: None,
+        }
+    }
+
+    pub fn info(&self) -> PoolInfo {
+        let (token_a, token_b) = (&self.token_a, &self.token_b);
+        PoolInfo {
+            token_a_id: token_a.account_id.clone(),
+            token_a_name: token_a.metadata.name.clone(),
+            token_a_symbol: token_a.metadata.symbol.clone(),
+            token_a_supply: token_a.supply,
+            token_a_decimals: token_a.metadata.decimals,
+            token_b_id: token_b.account_id.clone(),
+            token_b_name: token_b.metadata.name.clone(),
+            token_b_symbol: token_b.metadata.symbol.clone(),
+            token_b_supply: token_b.supply,
+            token_b_decimals: token_b.metadata.decimals,
+            fee: self.fee,
+            curve: self.curve.info(token_a, token_b),
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::*;
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+use near_sdk::{assert_one_yocto, Balance, StorageUsage};
+
+/// Bytes, that are covered by the minimum storage balance. Besides the registration itself
+/// this covers the deposits, shares, positions or orders of a few pools.
+pub const MIN_STORAGE_USAGE: StorageUsage = 1_000;
+
+/// Storage balance of an account, that pays for the records of the account in this contract.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct AccountStorage {
+    /// Deposited NEAR.
+    pub balance: Balance,
+    /// Bytes used by the records of the account including its registration.
+    pub usage: StorageUsage,
+    /// Number of deposited tokens and pools with shares of the account.
+    pub entries: u32,
+}
+
+impl AccountStorage {
+    fn available(&self) -> Balance {
+        self.balance
+            .saturating_sub(Balance::from(self.usage) * env::storage_byte_cost())
+    }
+
+    fn storage_balance(&self) -> StorageBalance {
+        StorageBalance {
+            total: self.balance.into(),
+            available: self.available().into(),
+        }
+    }
+}
+
+#[near_bindgen]
+impl StorageManagement for OrderlyContract {
+    /// Registers `account_id` or the caller, which is required before depositing tokens,
+    /// adding liquidity or placing orders. Deposits to a registered account increase its balance.
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let registration_only = registration_only.unwrap_or_default();
+        let refund = if let Some(mut storage) = self.storage_accounts.get(&account_id) {
+            if registration_only {
+                log!("The account is already registered, refunding the deposit");
+                amount
+            } else {
+                storage.balance += amount;
+                self.storage_accounts.insert(&account_id, &storage);
+                0
+            }
+        } else {
+            let min_balance = self.storage_balance_bounds().min.0;
+            assert!(
+                amount >= min_balance,
+                "The attached deposit is less than the minimum storage balance of {}",
+                min_balance
+            );
+            let balance = if registration_only {
+                min_balance
+            } else {
+                amount
+            };
+            let initial_storage = env::storage_usage();
+            self.storage_accounts.insert(
+                &account_id,
+                &AccountStorage {
+                    balance,
+                    usage: 0,
+                    entries: 0,
+                },
+            );
+            self.internal_update_storage(&account_id, initial_storage);
+            log!("Registered account {}", account_id);
+            amount - balance
+        };
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    /// Withdraws `amount` or all of the available storage balance of the caller.
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut storage = self.internal_get_storage(&account_id);
+        let available = storage.available();
+        let amount = amount.map(|amount| amount.0).unwrap_or(available);
+        assert!(
+            amount <= available,
+            "The amount is greater than the available storage balance of {}",
+            available
+        );
+        storage.balance -= amount;
+        self.storage_accounts.insert(&account_id, &storage);
+        if amount > 0 {
+            Promise::new(account_id).transfer(amount);
+        }
+        storage.storage_balance()
+    }
+
+    /// Unregisters the caller and returns its storage balance. This is only possible
+    /// without any deposits, shares, positions or orders, because they cannot be burned.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        assert!(
+            !force.unwrap_or_default(),
+            "Force unregistration is not supported"
+        );
+        let account_id = env::predecessor_account_id();
+        let storage = match self.storage_accounts.get(&account_id) {
+            Some(storage) => storage,
+            None => return false,
+        };
+        assert!(
+            storage.entries == 0
+                && !self.account_positions.contains_key(&account_id)
+                && !self.account_orders.contains_key(&account_id),
+            "Account {} still has deposits, shares, positions or orders",
+            account_id
+        );
+        self.storage_accounts.remove(&account_id);
+        log!("Unregistered account {}", account_id);
+        if storage.balance > 0 {
+            Promise::new(account_id).transfer(storage.balance);
+        }
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: (Balance::from(MIN_STORAGE_USAGE) * env::storage_byte_cost()).into(),
+            max: None,
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts
+            .get(&account_id)
+            .map(|storage| storage.storage_balance())
+    }
+}
+
+impl OrderlyContract {
+    fn internal_get_storage(&self, account_id: &AccountId) -> AccountStorage {
+        self.storage_accounts
+            .get(account_id)
+            .unwrap_or_else(|| panic!("The account {} is not registered", account_id))
+    }
+
+    pub(crate) fn is_registered(&self, account_id: &AccountId) -> bool {
+        self.storage_accounts.contains_key(account_id)
+    }
+
+    /// Records the storage, that was used or released for `account_id` since `initial_storage`.
+    /// Records of unregistered accounts, which only come from credits that cannot be refused,
+    /// e.g. failed withdrawals, are paid by the contract.
+    pub(crate) fn internal_update_storage(
+        &mut self,
+        account_id: &AccountId,
+        initial_storage: StorageUsage,
+    ) {
+        if let Some(mut storage) = self.storage_accounts.get(account_id) {
+            let storage_usage = env::storage_usage();
+            storage.usage = if storage_usage >= initial_storage {
+                storage.usage + (storage_usage - initial_storage)
+            } else {
+                storage
+                    .usage
+                    .saturating_sub(initial_storage - storage_usage)
+            };
+            self.storage_accounts.insert(account_id, &storage);
+        }
+    }
+
+    /// Counts a deposit or share balance of `account_id`, that was `created` or removed.
+    pub(crate) fn internal_update_entries(&mut self, account_id: &AccountId, created: bool) {
+        if let Some(mut storage) = self.storage_accounts.get(account_id) {
+            if created {
+                storage.entries += 1;
+            } else {
+                storage.entries -= 1;
+            }
+            self.storage_accounts.insert(account_id, &storage);
+        }
+    }
+
+    /// Panics, if the storage balance of `account_id` does not cover the storage of its records.
+    pub(crate) fn assert_storage_covered(&self, account_id: &AccountId) {
+        let storage = self.internal_get_storage(account_id);
+        let required = Balance::from(storage.usage) * env::storage_byte_cost();
+        assert!(
+            storage.balance >= required,
+            "Storage balance of {} is not enough, {} more need to be deposited with storage_deposit",
+            account_id,
+            required - storage.balance
+        );
+    }
+}
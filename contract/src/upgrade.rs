@@ -0,0 +1,129 @@
+use crate::{curve::WEIGHT_DIVISOR, math::sqrt_mul, *};
+use near_sdk::collections::LazyOption;
+
+/// Version of the current state layout, which is stored next to the state.
+pub const STATE_VERSION: u8 = 2;
+/// Storage key of the state version. The first version did not store it.
+const VERSION_KEY: &[u8] = b"VERSION";
+
+const GAS_FOR_MIGRATE: Gas = Gas(100_000_000_000_000);
+
+/// State of the first version with a single pool, whose tokens were set by `init`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct OrderlyContractV1 {
+    pub owner: AccountId,
+    pub token_a: LazyOption<TokenPairV1>,
+    pub token_b: LazyOption<TokenPairV1>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct TokenPairV1 {
+    pub account_id: AccountId,
+    pub metadata: FungibleTokenMetadata,
+    pub supply: U128,
+}
+
+/// State with the layout of any version, that can be migrated to the current one.
+pub(crate) enum VersionedState {
+    V1(OrderlyContractV1),
+    V2(Box<OrderlyContract>),
+}
+
+impl VersionedState {
+    /// Reads the state with the layout of its stored version.
+    pub fn read() -> Self {
+        let version = env::storage_read(VERSION_KEY).map_or(1, |version| version[0]);
+        match version {
+            1 => VersionedState::V1(env::state_read().expect("State does not exist")),
+            2 => VersionedState::V2(Box::new(env::state_read().expect("State does not exist"))),
+            _ => panic!("Unsupported state version {}", version),
+        }
+    }
+}
+
+#[near_bindgen]
+impl OrderlyContract {
+    /// Deploys the code passed as raw input to this contract and migrates the state to it.
+    /// If the migration fails, the previous code stays deployed.
+    pub fn upgrade(&self) -> Promise {
+        self.assert_owner();
+        let code = env::input().expect("Expected the code of the contract as input");
+        log!("Upgrading contract by {}", self.owner);
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call("migrate".to_string(), vec![], 0, GAS_FOR_MIGRATE)
+    }
+
+    /// Deletes the account of this contract and transfers its balance to `beneficiary_id`,
+    /// e.g. after a factory failed to add the first pool to it. This is only possible
+    /// as long as the contract has no pools.
+    pub fn delete_account(&self, beneficiary_id: AccountId) -> Promise {
+        self.assert_owner();
+        assert!(
+            self.pools.is_empty() && self.concentrated_pools.is_empty(),
+            "Contract with pools cannot be deleted"
+        );
+        log!(
+            "Deleting {} in favor of {}",
+            env::current_account_id(),
+            beneficiary_id
+        );
+        Promise::new(env::current_account_id()).delete_account(beneficiary_id)
+    }
+
+    /// Migrates the state of any previous version to the current layout.
+    /// The single pool of the first version becomes pool 0 and its owner receives
+    /// the shares of the reserves.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let contract = match VersionedState::read() {
+            VersionedState::V1(mut state) => {
+                let mut contract = Self::empty(state.owner);
+                if let (Some(token_a), Some(token_b)) = (state.token_a.take(), state.token_b.take())
+                {
+                    contract.internal_migrate_pool_v1(token_a, token_b);
+                }
+                log!("Migrated state from version 1 to {}", STATE_VERSION);
+                contract
+            }
+            VersionedState::V2(contract) => *contract,
+        };
+        write_state_version();
+        contract
+    }
+}
+
+impl OrderlyContract {
+    fn internal_migrate_pool_v1(&mut self, token_a: TokenPairV1, token_b: TokenPairV1) {
+        let shares = sqrt_mul(token_a.supply.0, token_b.supply.0);
+        let mut pool = Pool::new(
+            0,
+            TokenPair {
+                account_id: token_a.account_id,
+                metadata: token_a.metadata,
+                supply: token_a.supply,
+                weight: WEIGHT_DIVISOR / 2,
+            },
+            TokenPair {
+                account_id: token_b.account_id,
+                metadata: token_b.metadata,
+                supply: token_b.supply,
+                weight: WEIGHT_DIVISOR / 2,
+            },
+            Curve::ConstantProduct,
+        );
+        if shares > 0 {
+            pool.mint_shares(&self.owner, shares);
+            multi_token::emit_mint(0, &self.owner, shares);
+        }
+        pool.update_oracle();
+        self.tokens.insert(&pool.token_a.account_id);
+        self.tokens.insert(&pool.token_b.account_id);
+        self.pools.push(&pool);
+    }
+}
+
+pub(crate) fn write_state_version() {
+    env::storage_write(VERSION_KEY, &[STATE_VERSION]);
+}
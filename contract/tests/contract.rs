@@ -1,5 +1,10 @@
+use near_contract_standards::storage_management::StorageBalance;
 use near_sdk::json_types::U128;
-use orderly_contract::ContractInfo;
+use orderly_contract::{
+    ConcentratedPoolInfo, ContractInfo, OrderInfo, PoolCurve, PoolInfo, PositionInfo,
+    OBSERVATIONS_STORAGE, PRICE_DIVISOR,
+};
+use std::collections::HashMap;
 use tokio::fs;
 use workspaces::{
     network::Sandbox,
@@ -8,20 +13,74 @@ use workspaces::{
     Account, AccountId, Contract, Worker,
 };
 
+/// Deposit for the observations of a pool with the storage price of the sandbox.
+const POOL_DEPOSIT: u128 = OBSERVATIONS_STORAGE as u128 * 10_000_000_000_000_000_000;
+
 #[tokio::test]
-async fn test_init() -> anyhow::Result<()> {
-    let (worker, _, contract, token_a, token_b) = initialize_contracts().await?;
+async fn test_add_pool() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
 
-    contract_init(&worker, &contract, token_a.id(), token_b.id()).await?;
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+
+    let res = contract.call(&worker, "get_contract_info").view().await?;
+    assert_eq!(res.json::<ContractInfo>()?.pool_count, 1);
 
     Ok(())
 }
 
 #[tokio::test]
-async fn test_get_contract_info() -> anyhow::Result<()> {
+async fn test_add_pool_not_owner() -> anyhow::Result<()> {
     let (worker, _, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    let res = user
+        .call(&worker, contract.id(), "add_pool")
+        .args_json((token_a.id(), token_b.id(), Option::<PoolCurve>::None))?
+        .deposit(POOL_DEPOSIT)
+        .max_gas()
+        .transact()
+        .await;
+    assert!(res.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_add_pool_not_a_token() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    // the account has no contract, so fetching its metadata fails
+    let res = owner
+        .call(&worker, contract.id(), "add_pool")
+        .args_json((token_a.id(), user.id(), Option::<PoolCurve>::None))?
+        .deposit(POOL_DEPOSIT)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    assert_eq!(res.json::<Option<u64>>()?, None);
+    let event = format!(
+        r#"EVENT_JSON:{{"standard":"orderly","version":"1.0.0","event":"metadata_failed","data":[{{"token_id":"{}","reason":"call_failed"}}]}}"#,
+        user.id()
+    );
+    assert!(res.logs().contains(&event.as_str()));
+
+    let res = contract.call(&worker, "get_contract_info").view().await?;
+    assert_eq!(res.json::<ContractInfo>()?.pool_count, 0);
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    let res = contract.call(&worker, "get_contract_info").view().await?;
+    assert_eq!(res.json::<ContractInfo>()?.pool_count, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_pool() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
 
-    contract_init(&worker, &contract, token_a.id(), token_b.id()).await?;
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
 
     assert_token_supplies(
         &worker,
@@ -37,32 +96,48 @@ async fn test_get_contract_info() -> anyhow::Result<()> {
 }
 
 #[tokio::test]
-async fn test_get_contract_info_no_init() -> anyhow::Result<()> {
-    let (worker, _, contract, _, _) = initialize_contracts().await?;
+async fn test_get_contract_info_no_pool() -> anyhow::Result<()> {
+    let (worker, owner, contract, _, _) = initialize_contracts().await?;
 
     let res = contract.call(&worker, "get_contract_info").view().await?;
-    assert_eq!(res.json::<Option<ContractInfo>>()?, None);
+    assert_eq!(
+        res.json::<ContractInfo>()?,
+        ContractInfo {
+            owner: owner.id().to_string().parse().unwrap(),
+            pool_count: 0,
+            paused: false,
+        }
+    );
+    let res = contract
+        .call(&worker, "get_pool")
+        .args_json((0,))?
+        .view()
+        .await?;
+    assert_eq!(res.json::<Option<PoolInfo>>()?, None);
 
     Ok(())
 }
 
 #[tokio::test]
-async fn test_deposit_owner() -> anyhow::Result<()> {
+async fn test_deposit() -> anyhow::Result<()> {
     let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
 
-    contract_init(&worker, &contract, token_a.id(), token_b.id()).await?;
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
     storage_deposit(&worker, &token_a, contract.id()).await?;
     mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
 
-    transfer_tokens(&worker, &owner, contract.id(), token_a.id(), 1_000.into()).await?;
+    deposit_tokens(&worker, &owner, contract.id(), token_a.id(), 1_000.into()).await?;
 
     let res = ft_balance_of(&worker, &token_a, owner.id()).await?;
     assert_eq!(res.json::<U128>()?, U128::from(999_000));
+    let deposits = get_deposits(&worker, &contract, owner.id()).await?;
+    assert_eq!(deposits.len(), 1);
+    assert_eq!(deposits[token_a.id()], U128::from(1_000));
     assert_token_supplies(
         &worker,
         &contract,
         token_a.id(),
-        1_000.into(),
+        0.into(),
         token_b.id(),
         0.into(),
     )
@@ -72,44 +147,75 @@ async fn test_deposit_owner() -> anyhow::Result<()> {
 }
 
 #[tokio::test]
-async fn test_deposit_owner_2() -> anyhow::Result<()> {
+async fn test_deposit_2() -> anyhow::Result<()> {
     let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
 
-    contract_init(&worker, &contract, token_a.id(), token_b.id()).await?;
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
     storage_deposit(&worker, &token_a, contract.id()).await?;
     mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
     storage_deposit(&worker, &token_b, contract.id()).await?;
     mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
 
-    transfer_tokens(&worker, &owner, contract.id(), token_a.id(), 1_000.into()).await?;
-    transfer_tokens(&worker, &owner, contract.id(), token_b.id(), 69_000.into()).await?;
-    transfer_tokens(&worker, &owner, contract.id(), token_b.id(), 42.into()).await?;
+    deposit_tokens(&worker, &owner, contract.id(), token_a.id(), 1_000.into()).await?;
+    deposit_tokens(&worker, &owner, contract.id(), token_b.id(), 69_000.into()).await?;
+    deposit_tokens(&worker, &owner, contract.id(), token_b.id(), 42.into()).await?;
 
     let res = ft_balance_of(&worker, &token_a, owner.id()).await?;
     assert_eq!(res.json::<U128>()?, U128::from(999_000));
     let res = ft_balance_of(&worker, &token_b, owner.id()).await?;
     assert_eq!(res.json::<U128>()?, U128::from(1_000_000 - 69_000 - 42));
-    assert_token_supplies(
+    let deposits = get_deposits(&worker, &contract, owner.id()).await?;
+    assert_eq!(deposits[token_a.id()], U128::from(1_000));
+    assert_eq!(deposits[token_b.id()], U128::from(69_042));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_deposit_unregistered_should_refund() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+
+    transfer_tokens_with_msg(
         &worker,
-        &contract,
+        &owner,
+        contract.id(),
         token_a.id(),
         1_000.into(),
-        token_b.id(),
-        69_042.into(),
+        r#"{"action":"deposit"}"#,
     )
     .await?;
 
+    let res = ft_balance_of(&worker, &token_a, owner.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_000));
+    assert!(get_deposits(&worker, &contract, owner.id())
+        .await?
+        .is_empty());
+
+    register_account(&worker, &owner, contract.id()).await?;
+    let res = contract
+        .call(&worker, "storage_balance_of")
+        .args_json((owner.id(),))?
+        .view()
+        .await?;
+    let balance = res.json::<StorageBalance>()?;
+    assert_eq!(balance.total, U128::from(100_000_000_000_000_000_000_000));
+    assert!(balance.available.0 < balance.total.0);
+
     Ok(())
 }
 
 #[tokio::test]
-async fn test_deposit_owner_no_init_should_refund() -> anyhow::Result<()> {
+async fn test_deposit_no_init_should_refund() -> anyhow::Result<()> {
     let (worker, owner, contract, token_a, _) = initialize_contracts().await?;
 
     storage_deposit(&worker, &token_a, contract.id()).await?;
     mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
 
-    transfer_tokens(&worker, &owner, contract.id(), token_a.id(), 1_000.into()).await?;
+    deposit_tokens(&worker, &owner, contract.id(), token_a.id(), 1_000.into()).await?;
 
     let res = ft_balance_of(&worker, &token_a, owner.id()).await?;
     assert_eq!(res.json::<U128>()?, U128::from(1_000_000));
@@ -118,110 +224,199 @@ async fn test_deposit_owner_no_init_should_refund() -> anyhow::Result<()> {
 }
 
 #[tokio::test]
-async fn test_swap() -> anyhow::Result<()> {
+async fn test_add_liquidity() -> anyhow::Result<()> {
     let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
     let user = worker.dev_create_account().await?;
 
-    contract_init(&worker, &contract, token_a.id(), token_b.id()).await?;
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
     storage_deposit(&worker, &token_a, contract.id()).await?;
     mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
     mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
     storage_deposit(&worker, &token_b, contract.id()).await?;
     mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
     mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
-    transfer_tokens(&worker, &owner, contract.id(), token_a.id(), 1_000.into()).await?;
-    transfer_tokens(&worker, &owner, contract.id(), token_b.id(), 1_000.into()).await?;
 
-    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 100.into()).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        4_000,
+    )
+    .await?;
+    let res = share_balance_of(&worker, &contract, owner.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(2_000));
 
-    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
-    assert_eq!(res.json::<U128>()?, U128::from(999_900));
-    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
-    assert_eq!(res.json::<U128>()?, U128::from(1_000_091));
+    // only the amounts matching the reserve ratio are added
+    add_liquidity(
+        &worker,
+        &user,
+        &contract,
+        token_a.id(),
+        500,
+        token_b.id(),
+        4_000,
+    )
+    .await?;
+    let res = share_balance_of(&worker, &contract, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000));
+    let deposits = get_deposits(&worker, &contract, user.id()).await?;
+    assert_eq!(deposits.len(), 1);
+    assert_eq!(deposits[token_b.id()], U128::from(2_000));
     assert_token_supplies(
         &worker,
         &contract,
         token_a.id(),
-        1_100.into(),
+        1_500.into(),
         token_b.id(),
-        909.into(),
+        6_000.into(),
     )
     .await?;
 
+    let res = user
+        .call(&worker, contract.id(), "withdraw")
+        .args_json((token_b.id(), U128::from(2_000)))?
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(998_000));
+    let deposits = get_deposits(&worker, &contract, user.id()).await?;
+    assert!(deposits.is_empty());
+
     Ok(())
 }
 
 #[tokio::test]
-async fn test_swap_2() -> anyhow::Result<()> {
+async fn test_transfer_shares() -> anyhow::Result<()> {
     let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
     let user = worker.dev_create_account().await?;
 
-    contract_init(&worker, &contract, token_a.id(), token_b.id()).await?;
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
     storage_deposit(&worker, &token_a, contract.id()).await?;
     mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
-    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
     storage_deposit(&worker, &token_b, contract.id()).await?;
     mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
-    mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
-    transfer_tokens(&worker, &owner, contract.id(), token_a.id(), 1_000.into()).await?;
-    transfer_tokens(&worker, &owner, contract.id(), token_b.id(), 1_000.into()).await?;
-
-    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 50.into()).await?;
-    transfer_tokens(&worker, &user, contract.id(), token_b.id(), 150.into()).await?;
-    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 200.into()).await?;
-
-    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
-    assert_eq!(res.json::<U128>()?, U128::from(999_893));
-    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
-    assert_eq!(res.json::<U128>()?, U128::from(1_000_098));
-    assert_token_supplies(
+    add_liquidity(
         &worker,
+        &owner,
         &contract,
         token_a.id(),
-        1_107.into(),
+        1_000,
         token_b.id(),
-        902.into(),
+        4_000,
     )
     .await?;
 
+    // the receiver has to be registered
+    let res = owner
+        .call(&worker, contract.id(), "mt_transfer")
+        .args_json((
+            user.id(),
+            "0",
+            U128::from(500),
+            Option::<()>::None,
+            Option::<String>::None,
+        ))?
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await;
+    assert!(res.is_err());
+
+    register_account(&worker, &user, contract.id()).await?;
+    let res = owner
+        .call(&worker, contract.id(), "mt_transfer")
+        .args_json((
+            user.id(),
+            "0",
+            U128::from(500),
+            Option::<()>::None,
+            Option::<String>::None,
+        ))?
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    assert!(res.logs().contains(&format!(
+        r#"EVENT_JSON:{{"standard":"nep245","version":"1.0.0","event":"mt_transfer","data":[{{"old_owner_id":"{}","new_owner_id":"{}","token_ids":["0"],"amounts":["500"]}}]}}"#,
+        owner.id(),
+        user.id()
+    ).as_str()));
+
+    let res = share_balance_of(&worker, &contract, owner.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_500));
+    let res = share_balance_of(&worker, &contract, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(500));
+    let res = contract
+        .call(&worker, "mt_supply")
+        .args_json(("0",))?
+        .view()
+        .await?;
+    assert_eq!(res.json::<Option<U128>>()?, Some(U128::from(2_000)));
+
+    // the received shares can be removed as liquidity
+    let res = user
+        .call(&worker, contract.id(), "remove_liquidity")
+        .args_json((0, U128::from(500), U128::from(0), U128::from(0)))?
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    let res = share_balance_of(&worker, &contract, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(0));
+
     Ok(())
 }
 
 #[tokio::test]
-async fn test_swap_all() -> anyhow::Result<()> {
+async fn test_remove_liquidity() -> anyhow::Result<()> {
     let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
-    let user = worker.dev_create_account().await?;
 
-    contract_init(&worker, &contract, token_a.id(), token_b.id()).await?;
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
     storage_deposit(&worker, &token_a, contract.id()).await?;
     mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
-    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
     storage_deposit(&worker, &token_b, contract.id()).await?;
     mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
-    mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
-    transfer_tokens(&worker, &owner, contract.id(), token_a.id(), 1_000.into()).await?;
-    transfer_tokens(&worker, &owner, contract.id(), token_b.id(), 1_000.into()).await?;
-
-    transfer_tokens(
+    add_liquidity(
         &worker,
-        &user,
-        contract.id(),
+        &owner,
+        &contract,
         token_a.id(),
-        1_000_000.into(),
+        1_000,
+        token_b.id(),
+        4_000,
     )
     .await?;
 
-    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
-    assert_eq!(res.json::<U128>()?, U128::from(0));
-    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
-    assert_eq!(res.json::<U128>()?, U128::from(1_001_000));
+    let res = owner
+        .call(&worker, contract.id(), "remove_liquidity")
+        .args_json((0, U128::from(1_000), U128::from(500), U128::from(2_000)))?
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+
+    let res = share_balance_of(&worker, &contract, owner.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000));
+    let res = ft_balance_of(&worker, &token_a, owner.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(999_500));
+    let res = ft_balance_of(&worker, &token_b, owner.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(998_000));
     assert_token_supplies(
         &worker,
         &contract,
         token_a.id(),
-        1_001_000.into(),
+        500.into(),
         token_b.id(),
-        0.into(),
+        2_000.into(),
     )
     .await?;
 
@@ -229,108 +424,1417 @@ async fn test_swap_all() -> anyhow::Result<()> {
 }
 
 #[tokio::test]
-async fn test_swap_not_enough_liquidity() -> anyhow::Result<()> {
+async fn test_swap() -> anyhow::Result<()> {
     let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
     let user = worker.dev_create_account().await?;
 
-    contract_init(&worker, &contract, token_a.id(), token_b.id()).await?;
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
     storage_deposit(&worker, &token_a, contract.id()).await?;
     mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
     mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
     storage_deposit(&worker, &token_b, contract.id()).await?;
     mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
     mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
-    transfer_tokens(&worker, &owner, contract.id(), token_a.id(), 1_000.into()).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
 
-    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 10.into()).await?;
+    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 100.into()).await?;
 
     let res = ft_balance_of(&worker, &token_a, user.id()).await?;
-    assert_eq!(res.json::<U128>()?, U128::from(1_000_000));
+    assert_eq!(res.json::<U128>()?, U128::from(999_900));
     let res = ft_balance_of(&worker, &token_b, user.id()).await?;
-    assert_eq!(res.json::<U128>()?, U128::from(1_000_000));
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_090));
     assert_token_supplies(
         &worker,
         &contract,
         token_a.id(),
-        1_000.into(),
+        1_100.into(),
         token_b.id(),
-        0.into(),
+        910.into(),
     )
     .await?;
 
     Ok(())
 }
 
-async fn initialize_contracts(
-) -> anyhow::Result<(Worker<Sandbox>, Account, Contract, Contract, Contract)> {
-    let worker = workspaces::sandbox().await?;
-
-    let owner = worker.dev_create_account().await?;
+#[tokio::test]
+async fn test_swap_stable_swap() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
 
-    let contract = worker
-        .dev_deploy(&fs::read("../res/orderly_contract.wasm").await?)
-        .await?;
-    contract
-        .call(&worker, "new")
-        .args_json((owner.id(),))?
+    let res = owner
+        .call(&worker, contract.id(), "add_pool")
+        .args_json((
+            token_a.id(),
+            token_b.id(),
+            PoolCurve::StableSwap { amp: 100 },
+        ))?
+        .deposit(POOL_DEPOSIT)
         .max_gas()
         .transact()
         .await?;
+    assert!(res.is_success());
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000_000,
+        token_b.id(),
+        1_000_000,
+    )
+    .await?;
 
-    let token_a_contract = worker
-        .dev_deploy(&fs::read("../res/test_token.wasm").await?)
-        .await?;
-    token_a_contract
-        .call(&worker, "new")
-        .args_json(("TokenA", "TKNA"))?
-        .transact()
-        .await?;
+    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 100_000.into()).await?;
 
-    let token_b_contract = worker
-        .dev_deploy(&fs::read("../res/test_token.wasm").await?)
-        .await?;
-    token_b_contract
-        .call(&worker, "new")
-        .args_json(("TokenB", "TKNB"))?
-        .transact()
-        .await?;
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(900_000));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_099_949));
 
-    Ok((worker, owner, contract, token_a_contract, token_b_contract))
+    Ok(())
 }
 
-async fn contract_init(
-    worker: &Worker<Sandbox>,
-    contract: &Contract,
-    token_a: &AccountId,
-    token_b: &AccountId,
-) -> anyhow::Result<()> {
-    let res = contract
-        .call(worker, "init")
-        .args_json((token_a, token_b))?
+#[tokio::test]
+async fn test_swap_weighted() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    let res = owner
+        .call(&worker, contract.id(), "add_pool")
+        .args_json((
+            token_a.id(),
+            token_b.id(),
+            PoolCurve::Weighted {
+                weight_a: 8_000,
+                weight_b: 2_000,
+            },
+        ))?
+        .deposit(POOL_DEPOSIT)
         .max_gas()
         .transact()
         .await?;
     assert!(res.is_success());
-    Ok(())
-}
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000_000,
+        token_b.id(),
+        1_000_000,
+    )
+    .await?;
 
-async fn storage_deposit(
-    worker: &Worker<Sandbox>,
-    token: &Contract,
-    receiver: &AccountId,
-) -> anyhow::Result<()> {
-    let res = token
-        .call(worker, "storage_deposit")
-        .args_json((receiver, Option::<bool>::None))?
-        .deposit(1_000_000_000_000_000_000_000_000)
-        .transact()
+    // 1_000_000 * (1 - (1 / 1.1)^4) = 316_986.5
+    let res = contract
+        .call(&worker, "get_return")
+        .args_json((0, token_a.id(), U128::from(100_000)))?
+        .view()
         .await?;
-    assert!(res.is_success());
+    let amount_out = res.json::<U128>()?;
+    assert_eq!(amount_out, U128::from(316_986));
+
+    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 100_000.into()).await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(900_000));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_000 + amount_out.0));
+    let res = contract
+        .call(&worker, "get_pool")
+        .args_json((0,))?
+        .view()
+        .await?;
+    let info = res.json::<PoolInfo>()?;
+    assert_eq!(info.token_a_supply, U128::from(1_100_000));
+    assert_eq!(info.token_b_supply, U128::from(1_000_000 - amount_out.0));
+
     Ok(())
 }
 
-async fn mint_tokens(
-    worker: &Worker<Sandbox>,
+#[tokio::test]
+async fn test_swap_2() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+
+    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 50.into()).await?;
+    transfer_tokens(&worker, &user, contract.id(), token_b.id(), 150.into()).await?;
+    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 200.into()).await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(999_892));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_096));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_108.into(),
+        token_b.id(),
+        904.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_all() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+
+    transfer_tokens(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        1_000_000.into(),
+    )
+    .await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(0));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_999));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_001_000.into(),
+        token_b.id(),
+        1.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_not_enough_liquidity() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
+    deposit_tokens(&worker, &owner, contract.id(), token_a.id(), 1_000.into()).await?;
+
+    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 10.into()).await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_000));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_000));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        0.into(),
+        token_b.id(),
+        0.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_with_fee() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        10_000,
+        token_b.id(),
+        10_000,
+    )
+    .await?;
+    let res = owner
+        .call(&worker, contract.id(), "set_fee")
+        .args_json((0, 100))?
+        .transact()
+        .await?;
+    assert!(res.is_success());
+
+    let res = contract
+        .call(&worker, "get_return")
+        .args_json((0, token_a.id(), U128::from(1_000)))?
+        .view()
+        .await?;
+    assert_eq!(res.json::<U128>()?, U128::from(900));
+    let res = contract
+        .call(&worker, "get_amount_in")
+        .args_json((0, token_b.id(), U128::from(900)))?
+        .view()
+        .await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000));
+
+    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 1_000.into()).await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(999_000));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_900));
+    let res = contract
+        .call(&worker, "get_pool")
+        .args_json((0,))?
+        .view()
+        .await?;
+    let info = res.json::<PoolInfo>()?;
+    assert_eq!(info.fee, 100);
+    assert_eq!(info.token_a_supply, U128::from(11_000));
+    assert_eq!(info.token_b_supply, U128::from(9_100));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_min_amount_out_should_refund() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+
+    transfer_tokens_with_msg(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        100.into(),
+        r#"{"pool_id":0,"min_amount_out":"91"}"#,
+    )
+    .await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_000));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_000));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_000.into(),
+        token_b.id(),
+        1_000.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_unregistered_receiver_should_credit_deposits() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+
+    // user is not registered on token b, so the outgoing transfer fails
+    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 100.into()).await?;
+
+    // the output is credited to the deposits of the user, who can withdraw it after registering
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(999_900));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(0));
+    let deposits = get_deposits(&worker, &contract, user.id()).await?;
+    assert_eq!(deposits[token_b.id()], U128::from(90));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_100.into(),
+        token_b.id(),
+        910.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_exact_output() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+
+    transfer_tokens_with_msg(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        500.into(),
+        r#"{"pool_id":0,"amount_out":"90"}"#,
+    )
+    .await?;
+
+    // 99 of token a are needed, the rest gets refunded
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(999_901));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_090));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_099.into(),
+        token_b.id(),
+        910.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_deadline_exceeded_should_refund() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, user.id(), 1_000_000).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+
+    // deadline in 10 seconds
+    let deadline = worker.view_latest_block().await?.timestamp() + 10_000_000_000;
+    let msg = format!(r#"{{"pool_id":0,"deadline":"{}"}}"#, deadline);
+    transfer_tokens_with_msg(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        100.into(),
+        &msg,
+    )
+    .await?;
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_090));
+
+    worker.fast_forward(1_000).await?;
+    assert!(worker.view_latest_block().await?.timestamp() > deadline);
+
+    transfer_tokens_with_msg(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        100.into(),
+        &msg,
+    )
+    .await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(999_900));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_090));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_100.into(),
+        token_b.id(),
+        910.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_receiver() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+    let merchant = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, merchant.id()).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+
+    let msg = format!(r#"{{"pool_id":0,"receiver_id":"{}"}}"#, merchant.id());
+    transfer_tokens_with_msg(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        100.into(),
+        &msg,
+    )
+    .await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(999_900));
+    let res = ft_balance_of(&worker, &token_b, merchant.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(90));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_100.into(),
+        token_b.id(),
+        910.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_unregistered_receiver_id_should_credit_sender() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+    let merchant = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+
+    // merchant is not registered on token b
+    let msg = format!(r#"{{"pool_id":0,"receiver_id":"{}"}}"#, merchant.id());
+    transfer_tokens_with_msg(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        100.into(),
+        &msg,
+    )
+    .await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(999_900));
+    let res = ft_balance_of(&worker, &token_b, merchant.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(0));
+    let deposits = get_deposits(&worker, &contract, user.id()).await?;
+    assert_eq!(deposits[token_b.id()], U128::from(90));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_100.into(),
+        token_b.id(),
+        910.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_then_call() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let (contract_2, token_c) = initialize_second_pool(&worker, &owner, &token_b).await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_c, user.id()).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+
+    // swap a for b and then b for c in the second pool, which sends c to the user
+    let msg = format!(
+        r#"{{"pool_id":0,"then_call":{{"receiver_id":"{}","msg":"{{\"pool_id\":0,\"receiver_id\":\"{}\"}}"}}}}"#,
+        contract_2.id(),
+        user.id()
+    );
+    transfer_tokens_with_msg(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        100.into(),
+        &msg,
+    )
+    .await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(999_900));
+    let res = ft_balance_of(&worker, &token_c, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(82));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_100.into(),
+        token_b.id(),
+        910.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_then_call_refunded_should_credit_deposits() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let (contract_2, token_c) = initialize_second_pool(&worker, &owner, &token_b).await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_c, user.id()).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+
+    // the second pool refunds all of b due to slippage
+    let msg = format!(
+        r#"{{"pool_id":0,"then_call":{{"receiver_id":"{}","msg":"{{\"pool_id\":0,\"min_amount_out\":\"1000\"}}"}}}}"#,
+        contract_2.id()
+    );
+    transfer_tokens_with_msg(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        100.into(),
+        &msg,
+    )
+    .await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(999_900));
+    let res = ft_balance_of(&worker, &token_c, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(0));
+    let deposits = get_deposits(&worker, &contract, user.id()).await?;
+    assert_eq!(deposits[token_b.id()], U128::from(90));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_100.into(),
+        token_b.id(),
+        910.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_route() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let token_c = worker
+        .dev_deploy(&fs::read("../res/test_token.wasm").await?)
+        .await?;
+    token_c
+        .call(&worker, "new")
+        .args_json(("TokenC", "TKNC"))?
+        .transact()
+        .await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    add_pool(&worker, &owner, &contract, token_b.id(), token_c.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_c, contract.id()).await?;
+    mint_tokens(&worker, &token_c, owner.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_c, user.id()).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+    deposit_tokens(&worker, &owner, contract.id(), token_b.id(), 2_000.into()).await?;
+    deposit_tokens(&worker, &owner, contract.id(), token_c.id(), 2_000.into()).await?;
+    let res = owner
+        .call(&worker, contract.id(), "add_liquidity")
+        .args_json((1, U128::from(2_000), U128::from(2_000), U128::from(0)))?
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+
+    // the final minimum is not reached, so nothing gets swapped
+    let route = format!(
+        r#"[{{"pool_id":0,"token_out":"{}"}},{{"pool_id":1,"token_out":"{}"}}]"#,
+        token_b.id(),
+        token_c.id()
+    );
+    let msg = format!(r#"{{"route":{},"min_amount_out":"87"}}"#, route);
+    transfer_tokens_with_msg(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        100.into(),
+        &msg,
+    )
+    .await?;
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(1_000_000));
+
+    let msg = format!(r#"{{"route":{},"min_amount_out":"86"}}"#, route);
+    transfer_tokens_with_msg(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        100.into(),
+        &msg,
+    )
+    .await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(999_900));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(0));
+    let res = ft_balance_of(&worker, &token_c, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(86));
+    let res = contract
+        .call(&worker, "get_pools")
+        .args_json((0, 10))?
+        .view()
+        .await?;
+    let pools = res.json::<Vec<PoolInfo>>()?;
+    assert_eq!(pools[0].token_a_supply, U128::from(1_100));
+    assert_eq!(pools[0].token_b_supply, U128::from(910));
+    assert_eq!(pools[1].token_a_supply, U128::from(2_090));
+    assert_eq!(pools[1].token_b_supply, U128::from(1_914));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_limit_orders() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let maker = worker.dev_create_account().await?;
+    let taker = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, maker.id(), 0).await?;
+    mint_tokens(&worker, &token_a, taker.id(), 1_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, maker.id(), 1_000).await?;
+    mint_tokens(&worker, &token_b, taker.id(), 0).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000,
+        token_b.id(),
+        1_000,
+    )
+    .await?;
+
+    // sells 100 of token b for 0.5 of token a each, and another 200 at a price above the pool
+    register_account(&worker, &maker, contract.id()).await?;
+    let order_msg =
+        |price: u128| format!(r#"{{"limit_order":{{"pool_id":0,"price":"{}"}}}}"#, price);
+    for (amount, price) in [(100, PRICE_DIVISOR / 2), (200, 2 * PRICE_DIVISOR)] {
+        transfer_tokens_with_msg(
+            &worker,
+            &maker,
+            contract.id(),
+            token_b.id(),
+            U128::from(amount),
+            &order_msg(price),
+        )
+        .await?;
+    }
+    let orders = get_orders(&worker, &contract, maker.id()).await?;
+    assert_eq!(orders.len(), 2);
+    let res = ft_balance_of(&worker, &token_b, maker.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(700));
+
+    let res = contract
+        .call(&worker, "get_return")
+        .args_json((0, token_a.id(), U128::from(100)))?
+        .view()
+        .await?;
+    assert_eq!(res.json::<U128>()?, U128::from(147));
+
+    // 50 of token a fill the first order and the other 50 are swapped in the pool
+    transfer_tokens(&worker, &taker, contract.id(), token_a.id(), 100.into()).await?;
+    let res = ft_balance_of(&worker, &token_b, taker.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(147));
+    let orders = get_orders(&worker, &contract, maker.id()).await?;
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].filled, U128::from(0));
+    let deposits = get_deposits(&worker, &contract, maker.id()).await?;
+    assert_eq!(deposits[token_a.id()], U128::from(50));
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_050.into(),
+        token_b.id(),
+        953.into(),
+    )
+    .await?;
+
+    // cancelling returns the unfilled amount
+    let res = maker
+        .call(&worker, contract.id(), "cancel_order")
+        .args_json((orders[0].order_id,))?
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    assert!(get_orders(&worker, &contract, maker.id()).await?.is_empty());
+    let res = ft_balance_of(&worker, &token_b, maker.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(900));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_concentrated_position() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    let res = owner
+        .call(&worker, contract.id(), "add_concentrated_pool")
+        .args_json((token_a.id(), token_b.id(), 30, 10, 0))?
+        .deposit(POOL_DEPOSIT)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 200_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, user.id(), 0).await?;
+
+    // a wide position and a narrow one, whose lower tick gets crossed by the swap
+    deposit_tokens(
+        &worker,
+        &owner,
+        contract.id(),
+        token_a.id(),
+        1_000_000.into(),
+    )
+    .await?;
+    deposit_tokens(
+        &worker,
+        &owner,
+        contract.id(),
+        token_b.id(),
+        1_000_000.into(),
+    )
+    .await?;
+    for (lower_tick, upper_tick, amount) in [(-1_000, 1_000, 900_000), (-100, 100, 10_000)] {
+        let res = owner
+            .call(&worker, contract.id(), "open_position")
+            .args_json((
+                0,
+                lower_tick,
+                upper_tick,
+                U128::from(amount),
+                U128::from(amount),
+                U128::from(0),
+            ))?
+            .max_gas()
+            .transact()
+            .await?;
+        assert!(res.is_success());
+    }
+    let res = contract
+        .call(&worker, "get_positions")
+        .args_json((owner.id(),))?
+        .view()
+        .await?;
+    let positions = res.json::<Vec<PositionInfo>>()?;
+    assert_eq!(positions.len(), 2);
+    let wide = &positions[0];
+
+    transfer_tokens_with_msg(
+        &worker,
+        &user,
+        contract.id(),
+        token_a.id(),
+        200_000.into(),
+        r#"{"action":"concentrated_swap","pool_id":0}"#,
+    )
+    .await?;
+
+    let res = ft_balance_of(&worker, &token_a, user.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(0));
+    let res = ft_balance_of(&worker, &token_b, user.id()).await?;
+    assert!(res.json::<U128>()?.0 > 190_000);
+    let res = contract
+        .call(&worker, "get_concentrated_pool")
+        .args_json((0,))?
+        .view()
+        .await?;
+    let info = res.json::<ConcentratedPoolInfo>()?;
+    assert!(info.tick < -100);
+    // only the wide position is left in range
+    assert_eq!(info.liquidity, wide.liquidity);
+
+    let deposits = get_deposits(&worker, &contract, owner.id()).await?;
+    let res = owner
+        .call(&worker, contract.id(), "collect_fees")
+        .args_json((wide.position_id,))?
+        .max_gas()
+        .transact()
+        .await?;
+    let (fees_a, fees_b) = res.json::<(U128, U128)>()?;
+    assert!(fees_a.0 > 0);
+    assert_eq!(fees_b, U128::from(0));
+    let collected = get_deposits(&worker, &contract, owner.id()).await?;
+    assert_eq!(
+        collected[token_a.id()].0,
+        deposits[token_a.id()].0 + fees_a.0
+    );
+
+    let res = owner
+        .call(&worker, contract.id(), "decrease_liquidity")
+        .args_json((
+            wide.position_id,
+            wide.liquidity,
+            U128::from(0),
+            U128::from(0),
+        ))?
+        .max_gas()
+        .transact()
+        .await?;
+    let (amount_a, amount_b) = res.json::<(U128, U128)>()?;
+    assert!(amount_a.0 > 900_000);
+    assert!(amount_b.0 > 0);
+    let res = contract
+        .call(&worker, "get_position")
+        .args_json((wide.position_id,))?
+        .view()
+        .await?;
+    assert_eq!(res.json::<Option<PositionInfo>>()?, None);
+    let res = contract
+        .call(&worker, "get_concentrated_pool")
+        .args_json((0,))?
+        .view()
+        .await?;
+    assert_eq!(res.json::<ConcentratedPoolInfo>()?.liquidity, U128::from(0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_twap() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_a, user.id(), 250_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    mint_tokens(&worker, &token_b, user.id(), 0).await?;
+    add_liquidity(
+        &worker,
+        &owner,
+        &contract,
+        token_a.id(),
+        1_000_000,
+        token_b.id(),
+        1_000_000,
+    )
+    .await?;
+
+    // the price of token a falls from 1 to 800_000 / 1_250_000
+    worker.fast_forward(100).await?;
+    transfer_tokens(&worker, &user, contract.id(), token_a.id(), 250_000.into()).await?;
+    worker.fast_forward(100).await?;
+
+    let res = contract
+        .call(&worker, "get_twap")
+        .args_json((0, token_a.id(), 10))?
+        .view()
+        .await?;
+    assert_eq!(res.json::<U128>()?, U128::from(PRICE_DIVISOR / 100 * 64));
+    let res = contract
+        .call(&worker, "get_twap")
+        .args_json((0, token_b.id(), 10))?
+        .view()
+        .await?;
+    assert_eq!(res.json::<U128>()?, U128::from(PRICE_DIVISOR / 64 * 100));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_transfer_ownership() -> anyhow::Result<()> {
+    let (worker, owner, contract, token_a, token_b) = initialize_contracts().await?;
+    let new_owner = worker.dev_create_account().await?;
+    let user = worker.dev_create_account().await?;
+    add_pool(&worker, &owner, &contract, token_a.id(), token_b.id()).await?;
+
+    let res = owner
+        .call(&worker, contract.id(), "propose_owner")
+        .args_json((new_owner.id(),))?
+        .transact()
+        .await?;
+    assert_eq!(
+        res.logs(),
+        vec![format!(
+            r#"EVENT_JSON:{{"standard":"orderly","version":"1.0.0","event":"owner_proposed","data":[{{"owner_id":"{}","new_owner_id":"{}"}}]}}"#,
+            owner.id(),
+            new_owner.id()
+        )]
+    );
+    let res = contract.call(&worker, "get_pending_owner").view().await?;
+    assert_eq!(
+        res.json::<Option<AccountId>>()?.as_ref(),
+        Some(new_owner.id())
+    );
+    // the proposed owner has no rights before accepting
+    let res = new_owner
+        .call(&worker, contract.id(), "set_fee")
+        .args_json((0, 30))?
+        .transact()
+        .await;
+    assert!(res.is_err());
+    let res = user
+        .call(&worker, contract.id(), "accept_ownership")
+        .transact()
+        .await;
+    assert!(res.is_err());
+
+    let res = new_owner
+        .call(&worker, contract.id(), "accept_ownership")
+        .transact()
+        .await?;
+    assert_eq!(
+        res.logs(),
+        vec![format!(
+            r#"EVENT_JSON:{{"standard":"orderly","version":"1.0.0","event":"ownership_transferred","data":[{{"old_owner_id":"{}","new_owner_id":"{}"}}]}}"#,
+            owner.id(),
+            new_owner.id()
+        )]
+    );
+    let res = contract.call(&worker, "get_contract_info").view().await?;
+    assert_eq!(
+        res.json::<ContractInfo>()?.owner.as_str(),
+        new_owner.id().as_str()
+    );
+    let res = contract.call(&worker, "get_pending_owner").view().await?;
+    assert_eq!(res.json::<Option<AccountId>>()?, None);
+
+    let res = owner
+        .call(&worker, contract.id(), "set_fee")
+        .args_json((0, 30))?
+        .transact()
+        .await;
+    assert!(res.is_err());
+    let res = new_owner
+        .call(&worker, contract.id(), "set_fee")
+        .args_json((0, 30))?
+        .transact()
+        .await?;
+    assert!(res.is_success());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_upgrade_from_v1() -> anyhow::Result<()> {
+    let (worker, owner, _, token_a, token_b) = initialize_contracts().await?;
+
+    let contract = worker
+        .dev_deploy(&fs::read("../res/orderly_contract_v1.wasm").await?)
+        .await?;
+    let res = contract
+        .call(&worker, "new")
+        .args_json((owner.id(),))?
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    let res = contract
+        .as_account()
+        .call(&worker, contract.id(), "init")
+        .args_json((token_a.id(), token_b.id()))?
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    storage_deposit(&worker, &token_a, contract.id()).await?;
+    mint_tokens(&worker, &token_a, owner.id(), 1_000_000).await?;
+    storage_deposit(&worker, &token_b, contract.id()).await?;
+    mint_tokens(&worker, &token_b, owner.id(), 1_000_000).await?;
+    // the first version adds all transfers of the owner to the reserves
+    transfer_tokens_with_msg(
+        &worker,
+        &owner,
+        contract.id(),
+        token_a.id(),
+        1_000.into(),
+        "",
+    )
+    .await?;
+    transfer_tokens_with_msg(
+        &worker,
+        &owner,
+        contract.id(),
+        token_b.id(),
+        4_000.into(),
+        "",
+    )
+    .await?;
+
+    // the first version has no upgrade method, so the new code is deployed directly
+    let res = contract
+        .as_account()
+        .deploy(&worker, &contract_wasm().await?)
+        .await?;
+    assert!(res.is_success());
+    let res = contract
+        .call(&worker, "migrate")
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_000.into(),
+        token_b.id(),
+        4_000.into(),
+    )
+    .await?;
+    let res = share_balance_of(&worker, &contract, owner.id()).await?;
+    assert_eq!(res.json::<U128>()?, U128::from(2_000));
+
+    // upgrading to the same version keeps the state
+    let res = owner
+        .call(&worker, contract.id(), "upgrade")
+        .args(contract_wasm().await?)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    assert_token_supplies(
+        &worker,
+        &contract,
+        token_a.id(),
+        1_000.into(),
+        token_b.id(),
+        4_000.into(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Builds the current contract, so that the tests never run against an outdated wasm file.
+async fn contract_wasm() -> anyhow::Result<Vec<u8>> {
+    workspaces::compile_project("./").await
+}
+
+async fn initialize_contracts(
+) -> anyhow::Result<(Worker<Sandbox>, Account, Contract, Contract, Contract)> {
+    let worker = workspaces::sandbox().await?;
+
+    let owner = worker.dev_create_account().await?;
+
+    let contract = worker.dev_deploy(&contract_wasm().await?).await?;
+    contract
+        .call(&worker, "new")
+        .args_json((owner.id(),))?
+        .max_gas()
+        .transact()
+        .await?;
+
+    let token_a_contract = worker
+        .dev_deploy(&fs::read("../res/test_token.wasm").await?)
+        .await?;
+    token_a_contract
+        .call(&worker, "new")
+        .args_json(("TokenA", "TKNA"))?
+        .transact()
+        .await?;
+
+    let token_b_contract = worker
+        .dev_deploy(&fs::read("../res/test_token.wasm").await?)
+        .await?;
+    token_b_contract
+        .call(&worker, "new")
+        .args_json(("TokenB", "TKNB"))?
+        .transact()
+        .await?;
+
+    Ok((worker, owner, contract, token_a_contract, token_b_contract))
+}
+
+/// Deploys a second pool for token b and a new token c with 1_000 liquidity each.
+async fn initialize_second_pool(
+    worker: &Worker<Sandbox>,
+    owner: &Account,
+    token_b: &Contract,
+) -> anyhow::Result<(Contract, Contract)> {
+    let contract = worker.dev_deploy(&contract_wasm().await?).await?;
+    contract
+        .call(worker, "new")
+        .args_json((owner.id(),))?
+        .max_gas()
+        .transact()
+        .await?;
+
+    let token_c = worker
+        .dev_deploy(&fs::read("../res/test_token.wasm").await?)
+        .await?;
+    token_c
+        .call(worker, "new")
+        .args_json(("TokenC", "TKNC"))?
+        .transact()
+        .await?;
+
+    add_pool(worker, owner, &contract, token_b.id(), token_c.id()).await?;
+    storage_deposit(worker, token_b, contract.id()).await?;
+    storage_deposit(worker, &token_c, contract.id()).await?;
+    mint_tokens(worker, token_b, owner.id(), 1_000).await?;
+    mint_tokens(worker, &token_c, owner.id(), 1_000).await?;
+    add_liquidity(
+        worker,
+        owner,
+        &contract,
+        token_b.id(),
+        1_000,
+        token_c.id(),
+        1_000,
+    )
+    .await?;
+
+    Ok((contract, token_c))
+}
+
+async fn add_pool(
+    worker: &Worker<Sandbox>,
+    owner: &Account,
+    contract: &Contract,
+    token_a: &AccountId,
+    token_b: &AccountId,
+) -> anyhow::Result<()> {
+    let res = owner
+        .call(worker, contract.id(), "add_pool")
+        .args_json((token_a, token_b, Option::<PoolCurve>::None))?
+        .deposit(POOL_DEPOSIT)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    Ok(())
+}
+
+async fn storage_deposit(
+    worker: &Worker<Sandbox>,
+    token: &Contract,
+    receiver: &AccountId,
+) -> anyhow::Result<()> {
+    let res = token
+        .call(worker, "storage_deposit")
+        .args_json((receiver, Option::<bool>::None))?
+        .deposit(1_000_000_000_000_000_000_000_000)
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    Ok(())
+}
+
+async fn mint_tokens(
+    worker: &Worker<Sandbox>,
     token: &Contract,
     receiver: &AccountId,
     amount: u128,
@@ -350,10 +1854,21 @@ async fn transfer_tokens(
     receiver: &AccountId,
     token: &AccountId,
     amount: U128,
+) -> anyhow::Result<CallExecutionDetails> {
+    transfer_tokens_with_msg(worker, sender, receiver, token, amount, r#"{"pool_id":0}"#).await
+}
+
+async fn transfer_tokens_with_msg(
+    worker: &Worker<Sandbox>,
+    sender: &Account,
+    receiver: &AccountId,
+    token: &AccountId,
+    amount: U128,
+    msg: &str,
 ) -> anyhow::Result<CallExecutionDetails> {
     let res = sender
         .call(worker, token, "ft_transfer_call")
-        .args_json((receiver, amount, Option::<String>::None, "".to_string()))?
+        .args_json((receiver, amount, Option::<String>::None, msg.to_string()))?
         .max_gas()
         .deposit(1)
         .transact()
@@ -362,6 +1877,102 @@ async fn transfer_tokens(
     Ok(res)
 }
 
+/// Registers `account` with the contract, which is required for deposits.
+async fn register_account(
+    worker: &Worker<Sandbox>,
+    account: &Account,
+    contract: &AccountId,
+) -> anyhow::Result<()> {
+    let res = account
+        .call(worker, contract, "storage_deposit")
+        .args_json((Option::<AccountId>::None, Option::<bool>::None))?
+        .deposit(100_000_000_000_000_000_000_000)
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    Ok(())
+}
+
+/// Deposits tokens and registers the sender beforehand.
+async fn deposit_tokens(
+    worker: &Worker<Sandbox>,
+    sender: &Account,
+    receiver: &AccountId,
+    token: &AccountId,
+    amount: U128,
+) -> anyhow::Result<CallExecutionDetails> {
+    register_account(worker, sender, receiver).await?;
+    transfer_tokens_with_msg(
+        worker,
+        sender,
+        receiver,
+        token,
+        amount,
+        r#"{"action":"deposit"}"#,
+    )
+    .await
+}
+
+async fn add_liquidity(
+    worker: &Worker<Sandbox>,
+    sender: &Account,
+    contract: &Contract,
+    token_a: &AccountId,
+    amount_a: u128,
+    token_b: &AccountId,
+    amount_b: u128,
+) -> anyhow::Result<()> {
+    deposit_tokens(worker, sender, contract.id(), token_a, amount_a.into()).await?;
+    deposit_tokens(worker, sender, contract.id(), token_b, amount_b.into()).await?;
+    let res = sender
+        .call(worker, contract.id(), "add_liquidity")
+        .args_json((0, U128::from(amount_a), U128::from(amount_b), U128::from(0)))?
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    Ok(())
+}
+
+async fn get_orders(
+    worker: &Worker<Sandbox>,
+    contract: &Contract,
+    account_id: &AccountId,
+) -> anyhow::Result<Vec<OrderInfo>> {
+    let res = contract
+        .call(worker, "get_orders")
+        .args_json((account_id,))?
+        .view()
+        .await?;
+    res.json()
+}
+
+async fn get_deposits(
+    worker: &Worker<Sandbox>,
+    contract: &Contract,
+    account_id: &AccountId,
+) -> anyhow::Result<HashMap<AccountId, U128>> {
+    let res = contract
+        .call(worker, "get_deposits")
+        .args_json((account_id,))?
+        .view()
+        .await?;
+    res.json()
+}
+
+async fn share_balance_of(
+    worker: &Worker<Sandbox>,
+    contract: &Contract,
+    account_id: &AccountId,
+) -> anyhow::Result<ViewResultDetails> {
+    let res = contract
+        .call(worker, "mt_balance_of")
+        .args_json((account_id, "0"))?
+        .view()
+        .await?;
+    Ok(res)
+}
+
 async fn ft_balance_of(
     worker: &Worker<Sandbox>,
     token: &Contract,
@@ -383,10 +1994,14 @@ async fn assert_token_supplies(
     token_b: &AccountId,
     token_b_supply: U128,
 ) -> anyhow::Result<()> {
-    let res = contract.call(worker, "get_contract_info").view().await?;
+    let res = contract
+        .call(worker, "get_pool")
+        .args_json((0,))?
+        .view()
+        .await?;
     assert_eq!(
-        res.json::<ContractInfo>()?,
-        ContractInfo {
+        res.json::<PoolInfo>()?,
+        PoolInfo {
             token_a_id: token_a.to_string().parse().unwrap(),
             token_a_name: "TokenA".to_string(),
             token_a_symbol: "TKNA".to_string(),
@@ -396,7 +2011,9 @@ async fn assert_token_supplies(
             token_b_name: "TokenB".to_string(),
             token_b_symbol: "TKNB".to_string(),
             token_b_supply,
-            token_b_decimals: 12
+            token_b_decimals: 12,
+            fee: 0,
+            curve: PoolCurve::ConstantProduct,
         }
     );
     Ok(())
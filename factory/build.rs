@@ -0,0 +1,61 @@
+//! Builds the pool contract, which gets embedded into the factory.
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let contract_dir = manifest_dir.join("../contract");
+    println!(
+        "cargo:rerun-if-changed={}",
+        contract_dir.join("src").display()
+    );
+    println!(
+        "cargo:rerun-if-changed={}",
+        contract_dir.join("Cargo.toml").display()
+    );
+
+    println!("cargo:rerun-if-env-changed=DOCS_RS");
+    println!("cargo:rerun-if-env-changed=ORDERLY_EMPTY_POOL_CONTRACT");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let wasm = out_dir.join("orderly_contract.wasm");
+    // docs.rs and checks like clippy never execute the embedded contract,
+    // so they can opt out of the nested build, that needs the wasm32 target
+    if env::var_os("DOCS_RS").is_some() || env::var_os("ORDERLY_EMPTY_POOL_CONTRACT").is_some() {
+        println!("cargo:warning=Embedding an empty pool contract");
+        fs::write(&wasm, []).unwrap();
+        return;
+    }
+
+    // a separate target directory avoids waiting for the lock of the outer build
+    let target_dir = out_dir.join("target");
+    let status = Command::new(env::var("CARGO").unwrap())
+        .args([
+            "build",
+            "-p",
+            "orderly-contract",
+            "--target",
+            "wasm32-unknown-unknown",
+            "--release",
+            "--target-dir",
+        ])
+        .arg(&target_dir)
+        .current_dir(manifest_dir.join(".."))
+        // the flags of the outer build are meant for the factory, not for the pool contract
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .env_remove("RUSTC_WORKSPACE_WRAPPER")
+        .status()
+        .expect("Failed to run cargo for the pool contract");
+    if !status.success() {
+        eprintln!(
+            "Failed to build the pool contract for wasm32-unknown-unknown. \
+             Set ORDERLY_EMPTY_POOL_CONTRACT to embed an empty contract instead"
+        );
+        std::process::exit(status.code().unwrap_or(1));
+    }
+    fs::copy(
+        target_dir.join("wasm32-unknown-unknown/release/orderly_contract.wasm"),
+        &wasm,
+    )
+    .unwrap();
+}
@@ -0,0 +1,762 @@
+use near_sdk::{
+    borsh::{self, BorshDeserialize, BorshSerialize},
+    collections::UnorderedMap,
+    env, ext_contract,
+    json_types::{U128, U64},
+    log, near_bindgen,
+    serde::{Deserialize, Serialize},
+    serde_json::json,
+    AccountId, Balance, Gas, PanicOnDefault, Promise, PromiseOrValue, PromiseResult,
+};
+
+/// Compiled pool contract, that gets deployed for every pair. It is built by `build.rs`.
+const ORDERLY_CONTRACT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/orderly_contract.wasm"));
+/// Storage in bytes of the price observations of a pool, for which `add_pool`
+/// of the pool contract requires a deposit. This is `OBSERVATIONS_STORAGE` of the pool contract.
+const OBSERVATIONS_STORAGE: u128 = 97_000;
+/// Storage in bytes, that needs to be covered for the state of a pool in addition to its code,
+/// which includes the observations.
+const POOL_STATE_STORAGE: u128 = 10_000 + OBSERVATIONS_STORAGE;
+
+const GAS_FOR_NEW: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_ADD_POOL: Gas = Gas(50_000_000_000_000);
+const GAS_FOR_OWNER_CALL: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_UPGRADE: Gas = Gas(200_000_000_000_000);
+const GAS_FOR_RESOLVE_ADD_POOL: Gas = Gas(25_000_000_000_000);
+const GAS_FOR_RESOLVE_CREATE_POOL: Gas = Gas(100_000_000_000_000);
+
+#[ext_contract]
+pub trait ExtOrderlyContract {
+    fn add_pool(
+        &mut self,
+        token_a: AccountId,
+        token_b: AccountId,
+        curve: Option<PoolCurve>,
+    ) -> Option<u64>;
+    fn set_fee(&mut self, pool_id: u64, fee: u32);
+    fn ramp_amplification(&mut self, pool_id: u64, target_amp: u64, end_timestamp: U64);
+    fn pause(&mut self);
+    fn resume(&mut self);
+    fn add_guardians(&mut self, guardians: Vec<AccountId>);
+    fn remove_guardians(&mut self, guardians: Vec<AccountId>);
+    fn propose_owner(&mut self, new_owner: AccountId);
+    fn delete_account(&mut self, beneficiary_id: AccountId);
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct OrderlyFactory {
+    owner: AccountId,
+    /// Deployed pool contracts by their sorted token pair.
+    pools: UnorderedMap<(AccountId, AccountId), AccountId>,
+}
+
+#[near_bindgen]
+impl OrderlyFactory {
+    #[init]
+    pub fn new(owner: AccountId) -> Self {
+        assert!(!env::state_exists(), "Already initialized");
+        log!("Factory initialized with {} as owner", owner);
+        Self {
+            owner,
+            pools: UnorderedMap::new(StorageKey::Pools.try_to_vec().unwrap()),
+        }
+    }
+
+    /// Creates the sub-account `<name>.<factory>`, deploys the pool contract to it
+    /// and adds a liquidity pool for `token_a` and `token_b`, e.g. `usdc-wnear.factory.near`.
+    /// The pool uses the constant product curve, unless another `curve` is given.
+    /// The factory is the owner of the deployed contract.
+    ///
+    /// The attached deposit is transferred to the new account and needs to cover its storage.
+    /// The part for the observations of the pool is attached to `add_pool` instead.
+    /// It gets refunded, if the account could not be created. The pool is only registered,
+    /// once it was added to the deployed contract.
+    #[payable]
+    pub fn create_pool(
+        &mut self,
+        name: String,
+        token_a: AccountId,
+        token_b: AccountId,
+        curve: Option<PoolCurve>,
+    ) -> Promise {
+        self.assert_owner();
+        assert!(
+            self.pools
+                .get(&pair_key(token_a.clone(), token_b.clone()))
+                .is_none(),
+            "Pool for tokens {} and {} already exists",
+            token_a,
+            token_b
+        );
+        let account_id: AccountId = format!("{}.{}", name, env::current_account_id())
+            .parse()
+            .unwrap_or_else(|_| panic!("Invalid pool account name {}", name));
+        let deposit = env::attached_deposit();
+        let required_deposit = required_deposit();
+        assert!(
+            deposit >= required_deposit,
+            "Attached deposit of {} is less than the required {}",
+            deposit,
+            required_deposit
+        );
+
+        log!(
+            "Creating pool {} for tokens {} and {}",
+            account_id,
+            token_a,
+            token_b
+        );
+        Promise::new(account_id.clone())
+            .create_account()
+            .transfer(deposit - observations_deposit())
+            .deploy_contract(ORDERLY_CONTRACT.to_vec())
+            .function_call(
+                "new".to_string(),
+                json!({ "owner": env::current_account_id() })
+                    .to_string()
+                    .into_bytes(),
+                0,
+                GAS_FOR_NEW,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_CREATE_POOL)
+                    .handle_create_pool(
+                        token_a,
+                        token_b,
+                        curve,
+                        account_id,
+                        env::predecessor_account_id(),
+                        deposit.into(),
+                    ),
+            )
+    }
+
+    /// Adds the token pair to the created pool contract.
+    /// If the account could not be created, the deposit is refunded to the creator.
+    #[private]
+    pub fn handle_create_pool(
+        &mut self,
+        token_a: AccountId,
+        token_b: AccountId,
+        curve: Option<PoolCurve>,
+        account_id: AccountId,
+        creator_id: AccountId,
+        deposit: U128,
+    ) -> PromiseOrValue<bool> {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expected one promise result"
+        );
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            log!("Deployed pool contract to {}", account_id);
+            ext_orderly_contract::ext(account_id.clone())
+                .with_attached_deposit(observations_deposit())
+                .with_static_gas(GAS_FOR_ADD_POOL)
+                .add_pool(token_a.clone(), token_b.clone(), curve)
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_RESOLVE_ADD_POOL)
+                        .handle_add_pool(token_a, token_b, account_id, creator_id),
+                )
+                .into()
+        } else {
+            log!(
+                "Creating pool {} failed. Refunding {} to {}",
+                account_id,
+                deposit.0,
+                creator_id
+            );
+            Promise::new(creator_id).transfer(deposit.0);
+            PromiseOrValue::Value(false)
+        }
+    }
+
+    /// Registers the pool contract for the token pair, once the pool was added to it as pool 0.
+    /// `add_pool` returns no pool id, if the metadata of a token could not be fetched.
+    /// Otherwise the account of the pool contract is deleted in favor of the creator, who
+    /// also gets back the deposit for the observations, that `add_pool` refunded to the factory.
+    /// The pair stays available, so that the pool can be created again.
+    #[private]
+    pub fn handle_add_pool(
+        &mut self,
+        token_a: AccountId,
+        token_b: AccountId,
+        account_id: AccountId,
+        creator_id: AccountId,
+    ) -> bool {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expected one promise result"
+        );
+        let pool_id = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                serde_json::from_slice::<Option<u64>>(&value).ok().flatten()
+            }
+            _ => None,
+        };
+        if pool_id != Some(0) {
+            log!(
+                "Adding the pool for tokens {} and {} to {} failed. Deleting it in favor of {}",
+                token_a,
+                token_b,
+                account_id,
+                creator_id
+            );
+            ext_orderly_contract::ext(account_id)
+                .with_static_gas(GAS_FOR_OWNER_CALL)
+                .delete_account(creator_id.clone());
+            Promise::new(creator_id).transfer(observations_deposit());
+            return false;
+        }
+        let key = pair_key(token_a.clone(), token_b.clone());
+        if let Some(existing) = self.pools.get(&key) {
+            log!(
+                "Pool {} for tokens {} and {} was created in the meantime",
+                existing,
+                token_a,
+                token_b
+            );
+            return false;
+        }
+        self.pools.insert(&key, &account_id);
+        log!(
+            "Created pool {} for tokens {} and {}",
+            account_id,
+            token_a,
+            token_b
+        );
+        true
+    }
+
+    /// Sets the swap fee in basis points of the pool for `token_a` and `token_b`.
+    pub fn set_fee(&mut self, token_a: AccountId, token_b: AccountId, fee: u32) -> Promise {
+        self.assert_owner();
+        ext_orderly_contract::ext(self.internal_get_pool(token_a, token_b))
+            .with_static_gas(GAS_FOR_OWNER_CALL)
+            .set_fee(0, fee)
+    }
+
+    /// Ramps the amplification of the StableSwap pool for `token_a` and `token_b`
+    /// to `target_amp` until the block timestamp `end_timestamp` in nanoseconds.
+    pub fn ramp_amplification(
+        &mut self,
+        token_a: AccountId,
+        token_b: AccountId,
+        target_amp: u64,
+        end_timestamp: U64,
+    ) -> Promise {
+        self.assert_owner();
+        ext_orderly_contract::ext(self.internal_get_pool(token_a, token_b))
+            .with_static_gas(GAS_FOR_OWNER_CALL)
+            .ramp_amplification(0, target_amp, end_timestamp)
+    }
+
+    /// Pauses the pool contract for `token_a` and `token_b` in an emergency.
+    pub fn pause(&mut self, token_a: AccountId, token_b: AccountId) -> Promise {
+        self.assert_owner();
+        ext_orderly_contract::ext(self.internal_get_pool(token_a, token_b))
+            .with_static_gas(GAS_FOR_OWNER_CALL)
+            .pause()
+    }
+
+    pub fn resume(&mut self, token_a: AccountId, token_b: AccountId) -> Promise {
+        self.assert_owner();
+        ext_orderly_contract::ext(self.internal_get_pool(token_a, token_b))
+            .with_static_gas(GAS_FOR_OWNER_CALL)
+            .resume()
+    }
+
+    /// Adds accounts, that can pause the pool contract for `token_a` and `token_b`
+    /// directly without going through the factory.
+    pub fn add_guardians(
+        &mut self,
+        token_a: AccountId,
+        token_b: AccountId,
+        guardians: Vec<AccountId>,
+    ) -> Promise {
+        self.assert_owner();
+        ext_orderly_contract::ext(self.internal_get_pool(token_a, token_b))
+            .with_static_gas(GAS_FOR_OWNER_CALL)
+            .add_guardians(guardians)
+    }
+
+    pub fn remove_guardians(
+        &mut self,
+        token_a: AccountId,
+        token_b: AccountId,
+        guardians: Vec<AccountId>,
+    ) -> Promise {
+        self.assert_owner();
+        ext_orderly_contract::ext(self.internal_get_pool(token_a, token_b))
+            .with_static_gas(GAS_FOR_OWNER_CALL)
+            .remove_guardians(guardians)
+    }
+
+    /// Proposes `new_owner` as owner of the pool contract for `token_a` and `token_b`,
+    /// who needs to accept the ownership on the pool contract. Afterwards the pool can
+    /// no longer be managed via the factory.
+    pub fn propose_owner(
+        &mut self,
+        token_a: AccountId,
+        token_b: AccountId,
+        new_owner: AccountId,
+    ) -> Promise {
+        self.assert_owner();
+        ext_orderly_contract::ext(self.internal_get_pool(token_a, token_b))
+            .with_static_gas(GAS_FOR_OWNER_CALL)
+            .propose_owner(new_owner)
+    }
+
+    /// Upgrades the pool contract for `token_a` and `token_b` to the pool contract,
+    /// that is embedded in the factory. The factory itself is upgraded beforehand
+    /// by deploying it again.
+    pub fn upgrade(&mut self, token_a: AccountId, token_b: AccountId) -> Promise {
+        self.assert_owner();
+        let account_id = self.internal_get_pool(token_a, token_b);
+        log!("Upgrading pool {}", account_id);
+        // the code is passed as raw input instead of JSON
+        Promise::new(account_id).function_call(
+            "upgrade".to_string(),
+            ORDERLY_CONTRACT.to_vec(),
+            0,
+            GAS_FOR_UPGRADE,
+        )
+    }
+
+    /// Returns the account of the pool for `token_a` and `token_b` in any order.
+    pub fn get_pool(&self, token_a: AccountId, token_b: AccountId) -> Option<AccountId> {
+        self.pools.get(&pair_key(token_a, token_b))
+    }
+
+    /// Returns up to `limit` pools starting at `from_index`.
+    pub fn get_pools(&self, from_index: u64, limit: u64) -> Vec<PoolEntry> {
+        self.pools
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|((token_a, token_b), account_id)| PoolEntry {
+                account_id,
+                token_a,
+                token_b,
+            })
+            .collect()
+    }
+
+    pub fn get_number_of_pools(&self) -> u64 {
+        self.pools.len()
+    }
+
+    /// Returns the minimum deposit for `create_pool`.
+    pub fn get_required_deposit(&self) -> U128 {
+        required_deposit().into()
+    }
+}
+
+impl OrderlyFactory {
+    fn internal_get_pool(&self, token_a: AccountId, token_b: AccountId) -> AccountId {
+        self.get_pool(token_a.clone(), token_b.clone())
+            .unwrap_or_else(|| panic!("Pool for tokens {} and {} does not exist", token_a, token_b))
+    }
+
+    fn assert_owner(&self) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner,
+            "Only the owner can call this method"
+        );
+    }
+}
+
+/// Sorts the token pair, so that each pair has a unique key.
+fn pair_key(token_a: AccountId, token_b: AccountId) -> (AccountId, AccountId) {
+    assert_ne!(token_a, token_b, "Tokens of a pool must be different");
+    if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    }
+}
+
+fn required_deposit() -> Balance {
+    (ORDERLY_CONTRACT.len() as u128 + POOL_STATE_STORAGE) * env::storage_byte_cost()
+}
+
+fn observations_deposit() -> Balance {
+    OBSERVATIONS_STORAGE * env::storage_byte_cost()
+}
+
+/// Curve of a pool, that is passed to `add_pool` of the pool contract.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum PoolCurve {
+    ConstantProduct,
+    StableSwap {
+        amp: u64,
+    },
+    /// Weights of the tokens in the order they were passed to `create_pool`.
+    Weighted {
+        weight_a: u32,
+        weight_b: u32,
+    },
+}
+
+#[derive(Deserialize, Serialize, Eq, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolEntry {
+    pub account_id: AccountId,
+    pub token_a: AccountId,
+    pub token_b: AccountId,
+}
+
+#[derive(BorshSerialize)]
+enum StorageKey {
+    Pools,
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    use near_sdk::{
+        mock::VmAction,
+        test_utils::{accounts, get_created_receipts, VMContextBuilder},
+        testing_env, RuntimeFeesConfig, VMConfig,
+    };
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(accounts(0))
+            .signer_account_id(predecessor_account_id.clone())
+            .predecessor_account_id(predecessor_account_id);
+        builder
+    }
+
+    fn set_promise_result(result: PromiseResult) {
+        testing_env!(
+            get_context(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result],
+        );
+    }
+
+    fn handle_create_pool(
+        contract: &mut OrderlyFactory,
+        result: PromiseResult,
+    ) -> PromiseOrValue<bool> {
+        set_promise_result(result);
+        contract.handle_create_pool(
+            accounts(2),
+            accounts(3),
+            None,
+            "charlie-danny.alice".parse().unwrap(),
+            accounts(1),
+            required_deposit().into(),
+        )
+    }
+
+    fn handle_add_pool(contract: &mut OrderlyFactory, result: PromiseResult) -> bool {
+        set_promise_result(result);
+        contract.handle_add_pool(
+            accounts(2),
+            accounts(3),
+            "charlie-danny.alice".parse().unwrap(),
+            accounts(1),
+        )
+    }
+
+    #[test]
+    fn test_create_pool() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(required_deposit())
+            .build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        contract.create_pool("charlie-danny".to_string(), accounts(3), accounts(2), None);
+
+        let receipts = get_created_receipts();
+        let receipt = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id.as_str() == "charlie-danny.alice")
+            .unwrap();
+        assert!(matches!(receipt.actions[0], VmAction::CreateAccount));
+        // the deposit for the observations is attached to add_pool
+        assert!(matches!(
+            receipt.actions[1],
+            VmAction::Transfer { deposit } if deposit == required_deposit() - observations_deposit()
+        ));
+        assert!(matches!(
+            receipt.actions[2],
+            VmAction::DeployContract { .. }
+        ));
+    }
+
+    #[test]
+    fn test_observations_storage() {
+        assert_eq!(
+            OBSERVATIONS_STORAGE,
+            orderly_contract::OBSERVATIONS_STORAGE as u128
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_create_pool_not_owner() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        testing_env!(get_context(accounts(4))
+            .attached_deposit(required_deposit())
+            .build());
+        contract.create_pool("charlie-danny".to_string(), accounts(2), accounts(3), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit of 0 is less than the required")]
+    fn test_create_pool_not_enough_deposit() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        contract.create_pool("charlie-danny".to_string(), accounts(2), accounts(3), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tokens of a pool must be different")]
+    fn test_create_pool_same_tokens() {
+        testing_env!(get_context(accounts(1))
+            .attached_deposit(required_deposit())
+            .build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        contract.create_pool(
+            "charlie-charlie".to_string(),
+            accounts(2),
+            accounts(2),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_handle_create_pool() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        assert!(matches!(
+            handle_create_pool(&mut contract, PromiseResult::Successful(vec![])),
+            PromiseOrValue::Promise(_)
+        ));
+        // the pool is only registered, once it was added to the contract
+        assert_eq!(contract.get_pool(accounts(2), accounts(3)), None);
+
+        let receipts = get_created_receipts();
+        let add_pool = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id.as_str() == "charlie-danny.alice")
+            .unwrap();
+        assert!(matches!(
+            &add_pool.actions[0],
+            VmAction::FunctionCall { function_name, deposit, .. }
+                if function_name == "add_pool" && *deposit == observations_deposit()
+        ));
+    }
+
+    #[test]
+    fn test_handle_add_pool() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        assert!(handle_add_pool(
+            &mut contract,
+            PromiseResult::Successful(b"0".to_vec())
+        ));
+
+        let account_id: AccountId = "charlie-danny.alice".parse().unwrap();
+        assert_eq!(
+            contract.get_pool(accounts(2), accounts(3)),
+            Some(account_id.clone())
+        );
+        assert_eq!(
+            contract.get_pool(accounts(3), accounts(2)),
+            Some(account_id.clone())
+        );
+        assert_eq!(contract.get_number_of_pools(), 1);
+        assert_eq!(
+            contract.get_pools(0, 10),
+            vec![PoolEntry {
+                account_id,
+                token_a: accounts(2),
+                token_b: accounts(3),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_handle_create_pool_failed_should_refund() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        let res = handle_create_pool(&mut contract, PromiseResult::Failed);
+        assert!(matches!(res, PromiseOrValue::Value(false)));
+        assert_eq!(contract.get_pool(accounts(2), accounts(3)), None);
+
+        let receipts = get_created_receipts();
+        let refund = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id == accounts(1))
+            .unwrap();
+        assert!(matches!(
+            refund.actions[0],
+            VmAction::Transfer { deposit } if deposit == required_deposit()
+        ));
+    }
+
+    #[test]
+    fn test_handle_add_pool_without_pool_id() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        assert!(!handle_add_pool(
+            &mut contract,
+            PromiseResult::Successful(b"null".to_vec())
+        ));
+        assert_eq!(contract.get_pool(accounts(2), accounts(3)), None);
+    }
+
+    #[test]
+    fn test_handle_add_pool_failed() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        assert!(!handle_add_pool(&mut contract, PromiseResult::Failed));
+        assert_eq!(contract.get_pool(accounts(2), accounts(3)), None);
+        assert_eq!(contract.get_number_of_pools(), 0);
+
+        let receipts = get_created_receipts();
+        let delete = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id.as_str() == "charlie-danny.alice")
+            .unwrap();
+        match &delete.actions[0] {
+            VmAction::FunctionCall {
+                function_name,
+                args,
+                ..
+            } => {
+                assert_eq!(function_name, "delete_account");
+                let args = serde_json::from_slice::<serde_json::Value>(args).unwrap();
+                assert_eq!(args["beneficiary_id"], accounts(1).as_str());
+            }
+            _ => panic!("Expected a function call"),
+        }
+        let refund = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id == accounts(1))
+            .unwrap();
+        assert!(matches!(
+            refund.actions[0],
+            VmAction::Transfer { deposit } if deposit == observations_deposit()
+        ));
+    }
+
+    #[test]
+    fn test_handle_add_pool_created_twice() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        assert!(handle_add_pool(
+            &mut contract,
+            PromiseResult::Successful(b"0".to_vec())
+        ));
+        set_promise_result(PromiseResult::Successful(b"0".to_vec()));
+        assert!(!contract.handle_add_pool(
+            accounts(2),
+            accounts(3),
+            "danny-charlie.alice".parse().unwrap(),
+            accounts(1)
+        ));
+        assert_eq!(
+            contract.get_pool(accounts(2), accounts(3)),
+            Some("charlie-danny.alice".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_handle_create_pool_with_curve() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        set_promise_result(PromiseResult::Successful(vec![]));
+        let _ = contract.handle_create_pool(
+            accounts(3),
+            accounts(2),
+            Some(PoolCurve::Weighted {
+                weight_a: 8_000,
+                weight_b: 2_000,
+            }),
+            "danny-charlie.alice".parse().unwrap(),
+            accounts(1),
+            required_deposit().into(),
+        );
+
+        let receipts = get_created_receipts();
+        let add_pool = receipts
+            .iter()
+            .find(|receipt| receipt.receiver_id.as_str() == "danny-charlie.alice")
+            .unwrap();
+        let args = match &add_pool.actions[0] {
+            VmAction::FunctionCall { args, .. } => {
+                serde_json::from_slice::<serde_json::Value>(args).unwrap()
+            }
+            _ => panic!("Expected a function call"),
+        };
+        // the weights refer to the tokens in the given order
+        assert_eq!(args["token_a"], accounts(3).as_str());
+        assert_eq!(args["curve"]["weighted"]["weight_a"], 8_000);
+    }
+
+    #[test]
+    fn test_pause() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        handle_add_pool(&mut contract, PromiseResult::Successful(b"0".to_vec()));
+        testing_env!(get_context(accounts(1)).build());
+        let _ = contract.pause(accounts(3), accounts(2));
+
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id.as_str(), "charlie-danny.alice");
+        assert!(matches!(
+            &receipts[0].actions[0],
+            VmAction::FunctionCall { function_name, .. } if function_name == "pause"
+        ));
+    }
+
+    #[test]
+    fn test_upgrade() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        handle_add_pool(&mut contract, PromiseResult::Successful(b"0".to_vec()));
+        testing_env!(get_context(accounts(1)).build());
+        let _ = contract.upgrade(accounts(2), accounts(3));
+
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id.as_str(), "charlie-danny.alice");
+        assert!(matches!(
+            &receipts[0].actions[0],
+            VmAction::FunctionCall { function_name, args, .. }
+                if function_name == "upgrade" && args == ORDERLY_CONTRACT
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_add_guardians_not_owner() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        handle_add_pool(&mut contract, PromiseResult::Successful(b"0".to_vec()));
+        testing_env!(get_context(accounts(4)).build());
+        let _ = contract.add_guardians(accounts(2), accounts(3), vec![accounts(4)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Pool for tokens charlie and danny does not exist")]
+    fn test_propose_owner_unknown_pool() {
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = OrderlyFactory::new(accounts(1));
+        let _ = contract.propose_owner(accounts(2), accounts(3), accounts(4));
+    }
+}
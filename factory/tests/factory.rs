@@ -0,0 +1,179 @@
+use near_sdk::json_types::U128;
+use orderly_contract::PoolInfo;
+use orderly_factory::{PoolCurve, PoolEntry};
+use tokio::fs;
+use workspaces::{network::Sandbox, prelude::*, Account, AccountId, Contract, Worker};
+
+#[tokio::test]
+async fn test_create_pool() -> anyhow::Result<()> {
+    let (worker, owner, factory, token_a, token_b) = initialize_contracts().await?;
+
+    create_pool(&worker, &owner, &factory, token_a.id(), token_b.id()).await?;
+
+    let res = factory
+        .call(&worker, "get_pool")
+        .args_json((token_b.id(), token_a.id()))?
+        .view()
+        .await?;
+    let pool_id = res.json::<Option<AccountId>>()?.unwrap();
+    assert_eq!(pool_id.to_string(), format!("tkna-tknb.{}", factory.id()));
+
+    let res = factory
+        .call(&worker, "get_pools")
+        .args_json((0, 10))?
+        .view()
+        .await?;
+    let pools = res.json::<Vec<PoolEntry>>()?;
+    assert_eq!(pools.len(), 1);
+    assert_eq!(pools[0].account_id.as_str(), pool_id.as_str());
+
+    let res = worker
+        .view(&pool_id, "get_pool", br#"{"pool_id":0}"#.to_vec())
+        .await?;
+    let info = res.json::<PoolInfo>()?;
+    assert_eq!(info.token_a_supply, U128::from(0));
+    assert_eq!(info.token_b_supply, U128::from(0));
+    let mut tokens = [info.token_a_id.as_str(), info.token_b_id.as_str()];
+    tokens.sort_unstable();
+    let mut expected = [token_a.id().as_str(), token_b.id().as_str()];
+    expected.sort_unstable();
+    assert_eq!(tokens, expected);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_add_guardians_via_factory() -> anyhow::Result<()> {
+    let (worker, owner, factory, token_a, token_b) = initialize_contracts().await?;
+    create_pool(&worker, &owner, &factory, token_a.id(), token_b.id()).await?;
+    let guardian = worker.dev_create_account().await?;
+
+    let res = owner
+        .call(&worker, factory.id(), "add_guardians")
+        .args_json((token_a.id(), token_b.id(), [guardian.id()]))?
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+
+    let pool_id: AccountId = format!("tkna-tknb.{}", factory.id()).parse()?;
+    let res = worker
+        .view(&pool_id, "get_guardians", b"{}".to_vec())
+        .await?;
+    assert_eq!(res.json::<Vec<AccountId>>()?, vec![guardian.id().clone()]);
+
+    // the guardian pauses the pool directly
+    let res = guardian.call(&worker, &pool_id, "pause").transact().await?;
+    assert!(res.is_success());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_pool_not_a_token() -> anyhow::Result<()> {
+    let (worker, owner, factory, token_a, _) = initialize_contracts().await?;
+    let user = worker.dev_create_account().await?;
+
+    // the pool contract cannot fetch the metadata of an account without a contract
+    let res = owner
+        .call(&worker, factory.id(), "create_pool")
+        .args_json(("tkna-user", token_a.id(), user.id(), None::<PoolCurve>))?
+        .deposit(required_deposit(&worker, &factory).await?)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    assert!(!res.json::<bool>()?);
+
+    let res = factory
+        .call(&worker, "get_pool")
+        .args_json((token_a.id(), user.id()))?
+        .view()
+        .await?;
+    assert_eq!(res.json::<Option<AccountId>>()?, None);
+    let res = factory.call(&worker, "get_number_of_pools").view().await?;
+    assert_eq!(res.json::<u64>()?, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_pool_twice() -> anyhow::Result<()> {
+    let (worker, owner, factory, token_a, token_b) = initialize_contracts().await?;
+
+    create_pool(&worker, &owner, &factory, token_a.id(), token_b.id()).await?;
+    let res = owner
+        .call(&worker, factory.id(), "create_pool")
+        .args_json(("tknb-tkna", token_b.id(), token_a.id(), None::<PoolCurve>))?
+        .deposit(required_deposit(&worker, &factory).await?)
+        .max_gas()
+        .transact()
+        .await;
+    assert!(res.is_err());
+
+    let res = factory.call(&worker, "get_number_of_pools").view().await?;
+    assert_eq!(res.json::<u64>()?, 1);
+
+    Ok(())
+}
+
+async fn initialize_contracts(
+) -> anyhow::Result<(Worker<Sandbox>, Account, Contract, Contract, Contract)> {
+    let worker = workspaces::sandbox().await?;
+
+    let owner = worker.dev_create_account().await?;
+
+    let factory = worker
+        .dev_deploy(&workspaces::compile_project("./").await?)
+        .await?;
+    factory
+        .call(&worker, "new")
+        .args_json((owner.id(),))?
+        .max_gas()
+        .transact()
+        .await?;
+
+    let token_a_contract = worker
+        .dev_deploy(&fs::read("../res/test_token.wasm").await?)
+        .await?;
+    token_a_contract
+        .call(&worker, "new")
+        .args_json(("TokenA", "TKNA"))?
+        .transact()
+        .await?;
+
+    let token_b_contract = worker
+        .dev_deploy(&fs::read("../res/test_token.wasm").await?)
+        .await?;
+    token_b_contract
+        .call(&worker, "new")
+        .args_json(("TokenB", "TKNB"))?
+        .transact()
+        .await?;
+
+    Ok((worker, owner, factory, token_a_contract, token_b_contract))
+}
+
+async fn create_pool(
+    worker: &Worker<Sandbox>,
+    owner: &Account,
+    factory: &Contract,
+    token_a: &AccountId,
+    token_b: &AccountId,
+) -> anyhow::Result<()> {
+    let res = owner
+        .call(worker, factory.id(), "create_pool")
+        .args_json(("tkna-tknb", token_a, token_b, None::<PoolCurve>))?
+        .deposit(required_deposit(worker, factory).await?)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(res.is_success());
+    assert!(res.json::<bool>()?);
+    Ok(())
+}
+
+async fn required_deposit(worker: &Worker<Sandbox>, factory: &Contract) -> anyhow::Result<u128> {
+    let res = factory.call(worker, "get_required_deposit").view().await?;
+    Ok(res.json::<U128>()?.0)
+}